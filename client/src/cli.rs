@@ -0,0 +1,272 @@
+//! Command line of the client binary.
+
+use serde::de::{value, Deserialize, IntoDeserializer};
+use server::dto::{AddItemRequest, ItemSort, PageQuery, SortOrder};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+usage: restaurant-cli [--config FILE] [--output table|json] COMMAND
+
+commands:
+  item add TABLE NAME [--quantity N]    order N of a dish for a table, 1 by default
+  item get ITEM                         show an item
+  item remove ITEM [--quantity N]       remove N of an item, or all of it
+  table show TABLE                      list the items of a table
+  items list [--limit N] [--cursor CURSOR] [--name TEXT] [--table-from TABLE]
+             [--table-to TABLE] [--created-after YYYY-MM-DDTHH:MM:SS]
+             [--sort table_id|created_at|name|time_to_prepare]
+             [--order asc|desc]         list a page of the items of all tables
+
+  --config FILE    configuration with the base URL and credentials, by default
+                   ~/.config/restaurant-cli/config.json
+  --output FORMAT  print a table, the default, or the JSON of the responses
+
+The settings of the configuration are overridden by the environment variables
+RESTAURANT_URL, RESTAURANT_API_KEY and RESTAURANT_TOKEN.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Output {
+    #[default]
+    Table,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    AddItem(AddItemRequest),
+    GetItem(i64),
+    /// Removes the quantity of the item, or all of it.
+    RemoveItem {
+        item_id: i64,
+        quantity: Option<i32>,
+    },
+    ShowTable(i32),
+    ListItems(PageQuery),
+}
+
+/// Parsed command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    pub config: Option<PathBuf>,
+    pub output: Output,
+    pub command: Command,
+}
+
+fn parse_value<T: FromStr>(kind: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid {} {}", kind, value))
+}
+
+/// Parses a value of a DTO enum by its name in the API.
+fn parse_enum<'de, T: Deserialize<'de>>(kind: &str, value: &'de str) -> Result<T, String> {
+    let deserializer: value::StrDeserializer<value::Error> = value.into_deserializer();
+    T::deserialize(deserializer).map_err(|_| format!("invalid {} {}", kind, value))
+}
+
+/// Parses `--name value` pairs, allowing only the given names.
+fn parse_options<'a>(
+    args: &[&'a str],
+    allowed: &[&str],
+) -> Result<HashMap<&'a str, &'a str>, String> {
+    let mut options = HashMap::new();
+    for pair in args.chunks(2) {
+        match pair {
+            [name, value] if allowed.contains(name) => {
+                if options.insert(*name, *value).is_some() {
+                    return Err(format!("{} is given twice", name));
+                }
+            }
+            [name] if allowed.contains(name) => return Err(format!("{} needs a value", name)),
+            _ => return Err(format!("invalid option {}", pair[0])),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_quantity(options: &HashMap<&str, &str>) -> Result<Option<i32>, String> {
+    options
+        .get("--quantity")
+        .map(|quantity| {
+            parse_value::<i32>("quantity", quantity)
+                .ok()
+                .filter(|quantity| *quantity > 0)
+                .ok_or_else(|| format!("invalid quantity {}", quantity))
+        })
+        .transpose()
+}
+
+fn parse_page(options: &HashMap<&str, &str>) -> Result<PageQuery, String> {
+    let value = |name: &str| options.get(name).copied();
+    Ok(PageQuery {
+        limit: value("--limit")
+            .map(|limit| parse_value("limit", limit))
+            .transpose()?,
+        cursor: value("--cursor").map(str::to_string),
+        sort: value("--sort")
+            .map(|sort| parse_enum::<ItemSort>("sort", sort))
+            .transpose()?,
+        order: value("--order")
+            .map(|order| parse_enum::<SortOrder>("order", order))
+            .transpose()?,
+        name: value("--name").map(str::to_string),
+        table_from: value("--table-from")
+            .map(|table| parse_value("table", table))
+            .transpose()?,
+        table_to: value("--table-to")
+            .map(|table| parse_value("table", table))
+            .transpose()?,
+        created_after: value("--created-after")
+            .map(|created_after| parse_value("time", created_after))
+            .transpose()?,
+    })
+}
+
+impl Command {
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        match args {
+            ["item", "add", table_id, name, options @ ..] => {
+                let options = parse_options(options, &["--quantity"])?;
+                Ok(Command::AddItem(AddItemRequest {
+                    name: name.to_string(),
+                    table_id: parse_value("table", table_id)?,
+                    quantity: parse_quantity(&options)?.unwrap_or(1),
+                }))
+            }
+            ["item", "get", item_id] => Ok(Command::GetItem(parse_value("item", item_id)?)),
+            ["item", "remove", item_id, options @ ..] => {
+                let options = parse_options(options, &["--quantity"])?;
+                Ok(Command::RemoveItem {
+                    item_id: parse_value("item", item_id)?,
+                    quantity: parse_quantity(&options)?,
+                })
+            }
+            ["table", "show", table_id] => Ok(Command::ShowTable(parse_value("table", table_id)?)),
+            ["items", "list", options @ ..] => {
+                let options = parse_options(
+                    options,
+                    &[
+                        "--limit",
+                        "--cursor",
+                        "--name",
+                        "--table-from",
+                        "--table-to",
+                        "--created-after",
+                        "--sort",
+                        "--order",
+                    ],
+                )?;
+                Ok(Command::ListItems(parse_page(&options)?))
+            }
+            _ => Err(format!("invalid arguments: {}", args.join(" "))),
+        }
+    }
+}
+
+impl Invocation {
+    /// Parses the arguments without the name of the binary.
+    pub fn parse<I, S>(args: I) -> Result<Invocation, String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let args: Vec<S> = args.into_iter().collect();
+        let mut args: &[S] = &args;
+        let mut config = None;
+        let mut output = Output::default();
+        loop {
+            match args {
+                [name, value, rest @ ..] if name.as_ref() == "--config" => {
+                    config = Some(PathBuf::from(value.as_ref()));
+                    args = rest;
+                }
+                [name, value, rest @ ..] if name.as_ref() == "--output" => {
+                    output = match value.as_ref() {
+                        "table" => Output::Table,
+                        "json" => Output::Json,
+                        value => return Err(format!("invalid output {}", value)),
+                    };
+                    args = rest;
+                }
+                _ => break,
+            }
+        }
+        let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+        Ok(Invocation {
+            config,
+            output,
+            command: Command::parse(&args)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Invocation::parse(["item", "add", "4", "sushi", "--quantity", "2"]),
+            Ok(Invocation {
+                config: None,
+                output: Output::Table,
+                command: Command::AddItem(AddItemRequest {
+                    name: "sushi".to_string(),
+                    table_id: 4,
+                    quantity: 2,
+                }),
+            })
+        );
+        assert_eq!(
+            Invocation::parse(["--output", "json", "--config", "pos.json", "item", "get", "7"]),
+            Ok(Invocation {
+                config: Some(PathBuf::from("pos.json")),
+                output: Output::Json,
+                command: Command::GetItem(7),
+            })
+        );
+        assert_eq!(
+            Invocation::parse(["item", "remove", "7"]).map(|invocation| invocation.command),
+            Ok(Command::RemoveItem {
+                item_id: 7,
+                quantity: None
+            })
+        );
+        assert_eq!(
+            Invocation::parse([
+                "items", "list", "--sort", "name", "--order", "desc", "--limit", "5"
+            ])
+            .map(|invocation| invocation.command),
+            Ok(Command::ListItems(PageQuery {
+                limit: Some(5),
+                sort: Some(ItemSort::Name),
+                order: Some(SortOrder::Desc),
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            Invocation::parse(["items", "list", "--created-after", "2026-10-19T12:00:00"])
+                .map(|invocation| invocation.command),
+            Ok(Command::ListItems(PageQuery {
+                created_after: Some("2026-10-19T12:00:00".parse().unwrap()),
+                ..Default::default()
+            }))
+        );
+        for invalid in [
+            &["item"][..],
+            &["item", "add", "four", "sushi"],
+            &["item", "add", "4", "sushi", "--quantity", "0"],
+            &["item", "remove", "7", "--quantity"],
+            &["items", "list", "--sort", "price"],
+            &["items", "list", "--limit", "5", "--limit", "6"],
+            &["items", "list", "--table", "4"],
+            &["items", "list", "--created-after", "yesterday"],
+            &["--output", "xml", "table", "show", "4"],
+        ] {
+            assert!(Invocation::parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Blocking client of the REST API, speaking the DTOs of the server.
+
+use derive_more::Display;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::{AUTHORIZATION, ETAG, IF_MATCH};
+use serde::de::DeserializeOwned;
+use server::auth::API_KEY_HEADER;
+use server::dto::{
+    AddItemRequest, AddItemResponse, GetAllItemsResponse, GetItemForTableResponse, GetItemResponse,
+    PageQuery,
+};
+
+use crate::config::ClientConfig;
+
+#[derive(Debug, Display)]
+pub enum ClientError {
+    #[display(fmt = "request failed: {}", _0)]
+    Request(String),
+    /// The server answered with an error status, given with the body of the response.
+    #[display(fmt = "the server answered {}: {}", _0, _1)]
+    Status(u16, String),
+    #[display(fmt = "invalid response: {}", _0)]
+    InvalidResponse(String),
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Request(e.to_string())
+    }
+}
+
+/// What is left of an item after some of it was removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemovedItem {
+    pub item_id: i64,
+    pub removed: i32,
+    /// New version of the item, `None` when it was deleted.
+    pub version: Option<i32>,
+}
+
+pub struct RestaurantClient {
+    client: Client,
+    config: ClientConfig,
+}
+
+impl RestaurantClient {
+    pub fn new(config: ClientConfig) -> RestaurantClient {
+        RestaurantClient {
+            client: Client::new(),
+            config,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.config.base_url.trim_end_matches('/'), path)
+    }
+
+    fn authenticated(&self, request: RequestBuilder) -> RequestBuilder {
+        let request = match &self.config.api_key {
+            Some(api_key) => request.header(API_KEY_HEADER, api_key),
+            None => request,
+        };
+        match &self.config.token {
+            Some(token) => request.header(AUTHORIZATION, format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+
+    fn send(&self, request: RequestBuilder) -> Result<Response, ClientError> {
+        let response = self.authenticated(request).send()?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(response)
+        } else {
+            let body = response.text().unwrap_or_default();
+            Err(ClientError::Status(status.as_u16(), body))
+        }
+    }
+
+    fn read_json<T: DeserializeOwned>(response: Response) -> Result<T, ClientError> {
+        let body = response.bytes()?;
+        serde_json::from_slice(&body).map_err(|e| ClientError::InvalidResponse(e.to_string()))
+    }
+
+    fn page_url(&self, path: &str, page: &PageQuery) -> Result<String, ClientError> {
+        let query = serde_urlencoded::to_string(page)
+            .map_err(|e| ClientError::Request(format!("invalid page query: {}", e)))?;
+        if query.is_empty() {
+            Ok(self.url(path))
+        } else {
+            Ok(format!("{}?{}", self.url(path), query))
+        }
+    }
+
+    pub fn add_item(&self, item: &AddItemRequest) -> Result<AddItemResponse, ClientError> {
+        let body = serde_json::to_vec(item).map_err(|e| ClientError::Request(e.to_string()))?;
+        let request = self
+            .client
+            .post(self.url("/item"))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body);
+        RestaurantClient::read_json(self.send(request)?)
+    }
+
+    pub fn get_item(&self, item_id: i64) -> Result<GetItemResponse, ClientError> {
+        let request = self.client.get(self.url(&format!("/item/{}", item_id)));
+        RestaurantClient::read_json(self.send(request)?)
+    }
+
+    pub fn get_items_for_table(
+        &self,
+        table_id: i32,
+        page: &PageQuery,
+    ) -> Result<GetItemForTableResponse, ClientError> {
+        let url = self.page_url(&format!("/table/{}", table_id), page)?;
+        RestaurantClient::read_json(self.send(self.client.get(url))?)
+    }
+
+    /// Returns every item of the table, following the pages.
+    pub fn get_all_items_for_table(
+        &self,
+        table_id: i32,
+    ) -> Result<Vec<GetItemResponse>, ClientError> {
+        let mut page = PageQuery::default();
+        let mut items = vec![];
+        loop {
+            let response = self.get_items_for_table(table_id, &page)?;
+            items.extend(response.items);
+            match response.next_cursor {
+                Some(cursor) => page.cursor = Some(cursor),
+                None => return Ok(items),
+            }
+        }
+    }
+
+    pub fn get_all_items(&self, page: &PageQuery) -> Result<GetAllItemsResponse, ClientError> {
+        let url = self.page_url("/items", page)?;
+        RestaurantClient::read_json(self.send(self.client.get(url))?)
+    }
+
+    /// Removes `quantity` of the item, or all of it when no quantity is given. The whole
+    /// item is only removed if it was not changed since it was read.
+    pub fn remove_item(
+        &self,
+        item_id: i64,
+        quantity: Option<i32>,
+    ) -> Result<RemovedItem, ClientError> {
+        let url = self.url(&format!("/item/{}", item_id));
+        let (request, removed) = match quantity {
+            Some(quantity) => (
+                self.client.delete(format!("{}/{}", url, quantity)),
+                quantity,
+            ),
+            None => {
+                let item = self.get_item(item_id)?;
+                let request = self
+                    .client
+                    .delete(format!("{}/{}", url, item.quantity))
+                    .header(IF_MATCH, format!("\"{}\"", item.version));
+                (request, item.quantity)
+            }
+        };
+        let response = self.send(request)?;
+        let version = response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .and_then(|etag| etag.trim_matches('"').parse().ok());
+        Ok(RemovedItem {
+            item_id,
+            removed,
+            version,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::middleware::from_fn;
+    use actix_web::{rt, web, App, HttpServer};
+    use persistence::postgres_repositories::PgRepositories;
+    use persistence::{init_test_db, reset_test_db};
+    use server::api;
+    use server::auth::{authenticate, Authenticator};
+    use server::config::{AuthConfig, DeviceKey};
+    use server::policy::Role;
+    use std::collections::HashMap;
+    use std::sync::mpsc;
+    use std::thread;
+
+    const API_KEY: &str = "manager-key";
+
+    #[test]
+    #[serial_test::serial]
+    fn test_client() {
+        let (sender, receiver) = mpsc::channel();
+        let server_thread = thread::spawn(move || {
+            rt::System::new().block_on(async move {
+                let item_repository = init_test_db().await;
+                let repositories = PgRepositories::new(item_repository.clone());
+                let auth = AuthConfig {
+                    api_keys: HashMap::from([(
+                        "pos".to_string(),
+                        DeviceKey {
+                            key: API_KEY.to_string(),
+                            role: Role::Manager,
+                        },
+                    )]),
+                    ..Default::default()
+                };
+                let authenticator = web::Data::new(Authenticator::from_config(&auth).unwrap());
+                let server = HttpServer::new(move || {
+                    App::new()
+                        .app_data(web::Data::new(repositories.clone()))
+                        .app_data(authenticator.clone())
+                        .wrap(from_fn(authenticate))
+                        .configure(api::configure)
+                })
+                .workers(1)
+                .bind(("127.0.0.1", 0))
+                .unwrap();
+                let address = server.addrs()[0];
+                let server = server.run();
+                sender.send((address, server.handle())).unwrap();
+                server.await.unwrap();
+                reset_test_db(item_repository.connection_pool).await;
+            })
+        });
+        let (address, handle) = receiver.recv().unwrap();
+        let base_url = format!("http://{}/api/v1", address);
+
+        let anonymous = RestaurantClient::new(ClientConfig {
+            base_url: base_url.clone(),
+            ..Default::default()
+        });
+        match anonymous.get_all_items(&PageQuery::default()) {
+            Err(ClientError::Status(401, _)) => {}
+            result => panic!("{:?}", result),
+        }
+
+        let client = RestaurantClient::new(ClientConfig {
+            base_url,
+            api_key: Some(API_KEY.to_string()),
+            token: None,
+        });
+        let sushi = AddItemRequest {
+            name: "sushi".to_string(),
+            table_id: 4,
+            quantity: 3,
+        };
+        let sushi_id = client.add_item(&sushi).unwrap().added_item_id;
+        let ramen = AddItemRequest {
+            name: "ramen".to_string(),
+            table_id: 5,
+            quantity: 1,
+        };
+        let ramen_id = client.add_item(&ramen).unwrap().added_item_id;
+
+        let item = client.get_item(sushi_id).unwrap();
+        assert_eq!(
+            (item.name.as_str(), item.table_id, item.quantity),
+            ("sushi", 4, 3)
+        );
+        let items = client.get_all_items_for_table(4).unwrap();
+        assert_eq!(items.len(), 1);
+        let page = PageQuery {
+            limit: Some(1),
+            ..Default::default()
+        };
+        let response = client.get_all_items(&page).unwrap();
+        assert_eq!(response.items.len(), 1);
+        assert!(response.next_cursor.is_some());
+
+        let removed = client.remove_item(sushi_id, Some(1)).unwrap();
+        assert_eq!((removed.removed, removed.version), (1, Some(2)));
+        let removed = client.remove_item(sushi_id, None).unwrap();
+        assert_eq!((removed.removed, removed.version), (2, None));
+        match client.get_item(sushi_id) {
+            Err(ClientError::Status(404, _)) => {}
+            result => panic!("{:?}", result),
+        }
+        assert_eq!(client.get_item(ramen_id).unwrap().name, "ramen");
+
+        rt::System::new().block_on(handle.stop(false));
+        server_thread.join().unwrap();
+    }
+}
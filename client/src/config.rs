@@ -0,0 +1,109 @@
+//! Where the API is and how to authenticate to it.
+//!
+//! The settings are read from a JSON file, `~/.config/restaurant-cli/config.json` unless
+//! another one is given, and can be overridden with environment variables:
+//! - `RESTAURANT_URL` - base URL of the API, `http://localhost:8080/api/v1` by default
+//! - `RESTAURANT_API_KEY` - API key of a device, sent in `X-Api-Key`
+//! - `RESTAURANT_TOKEN` - session token or JWT of a staff member, sent as bearer token
+
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+pub const DEFAULT_BASE_URL: &str = "http://localhost:8080/api/v1";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub token: Option<String>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            api_key: None,
+            token: None,
+        }
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| {
+        PathBuf::from(home)
+            .join(".config")
+            .join("restaurant-cli")
+            .join("config.json")
+    })
+}
+
+impl ClientConfig {
+    /// Reads the given file, which must exist, or the default file if there is one, then
+    /// applies the environment variables.
+    pub fn load(path: Option<&Path>) -> io::Result<ClientConfig> {
+        let config = match path {
+            Some(path) => ClientConfig::read(path)?,
+            None => match default_path().filter(|path| path.exists()) {
+                Some(path) => ClientConfig::read(&path)?,
+                None => ClientConfig::default(),
+            },
+        };
+        Ok(config.with_overrides(|name| env::var(name).ok()))
+    }
+
+    fn read(path: &Path) -> io::Result<ClientConfig> {
+        let content = fs::read(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("failed to read {}: {}", path.display(), e),
+            )
+        })?;
+        serde_json::from_slice(&content).map_err(|e| {
+            io::Error::other(format!("invalid configuration {}: {}", path.display(), e))
+        })
+    }
+
+    /// Replaces the settings which have a variable, looked up with `var`.
+    fn with_overrides(self, var: impl Fn(&str) -> Option<String>) -> ClientConfig {
+        ClientConfig {
+            base_url: var("RESTAURANT_URL").unwrap_or(self.base_url),
+            api_key: var("RESTAURANT_API_KEY").or(self.api_key),
+            token: var("RESTAURANT_TOKEN").or(self.token),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let config: ClientConfig =
+            serde_json::from_str(r#"{"base_url": "http://pos:8080/api/v1", "api_key": "k1"}"#)
+                .unwrap();
+        assert_eq!(
+            config,
+            ClientConfig {
+                base_url: "http://pos:8080/api/v1".to_string(),
+                api_key: Some("k1".to_string()),
+                token: None,
+            }
+        );
+        assert!(serde_json::from_str::<ClientConfig>(r#"{"url": "http://pos"}"#).is_err());
+
+        let config = config.with_overrides(|name| match name {
+            "RESTAURANT_TOKEN" => Some("t1".to_string()),
+            _ => None,
+        });
+        assert_eq!(config.base_url, "http://pos:8080/api/v1");
+        assert_eq!(config.api_key.as_deref(), Some("k1"));
+        assert_eq!(config.token.as_deref(), Some("t1"));
+
+        let config = ClientConfig::default()
+            .with_overrides(|name| (name == "RESTAURANT_URL").then(|| "http://x".to_string()));
+        assert_eq!(config.base_url, "http://x");
+    }
+}
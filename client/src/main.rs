@@ -0,0 +1,116 @@
+mod cli;
+mod client;
+mod config;
+
+use cli::{Command, Invocation, Output, USAGE};
+use client::{ClientError, RemovedItem, RestaurantClient};
+use config::ClientConfig;
+use serde::Serialize;
+use server::dto::GetItemResponse;
+
+fn main() {
+    let invocation = match Invocation::parse(std::env::args().skip(1)) {
+        Ok(invocation) => invocation,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let config = match ClientConfig::load(invocation.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = run(RestaurantClient::new(config), invocation) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(client: RestaurantClient, invocation: Invocation) -> Result<(), ClientError> {
+    let output = invocation.output;
+    match invocation.command {
+        Command::AddItem(item) => {
+            let response = client.add_item(&item)?;
+            match output {
+                Output::Table => println!("added item {}", response.added_item_id),
+                Output::Json => print_json(&response),
+            }
+        }
+        Command::GetItem(item_id) => {
+            let item = client.get_item(item_id)?;
+            match output {
+                Output::Table => print_items(std::slice::from_ref(&item)),
+                Output::Json => print_json(&item),
+            }
+        }
+        Command::RemoveItem { item_id, quantity } => {
+            let removed = client.remove_item(item_id, quantity)?;
+            match output {
+                Output::Table => print_removed(&removed),
+                Output::Json => print_json(&serde_json::json!({
+                    "item_id": removed.item_id,
+                    "removed": removed.removed,
+                    "version": removed.version,
+                })),
+            }
+        }
+        Command::ShowTable(table_id) => {
+            let items = client.get_all_items_for_table(table_id)?;
+            match output {
+                Output::Table => print_items(&items),
+                Output::Json => print_json(&items),
+            }
+        }
+        Command::ListItems(page) => {
+            let response = client.get_all_items(&page)?;
+            match output {
+                Output::Table => {
+                    print_items(&response.items);
+                    if let Some(cursor) = &response.next_cursor {
+                        println!("next page: --cursor {}", cursor);
+                    }
+                }
+                Output::Json => print_json(&response),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("failed to print the response: {}", e),
+    }
+}
+
+fn print_removed(removed: &RemovedItem) {
+    match removed.version {
+        Some(_) => println!("removed {} of item {}", removed.removed, removed.item_id),
+        None => println!(
+            "removed {} of item {}, the item was deleted",
+            removed.removed, removed.item_id
+        ),
+    }
+}
+
+fn print_items(items: &[GetItemResponse]) {
+    println!("    id  table  quantity  prepare  status     name");
+    for item in items {
+        let status = serde_json::to_value(item.status)
+            .ok()
+            .and_then(|status| status.as_str().map(str::to_string))
+            .unwrap_or_default();
+        let notes = match &item.notes {
+            Some(notes) => format!(" ({})", notes),
+            None => String::new(),
+        };
+        println!(
+            "{:>6}  {:>5}  {:>8}  {:>7}  {:<9}  {}{}",
+            item.id, item.table_id, item.quantity, item.time_to_prepare, status, item.name, notes
+        );
+    }
+}
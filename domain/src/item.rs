@@ -1,21 +1,33 @@
 use derive_new::new;
-use persistence::dao::{InsertItemDao, ItemDao};
+use persistence::dao::{InsertItemDao, ItemDao, ItemStatus};
 
 #[derive(Debug, new)]
 pub struct Item {
+    #[new(default)]
+    pub id: i64,
     pub name: String,
     pub table_id: i32,
     pub time_to_prepare: i32,
     pub quantity: i32,
+    #[new(default)]
+    pub version: i32,
+    #[new(default)]
+    pub notes: Option<String>,
+    #[new(default)]
+    pub status: ItemStatus,
 }
 
 impl Item {
     pub fn from_dao(item_dao: ItemDao) -> Item {
         Item {
+            id: item_dao.id,
             name: item_dao.name,
             table_id: item_dao.table_id,
             time_to_prepare: item_dao.time_to_prepare,
             quantity: item_dao.quantity,
+            version: item_dao.version,
+            notes: item_dao.notes,
+            status: item_dao.status,
         }
     }
 
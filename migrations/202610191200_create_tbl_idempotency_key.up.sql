@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS tbl_idempotency_key (
+    key VARCHAR(255) PRIMARY KEY,
+    request_hash VARCHAR(64) NOT NULL,
+    status_code SMALLINT,
+    content_type VARCHAR(255),
+    response_body BYTEA,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX ON tbl_idempotency_key(created_at);
+COMMENT ON TABLE tbl_idempotency_key IS 'Table for storing responses of mutating requests sent with an Idempotency-Key header';
+COMMENT ON COLUMN tbl_idempotency_key.key IS 'Value of the Idempotency-Key header';
+COMMENT ON COLUMN tbl_idempotency_key.request_hash IS 'SHA-256 of the method, path and body of the request the key was first used with';
+COMMENT ON COLUMN tbl_idempotency_key.status_code IS 'Status code of the stored response. NULL while the request is being processed';
+COMMENT ON COLUMN tbl_idempotency_key.content_type IS 'Content type of the stored response';
+COMMENT ON COLUMN tbl_idempotency_key.response_body IS 'Body of the stored response';
+COMMENT ON COLUMN tbl_idempotency_key.created_at IS 'Time the key was first used. Keys older than the configured window are discarded';
@@ -0,0 +1,2 @@
+ALTER TABLE tbl_item ADD COLUMN IF NOT EXISTS version INT NOT NULL DEFAULT 1;
+COMMENT ON COLUMN tbl_item.version IS 'Version of the item, incremented on every update. Used for optimistic concurrency control';
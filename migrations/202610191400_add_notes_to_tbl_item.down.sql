@@ -0,0 +1 @@
+ALTER TABLE tbl_item DROP COLUMN IF EXISTS notes;
@@ -0,0 +1,2 @@
+ALTER TABLE tbl_item ADD COLUMN IF NOT EXISTS notes VARCHAR(1024);
+COMMENT ON COLUMN tbl_item.notes IS 'Free-form notes of the guest about the item, e.g. allergies';
@@ -0,0 +1,2 @@
+ALTER TABLE tbl_item DROP COLUMN IF EXISTS status;
+DROP TYPE IF EXISTS item_status;
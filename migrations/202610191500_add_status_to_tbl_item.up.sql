@@ -0,0 +1,3 @@
+CREATE TYPE item_status AS ENUM ('ordered', 'preparing', 'ready', 'served');
+ALTER TABLE tbl_item ADD COLUMN IF NOT EXISTS status item_status NOT NULL DEFAULT 'ordered';
+COMMENT ON COLUMN tbl_item.status IS 'Preparation status of the item, the values are ordered from the least to the most advanced';
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS tbl_session;
+DROP TABLE IF EXISTS tbl_staff;
@@ -0,0 +1,30 @@
+CREATE TABLE IF NOT EXISTS tbl_staff (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    role VARCHAR(32) NOT NULL CHECK (role IN ('waiter', 'kitchen', 'manager')),
+    pin_hash VARCHAR(255) NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+COMMENT ON TABLE tbl_staff IS 'Table for storing staff members logging in on the shared handhelds';
+COMMENT ON COLUMN tbl_staff.id IS 'Primary key for tbl_staff, entered by the staff member together with the PIN';
+COMMENT ON COLUMN tbl_staff.name IS 'Name of the staff member';
+COMMENT ON COLUMN tbl_staff.role IS 'Role defining the permissions of the staff member';
+COMMENT ON COLUMN tbl_staff.pin_hash IS 'Argon2 hash of the PIN of the staff member';
+COMMENT ON COLUMN tbl_staff.active IS 'Inactive staff members can not log in';
+COMMENT ON COLUMN tbl_staff.created_at IS 'Technical column to store the time of creation of the staff member';
+
+CREATE TABLE IF NOT EXISTS tbl_session (
+    token_hash VARCHAR(64) PRIMARY KEY,
+    staff_id INT NOT NULL REFERENCES tbl_staff(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT LOCALTIMESTAMP,
+    last_seen_at TIMESTAMP NOT NULL DEFAULT LOCALTIMESTAMP,
+    expires_at TIMESTAMP NOT NULL
+);
+CREATE INDEX ON tbl_session(staff_id);
+COMMENT ON TABLE tbl_session IS 'Table for storing sessions of staff members logged in with a PIN';
+COMMENT ON COLUMN tbl_session.token_hash IS 'SHA-256 of the session token, the token itself is only known to the device';
+COMMENT ON COLUMN tbl_session.staff_id IS 'Staff member the session belongs to';
+COMMENT ON COLUMN tbl_session.created_at IS 'Time of the login';
+COMMENT ON COLUMN tbl_session.last_seen_at IS 'Time of the last request, sessions inactive for longer than the configured timeout are logged out';
+COMMENT ON COLUMN tbl_session.expires_at IS 'Time after which the session ends regardless of activity';
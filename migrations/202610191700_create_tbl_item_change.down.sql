@@ -0,0 +1,3 @@
+DROP TRIGGER IF EXISTS trg_record_item_change ON tbl_item;
+DROP FUNCTION IF EXISTS record_item_change();
+DROP TABLE IF EXISTS tbl_item_change;
@@ -0,0 +1,49 @@
+CREATE TABLE IF NOT EXISTS tbl_item_change (
+    id BIGSERIAL PRIMARY KEY,
+    item_id BIGINT NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    table_id INT NOT NULL,
+    operation VARCHAR(16) NOT NULL,
+    quantity INT NOT NULL,
+    status item_status NOT NULL,
+    performed_by VARCHAR(255),
+    changed_at TIMESTAMP NOT NULL DEFAULT LOCALTIMESTAMP
+);
+CREATE INDEX ON tbl_item_change(item_id);
+COMMENT ON TABLE tbl_item_change IS 'Table for storing every change of tbl_item together with the staff member who made it';
+COMMENT ON COLUMN tbl_item_change.item_id IS 'Id of the changed item. The item may not exist anymore';
+COMMENT ON COLUMN tbl_item_change.name IS 'Name of the item after the change';
+COMMENT ON COLUMN tbl_item_change.table_id IS 'Table of the item after the change';
+COMMENT ON COLUMN tbl_item_change.operation IS 'insert, update or delete';
+COMMENT ON COLUMN tbl_item_change.quantity IS 'Quantity of the item after the change, 0 when it was deleted';
+COMMENT ON COLUMN tbl_item_change.status IS 'Status of the item after the change';
+COMMENT ON COLUMN tbl_item_change.performed_by IS 'Staff member or device which made the change, taken from the restaurant.performed_by setting of the transaction';
+COMMENT ON COLUMN tbl_item_change.changed_at IS 'Time of the change';
+
+CREATE OR REPLACE FUNCTION record_item_change() RETURNS TRIGGER AS $$
+DECLARE
+    item tbl_item;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        item := OLD;
+        item.quantity := 0;
+    ELSE
+        item := NEW;
+    END IF;
+    INSERT INTO tbl_item_change (item_id, name, table_id, operation, quantity, status, performed_by)
+    VALUES (
+        item.id,
+        item.name,
+        item.table_id,
+        lower(TG_OP),
+        item.quantity,
+        item.status,
+        NULLIF(current_setting('restaurant.performed_by', true), '')
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trg_record_item_change
+AFTER INSERT OR UPDATE OR DELETE ON tbl_item
+FOR EACH ROW EXECUTE FUNCTION record_item_change();
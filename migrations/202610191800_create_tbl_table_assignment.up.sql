@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS tbl_table_assignment (
+    table_id INT NOT NULL,
+    staff_id INT NOT NULL REFERENCES tbl_staff(id) ON DELETE CASCADE,
+    assigned_at TIMESTAMP NOT NULL DEFAULT LOCALTIMESTAMP,
+    PRIMARY KEY (table_id, staff_id)
+);
+CREATE INDEX ON tbl_table_assignment(staff_id);
+COMMENT ON TABLE tbl_table_assignment IS 'Table for storing which waiters serve a table';
+COMMENT ON COLUMN tbl_table_assignment.table_id IS 'Number of the table';
+COMMENT ON COLUMN tbl_table_assignment.staff_id IS 'Waiter serving the table, a table can be served by several waiters';
+COMMENT ON COLUMN tbl_table_assignment.assigned_at IS 'Time the waiter was assigned to the table';
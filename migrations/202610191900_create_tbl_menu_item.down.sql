@@ -0,0 +1 @@
+DROP TABLE IF EXISTS tbl_menu_item;
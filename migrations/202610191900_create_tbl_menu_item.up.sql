@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS tbl_menu_item (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    time_to_prepare INT NOT NULL CHECK (time_to_prepare > 0),
+    updated_at TIMESTAMP NOT NULL DEFAULT LOCALTIMESTAMP
+);
+CREATE UNIQUE INDEX ON tbl_menu_item(lower(name));
+COMMENT ON TABLE tbl_menu_item IS 'Table for storing the dishes of the menu, seeded by the operations staff';
+COMMENT ON COLUMN tbl_menu_item.name IS 'Name of the dish, unique regardless of case';
+COMMENT ON COLUMN tbl_menu_item.time_to_prepare IS 'Time in minutes to prepare the dish, taken by ordered items with the same name';
+COMMENT ON COLUMN tbl_menu_item.updated_at IS 'Time the dish was last seeded';
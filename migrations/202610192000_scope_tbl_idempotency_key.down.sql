@@ -0,0 +1,6 @@
+-- Keys of different owners may collide once the owner is dropped.
+DELETE FROM tbl_idempotency_key;
+ALTER TABLE tbl_idempotency_key DROP CONSTRAINT tbl_idempotency_key_pkey;
+ALTER TABLE tbl_idempotency_key ADD PRIMARY KEY (key);
+ALTER TABLE tbl_idempotency_key DROP COLUMN locked_until;
+ALTER TABLE tbl_idempotency_key DROP COLUMN owner;
@@ -0,0 +1,6 @@
+ALTER TABLE tbl_idempotency_key ADD COLUMN owner VARCHAR(255) NOT NULL DEFAULT '';
+ALTER TABLE tbl_idempotency_key ADD COLUMN locked_until TIMESTAMP;
+ALTER TABLE tbl_idempotency_key DROP CONSTRAINT tbl_idempotency_key_pkey;
+ALTER TABLE tbl_idempotency_key ADD PRIMARY KEY (owner, key);
+COMMENT ON COLUMN tbl_idempotency_key.owner IS 'Staff member or device which sent the request, keys of different owners are independent';
+COMMENT ON COLUMN tbl_idempotency_key.locked_until IS 'Time until which the request is being processed. Afterwards an unfinished request is considered abandoned and the key can be reserved again';
@@ -0,0 +1,2 @@
+ALTER TABLE tbl_staff DROP COLUMN login_locked_until;
+ALTER TABLE tbl_staff DROP COLUMN failed_logins;
@@ -0,0 +1,4 @@
+ALTER TABLE tbl_staff ADD COLUMN failed_logins INT NOT NULL DEFAULT 0;
+ALTER TABLE tbl_staff ADD COLUMN login_locked_until TIMESTAMP;
+COMMENT ON COLUMN tbl_staff.failed_logins IS 'Failed logins since the last successful one or the last lockout';
+COMMENT ON COLUMN tbl_staff.login_locked_until IS 'Time until which logins are refused after too many failed ones';
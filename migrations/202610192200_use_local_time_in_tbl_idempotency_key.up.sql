@@ -0,0 +1 @@
+ALTER TABLE tbl_idempotency_key ALTER COLUMN created_at SET DEFAULT LOCALTIMESTAMP;
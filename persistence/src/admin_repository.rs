@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::{
+    dao::{ItemDao, MenuItemDao, TableOverviewDao},
+    error::DbError,
+};
+
+#[derive(Debug)]
+pub enum CloseTableOutcome {
+    /// The table was closed, the removed items are given.
+    Closed(Vec<ItemDao>),
+    /// Nothing was removed, the given items were not served yet.
+    NotServed(Vec<ItemDao>),
+}
+
+/// Operations of the staff running the restaurant, which are not offered by the API.
+#[async_trait]
+pub trait AdminRepository {
+    /// Returns every table which has items or waiters, ordered by table id.
+    async fn get_table_overviews(&self) -> Result<Vec<TableOverviewDao>, DbError>;
+    /// Removes every item of the table once the guests left. Unless `force` is set,
+    /// nothing is removed while some of the items were not served.
+    async fn close_table(
+        &self,
+        table_id: i32,
+        force: bool,
+        performed_by: &str,
+    ) -> Result<CloseTableOutcome, DbError>;
+    /// Adds the dishes to the menu in one transaction, updating the time to prepare of
+    /// dishes which are already on it. Returns the number of seeded dishes.
+    async fn seed_menu(&self, menu: &[MenuItemDao]) -> Result<u64, DbError>;
+    /// Returns the dishes of the menu, ordered by name.
+    async fn get_menu(&self) -> Result<Vec<MenuItemDao>, DbError>;
+    /// Deletes the recorded changes of removed items which are older than `older_than`
+    /// and returns how many were deleted. The changes of existing items are kept.
+    async fn purge_archived_changes(&self, older_than: Duration) -> Result<u64, DbError>;
+    /// Returns the name of the connected database.
+    async fn database_name(&self) -> Result<String, DbError>;
+    /// Deletes all data. Fails with `DbError::NotConfirmed` unless `database_name` is the
+    /// name of the connected database, so that a reset can't hit another database than
+    /// the intended one.
+    async fn reset(&self, database_name: &str) -> Result<(), DbError>;
+}
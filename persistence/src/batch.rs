@@ -0,0 +1,35 @@
+use crate::dao::InsertItemDao;
+use crate::error::DbError;
+
+#[derive(Debug, Clone)]
+pub enum BatchOperation {
+    Add(InsertItemDao),
+    Remove {
+        item_id: i64,
+        quantity: i32,
+        expected_version: Option<i32>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BatchMode {
+    /// All operations are applied or none of them. Execution stops at the first failure.
+    #[default]
+    Atomic,
+    /// Every operation is applied on its own, failed operations don't affect the others.
+    Partial,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchOutcome {
+    Added(i64),
+    Removed,
+}
+
+#[derive(Debug)]
+pub struct BatchResult {
+    /// Outcomes in the order of the operations. In atomic mode the list ends with the
+    /// failed operation, the operations after it were not executed.
+    pub outcomes: Vec<Result<BatchOutcome, DbError>>,
+    pub committed: bool,
+}
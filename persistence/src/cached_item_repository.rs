@@ -0,0 +1,521 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::batch::{BatchMode, BatchOperation, BatchOutcome, BatchResult};
+use crate::dao::{InsertItemDao, ItemChangeDao, ItemDao, TableStatsDao, UpdateItemDao};
+use crate::error::DbError;
+use crate::item_repository::ItemRepository;
+use crate::query::{ItemPage, ItemQuery};
+
+/// Bounds of the cache of items and tables. Caching is disabled when either is zero.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheConfig {
+    /// How long a cached result is served. Changes made by other processes are only
+    /// visible after it expired.
+    pub ttl: Duration,
+    /// Largest number of cached items, tables and pages of tables together.
+    pub max_entries: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            ttl: Duration::from_secs(5),
+            max_entries: 1000,
+        }
+    }
+}
+
+impl CacheConfig {
+    fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero() && self.max_entries > 0
+    }
+}
+
+/// Lookups of one kind of cached result since the start.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheCounts {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups of single items.
+    pub items: CacheCounts,
+    /// Lookups of the items of a table, as a whole or by page.
+    pub tables: CacheCounts,
+    /// Number of cached results, including expired ones not evicted yet.
+    pub entries: usize,
+}
+
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Counters {
+    fn get(&self) -> CacheCounts {
+        CacheCounts {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum CacheKey {
+    Item(i64),
+    Table(i32),
+    TablePage(i32, ItemQuery),
+}
+
+impl CacheKey {
+    fn table_id(&self) -> Option<i32> {
+        match self {
+            CacheKey::Item(_) => None,
+            CacheKey::Table(table_id) | CacheKey::TablePage(table_id, _) => Some(*table_id),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum CacheValue {
+    Item(Option<ItemDao>),
+    Table(Vec<ItemDao>),
+    TablePage(ItemPage),
+}
+
+impl CacheValue {
+    fn items(&self) -> &[ItemDao] {
+        match self {
+            CacheValue::Item(item) => item.as_slice(),
+            CacheValue::Table(items) => items,
+            CacheValue::TablePage(page) => &page.items,
+        }
+    }
+}
+
+struct CacheEntry {
+    value: CacheValue,
+    expires_at: Instant,
+}
+
+struct CacheState {
+    entries: HashMap<CacheKey, CacheEntry>,
+    /// Incremented by every invalidation. Results read while one happened may be stale
+    /// and are not cached.
+    generation: u64,
+}
+
+/// What a change made stale: everything cached of `table_ids`, and every result
+/// containing one of `item_ids` or an item of one of `table_ids`.
+#[derive(Debug, Default)]
+struct Invalidation {
+    table_ids: Vec<i32>,
+    item_ids: Vec<i64>,
+}
+
+impl Invalidation {
+    fn new(
+        table_ids: impl IntoIterator<Item = i32>,
+        item_ids: impl IntoIterator<Item = i64>,
+    ) -> Self {
+        Invalidation {
+            table_ids: table_ids.into_iter().collect(),
+            item_ids: item_ids.into_iter().collect(),
+        }
+    }
+
+    fn add_items<'a>(&mut self, items: impl IntoIterator<Item = &'a ItemDao>) {
+        for item in items {
+            self.table_ids.push(item.table_id);
+            self.item_ids.push(item.id);
+        }
+    }
+
+    fn covers(&self, key: &CacheKey, value: &CacheValue) -> bool {
+        if matches!(key, CacheKey::Item(item_id) if self.item_ids.contains(item_id))
+            || matches!(key.table_id(), Some(table_id) if self.table_ids.contains(&table_id))
+        {
+            return true;
+        }
+        value
+            .items()
+            .iter()
+            .any(|item| self.item_ids.contains(&item.id) || self.table_ids.contains(&item.table_id))
+    }
+}
+
+/// Item repository serving single items and the items of single tables from memory for
+/// a short time. Changes made through it evict exactly the results they affect, changes
+/// made by other processes become visible when the cached results expire.
+#[derive(Clone)]
+pub struct CachedItemRepository<R> {
+    pub inner: R,
+    config: CacheConfig,
+    state: Arc<Mutex<CacheState>>,
+    items: Arc<Counters>,
+    tables: Arc<Counters>,
+}
+
+impl<R> CachedItemRepository<R> {
+    pub fn new(inner: R, config: CacheConfig) -> CachedItemRepository<R> {
+        CachedItemRepository {
+            inner,
+            config,
+            state: Arc::new(Mutex::new(CacheState {
+                entries: HashMap::new(),
+                generation: 0,
+            })),
+            items: Arc::default(),
+            tables: Arc::default(),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            items: self.items.get(),
+            tables: self.tables.get(),
+            entries: self.state.lock().unwrap().entries.len(),
+        }
+    }
+
+    fn counters(&self, key: &CacheKey) -> &Counters {
+        match key {
+            CacheKey::Item(_) => &self.items,
+            CacheKey::Table(_) | CacheKey::TablePage(..) => &self.tables,
+        }
+    }
+
+    /// Returns the cached value of `key`, or loads it and caches it unless the cache was
+    /// invalidated meanwhile. Errors are not cached.
+    async fn get_or_load<T, Fut>(
+        &self,
+        key: CacheKey,
+        load: Fut,
+        wrap: impl FnOnce(T) -> CacheValue,
+        unwrap: impl FnOnce(CacheValue) -> Option<T>,
+    ) -> Result<T, DbError>
+    where
+        T: Clone,
+        Fut: Future<Output = Result<T, DbError>>,
+    {
+        if !self.config.is_enabled() {
+            return load.await;
+        }
+        let counters = self.counters(&key);
+        let generation = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            match state.entries.get(&key) {
+                Some(entry) if entry.expires_at > now => {
+                    if let Some(value) = unwrap(entry.value.clone()) {
+                        counters.hits.fetch_add(1, Ordering::Relaxed);
+                        return Ok(value);
+                    }
+                }
+                Some(_) => {
+                    state.entries.remove(&key);
+                }
+                None => {}
+            }
+            state.generation
+        };
+        counters.misses.fetch_add(1, Ordering::Relaxed);
+
+        let value = load.await?;
+        let mut state = self.state.lock().unwrap();
+        if state.generation == generation {
+            let now = Instant::now();
+            self.make_room(&mut state, now);
+            let entry = CacheEntry {
+                value: wrap(value.clone()),
+                expires_at: now + self.config.ttl,
+            };
+            state.entries.insert(key, entry);
+        }
+        Ok(value)
+    }
+
+    /// Evicts the expired entries when the cache is full, and the entry expiring first
+    /// when none of them has expired.
+    fn make_room(&self, state: &mut CacheState, now: Instant) {
+        if state.entries.len() < self.config.max_entries {
+            return;
+        }
+        state.entries.retain(|_, entry| entry.expires_at > now);
+        while state.entries.len() >= self.config.max_entries {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires_at)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => state.entries.remove(&key),
+                None => break,
+            };
+        }
+    }
+
+    fn invalidate(&self, invalidation: &Invalidation) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        state
+            .entries
+            .retain(|key, entry| !invalidation.covers(key, &entry.value));
+    }
+}
+
+#[async_trait]
+impl<R: ItemRepository + Send + Sync> ItemRepository for CachedItemRepository<R> {
+    async fn add_item(&self, item: InsertItemDao, performed_by: &str) -> Result<i64, DbError> {
+        let table_id = item.table_id;
+        let result = self.inner.add_item(item, performed_by).await;
+        // The item may have been added even when the result was lost.
+        let item_ids = result.as_ref().ok().copied();
+        self.invalidate(&Invalidation::new([table_id], item_ids));
+        result
+    }
+
+    async fn get_item(&self, item_id: i64) -> Result<Option<ItemDao>, DbError> {
+        self.get_or_load(
+            CacheKey::Item(item_id),
+            self.inner.get_item(item_id),
+            CacheValue::Item,
+            |value| match value {
+                CacheValue::Item(item) => Some(item),
+                _ => None,
+            },
+        )
+        .await
+    }
+
+    async fn get_items_for_table(&self, table_id: i32) -> Result<Vec<ItemDao>, DbError> {
+        self.get_or_load(
+            CacheKey::Table(table_id),
+            self.inner.get_items_for_table(table_id),
+            CacheValue::Table,
+            |value| match value {
+                CacheValue::Table(items) => Some(items),
+                _ => None,
+            },
+        )
+        .await
+    }
+
+    async fn get_items_for_tables(&self, table_ids: &[i32]) -> Result<Vec<ItemDao>, DbError> {
+        self.inner.get_items_for_tables(table_ids).await
+    }
+
+    async fn get_all_items(&self) -> Result<Vec<ItemDao>, DbError> {
+        self.inner.get_all_items().await
+    }
+
+    /// Pages of a single table are cached, other queries go to the database.
+    async fn query_items(&self, query: ItemQuery) -> Result<ItemPage, DbError> {
+        let Some(table_id) = query.single_table() else {
+            return self.inner.query_items(query).await;
+        };
+        self.get_or_load(
+            CacheKey::TablePage(table_id, query.clone()),
+            self.inner.query_items(query),
+            CacheValue::TablePage,
+            |value| match value {
+                CacheValue::TablePage(page) => Some(page),
+                _ => None,
+            },
+        )
+        .await
+    }
+
+    async fn remove_item(
+        &self,
+        item_id: i64,
+        quantity: i32,
+        expected_version: Option<i32>,
+        performed_by: &str,
+    ) -> Result<Option<ItemDao>, DbError> {
+        let result = self
+            .inner
+            .remove_item(item_id, quantity, expected_version, performed_by)
+            .await;
+        // Results containing the item cover the table it was removed from.
+        let mut invalidation = Invalidation::new([], [item_id]);
+        if let Ok(Some(item)) = &result {
+            invalidation.add_items([item]);
+        }
+        self.invalidate(&invalidation);
+        result
+    }
+
+    async fn update_item(
+        &self,
+        item_id: i64,
+        update: UpdateItemDao,
+        expected_version: Option<i32>,
+        performed_by: &str,
+    ) -> Result<Option<ItemDao>, DbError> {
+        let result = self
+            .inner
+            .update_item(item_id, update, expected_version, performed_by)
+            .await;
+        let mut invalidation = Invalidation::new([], [item_id]);
+        if let Ok(Some(item)) = &result {
+            invalidation.add_items([item]);
+        }
+        self.invalidate(&invalidation);
+        result
+    }
+
+    async fn transfer_table(
+        &self,
+        from_table_id: i32,
+        to_table_id: i32,
+        performed_by: &str,
+    ) -> Result<Vec<ItemDao>, DbError> {
+        let result = self
+            .inner
+            .transfer_table(from_table_id, to_table_id, performed_by)
+            .await;
+        self.invalidate(&Invalidation::new([from_table_id, to_table_id], []));
+        result
+    }
+
+    async fn execute_batch(
+        &self,
+        operations: Vec<BatchOperation>,
+        mode: BatchMode,
+        performed_by: &str,
+    ) -> Result<BatchResult, DbError> {
+        let mut invalidation = Invalidation::default();
+        for operation in &operations {
+            match operation {
+                BatchOperation::Add(item) => invalidation.table_ids.push(item.table_id),
+                BatchOperation::Remove { item_id, .. } => invalidation.item_ids.push(*item_id),
+            }
+        }
+        let result = self
+            .inner
+            .execute_batch(operations, mode, performed_by)
+            .await;
+        // Added items may not be cached as missing.
+        if let Ok(batch) = &result {
+            invalidation
+                .item_ids
+                .extend(batch.outcomes.iter().filter_map(|outcome| match outcome {
+                    Ok(BatchOutcome::Added(item_id)) => Some(*item_id),
+                    _ => None,
+                }));
+        }
+        self.invalidate(&invalidation);
+        result
+    }
+
+    async fn get_item_changes(&self, item_id: i64) -> Result<Vec<ItemChangeDao>, DbError> {
+        self.inner.get_item_changes(item_id).await
+    }
+
+    async fn get_table_stats(&self) -> Result<Vec<TableStatsDao>, DbError> {
+        self.inner.get_table_stats().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{init_test_db, reset_test_db};
+
+    fn insert(table_id: i32, name: &str) -> InsertItemDao {
+        InsertItemDao::new(name.to_string(), table_id, 5, 1)
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_cache() {
+        let item_repository = init_test_db().await;
+        let repository = CachedItemRepository::new(
+            item_repository.clone(),
+            CacheConfig {
+                ttl: Duration::from_secs(60),
+                max_entries: 3,
+            },
+        );
+
+        let item_id = repository
+            .add_item(insert(1, "ramen"), "test")
+            .await
+            .unwrap();
+        assert_eq!(repository.get_items_for_table(1).await.unwrap().len(), 1);
+        assert_eq!(repository.get_items_for_table(1).await.unwrap().len(), 1);
+        assert!(repository.get_item(item_id).await.unwrap().is_some());
+        assert!(repository.get_item(item_id).await.unwrap().is_some());
+        let stats = repository.stats();
+        assert_eq!(stats.tables, CacheCounts { hits: 1, misses: 1 });
+        assert_eq!(stats.items, CacheCounts { hits: 1, misses: 1 });
+        assert_eq!(stats.entries, 2);
+
+        // Changes of other tables keep the results of table 1, their own are evicted.
+        assert!(repository.get_items_for_table(2).await.unwrap().is_empty());
+        repository
+            .add_item(insert(2, "gyoza"), "test")
+            .await
+            .unwrap();
+        assert_eq!(repository.stats().entries, 2);
+        assert_eq!(repository.get_items_for_table(2).await.unwrap().len(), 1);
+        assert_eq!(repository.stats().tables.misses, 3);
+
+        // Removing the item evicts it and its table.
+        repository
+            .remove_item(item_id, 1, None, "test")
+            .await
+            .unwrap();
+        assert_eq!(repository.stats().entries, 1);
+        assert!(repository.get_items_for_table(1).await.unwrap().is_empty());
+        assert!(repository.get_item(item_id).await.unwrap().is_none());
+
+        // The fourth result evicts the one expiring first, the table 2.
+        repository.get_item(item_id + 1).await.unwrap();
+        assert_eq!(repository.stats().entries, 3);
+        let misses = repository.stats().tables.misses;
+        repository.get_items_for_table(2).await.unwrap();
+        assert_eq!(repository.stats().tables.misses, misses + 1);
+        assert_eq!(repository.stats().entries, 3);
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_cache_expiry() {
+        let item_repository = init_test_db().await;
+        let repository = CachedItemRepository::new(
+            item_repository.clone(),
+            CacheConfig {
+                ttl: Duration::from_millis(100),
+                max_entries: 10,
+            },
+        );
+        assert!(repository.get_items_for_table(1).await.unwrap().is_empty());
+
+        // Changes bypassing the cache are visible once the result expired.
+        item_repository
+            .add_item(insert(1, "udon"), "test")
+            .await
+            .unwrap();
+        assert!(repository.get_items_for_table(1).await.unwrap().is_empty());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(repository.get_items_for_table(1).await.unwrap().len(), 1);
+        assert_eq!(
+            repository.stats().tables,
+            CacheCounts { hits: 1, misses: 2 }
+        );
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+}
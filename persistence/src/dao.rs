@@ -10,6 +10,11 @@ pub struct ItemDao {
     pub time_to_prepare: i32,
     pub quantity: i32,
     pub created_at: chrono::NaiveDateTime,
+    pub version: i32,
+    #[new(default)]
+    pub notes: Option<String>,
+    #[new(default)]
+    pub status: ItemStatus,
 }
 
 impl ItemDao {
@@ -21,10 +26,30 @@ impl ItemDao {
             10,
             1,
             chrono::NaiveDateTime::new(NaiveDate::MIN, NaiveTime::MIN),
+            1,
         )
     }
 }
 
+/// Preparation status of an item. The variants are ordered like in the database, from the
+/// least to the most advanced.
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[sqlx(type_name = "item_status", rename_all = "snake_case")]
+pub enum ItemStatus {
+    #[default]
+    Ordered,
+    Preparing,
+    Ready,
+    Served,
+}
+
+impl ItemStatus {
+    /// Whether the kitchen already prepared the item.
+    pub fn is_prepared(&self) -> bool {
+        matches!(self, ItemStatus::Ready | ItemStatus::Served)
+    }
+}
+
 #[derive(new, FromRow, Debug, Clone)]
 pub struct InsertItemDao {
     pub name: String,
@@ -32,3 +57,99 @@ pub struct InsertItemDao {
     pub time_to_prepare: i32,
     pub quantity: i32,
 }
+
+/// Longest notes of an item, the length of the `notes` column.
+pub const MAX_NOTES_LENGTH: usize = 1024;
+
+/// Changes of an item. Fields that are `None` are left as they are.
+#[derive(new, Debug, Clone, Default)]
+pub struct UpdateItemDao {
+    pub quantity: Option<i32>,
+    pub table_id: Option<i32>,
+    /// New notes, an empty string removes them.
+    pub notes: Option<String>,
+    #[new(default)]
+    pub status: Option<ItemStatus>,
+}
+
+/// Recorded change of an item.
+#[derive(FromRow, Debug, Clone)]
+pub struct ItemChangeDao {
+    pub id: i64,
+    pub item_id: i64,
+    pub name: String,
+    pub table_id: i32,
+    /// `insert`, `update` or `delete`.
+    pub operation: String,
+    pub quantity: i32,
+    pub status: ItemStatus,
+    pub performed_by: Option<String>,
+    pub changed_at: chrono::NaiveDateTime,
+}
+
+/// Items of a table which were not served yet.
+#[derive(FromRow, Debug, Clone, PartialEq)]
+pub struct TableStatsDao {
+    pub table_id: i32,
+    /// Sum of the quantities of the open items.
+    pub open_items: i64,
+    /// Average `time_to_prepare` of the open items, weighted by their quantity.
+    pub average_time_to_prepare: f64,
+}
+
+/// Items and waiters of a table.
+#[derive(FromRow, Debug, Clone, PartialEq)]
+pub struct TableOverviewDao {
+    pub table_id: i32,
+    /// Number of different items.
+    pub items: i64,
+    /// Sum of the quantities of the items which were not served yet.
+    pub open_items: i64,
+    pub waiters: i64,
+    /// Time the oldest item was ordered, `None` when the table has no items.
+    pub first_ordered_at: Option<chrono::NaiveDateTime>,
+}
+
+/// Dish of the menu.
+#[derive(new, FromRow, Debug, Clone, PartialEq)]
+pub struct MenuItemDao {
+    pub name: String,
+    pub time_to_prepare: i32,
+}
+
+#[derive(FromRow, Debug, Clone)]
+pub struct StaffDao {
+    pub id: i32,
+    pub name: String,
+    pub role: String,
+    pub pin_hash: String,
+    pub active: bool,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(new, Debug, Clone)]
+pub struct InsertStaffDao {
+    pub name: String,
+    pub role: String,
+    pub pin_hash: String,
+}
+
+#[derive(FromRow, Debug, Clone)]
+pub struct IdempotencyKeyDao {
+    pub owner: String,
+    pub key: String,
+    pub request_hash: String,
+    pub status_code: Option<i16>,
+    pub content_type: Option<String>,
+    pub response_body: Option<Vec<u8>>,
+    pub created_at: chrono::NaiveDateTime,
+    /// End of the reservation of a request which is being processed.
+    pub locked_until: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(new, Debug, Clone)]
+pub struct StoredResponseDao {
+    pub status_code: i16,
+    pub content_type: Option<String>,
+    pub response_body: Vec<u8>,
+}
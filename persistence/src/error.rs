@@ -1,14 +1,36 @@
 use derive_more::Display;
 use sqlx::migrate::MigrateError;
 use sqlx::Error;
+use std::time::Duration;
 
 #[derive(Debug, Display)]
 pub enum DbError {
     MigrateError(MigrateError),
     SqlxError(Error),
+    /// The item does not exist or its version differs from the expected one.
+    VersionMismatch,
+    /// The database is considered down, it is tried again after the given time.
+    #[display(fmt = "the database is unavailable")]
+    Unavailable(Duration),
+    /// The migration has no down migration.
+    #[display(fmt = "migration {} can not be reverted", _0)]
+    Irreversible(i64),
+    /// A reset was not confirmed with the name of the database, which is given.
+    #[display(fmt = "the reset of the database {} was not confirmed", _0)]
+    NotConfirmed(String),
+    /// The notes of two merged items together are longer than the given length.
+    #[display(
+        fmt = "the notes of the merged items are longer than {} characters together",
+        _0
+    )]
+    MergedNotesTooLong(usize),
 }
 impl std::error::Error for DbError {}
 
+/// SQLSTATEs of failures which are not caused by the query: serialization failures and
+/// deadlocks of concurrent transactions, lost connections and a restarting server.
+const TRANSIENT_SQLSTATES: [&str; 5] = ["40001", "40P01", "57P01", "57P02", "57P03"];
+
 impl DbError {
     pub fn from_sqlx_error(error: Error) -> DbError {
         DbError::SqlxError(error)
@@ -17,4 +39,49 @@ impl DbError {
     pub fn from_migrate_error(error: MigrateError) -> DbError {
         DbError::MigrateError(error)
     }
+
+    /// Whether the database could not be reached, rather than rejecting the query.
+    pub fn is_connection_failure(&self) -> bool {
+        match self {
+            DbError::SqlxError(Error::Database(e)) => e
+                .code()
+                .is_some_and(|code| code.starts_with("08") || code.starts_with("57P")),
+            DbError::SqlxError(e) => matches!(
+                e,
+                Error::Io(_)
+                    | Error::Tls(_)
+                    | Error::Protocol(_)
+                    | Error::PoolTimedOut
+                    | Error::PoolClosed
+                    | Error::WorkerCrashed
+            ),
+            _ => false,
+        }
+    }
+
+    /// Whether the same query may succeed when it is run again.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DbError::SqlxError(Error::Database(e)) => e.code().is_some_and(|code| {
+                code.starts_with("08") || TRANSIENT_SQLSTATES.contains(&code.as_ref())
+            }),
+            DbError::SqlxError(Error::Io(_)) => true,
+            DbError::SqlxError(Error::PoolTimedOut) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether a change failing with this error was certainly not applied, so it may be
+    /// run again. A connection lost while committing leaves the change possibly applied,
+    /// only failures before the transaction began and transactions the database rolled
+    /// back qualify.
+    pub fn is_retryable_change(&self) -> bool {
+        match self {
+            DbError::SqlxError(Error::Database(e)) => e
+                .code()
+                .is_some_and(|code| ["40001", "40P01", "57P03"].contains(&code.as_ref())),
+            DbError::SqlxError(Error::PoolTimedOut) => true,
+            _ => false,
+        }
+    }
 }
@@ -0,0 +1,12 @@
+use async_trait::async_trait;
+
+use crate::error::DbError;
+
+#[async_trait]
+pub trait HealthRepository {
+    /// Runs a trivial query to check that the database can be reached.
+    async fn ping(&self) -> Result<(), DbError>;
+    /// Returns `version description` of every migration of the server which is not
+    /// applied to the database yet, oldest first.
+    async fn pending_migrations(&self) -> Result<Vec<String>, DbError>;
+}
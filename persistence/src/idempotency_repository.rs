@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::{dao::StoredResponseDao, error::DbError};
+
+#[derive(Debug, Clone)]
+pub enum KeyReservation {
+    /// The key was not used yet and is now reserved for the request.
+    Reserved,
+    /// Another request with the same key is still being processed, and its reservation
+    /// did not expire yet.
+    InProgress,
+    /// The request was already processed, its response is stored.
+    Completed(StoredResponseDao),
+    /// The key was used with a different request.
+    Mismatch,
+}
+
+/// Keys are scoped by their `owner`, the staff member or device sending the requests, so
+/// that different devices can't replay or block each other's requests.
+#[async_trait]
+pub trait IdempotencyRepository {
+    /// Reserves the key for the request identified by `request_hash` for `lock_timeout`.
+    /// Keys older than `ttl` are considered unused. A reservation which was neither
+    /// completed nor released within `lock_timeout` is considered abandoned, e.g. because
+    /// the client disconnected, and is taken over by a repeated request.
+    async fn reserve_key(
+        &self,
+        owner: &str,
+        key: &str,
+        request_hash: &str,
+        ttl: Duration,
+        lock_timeout: Duration,
+    ) -> Result<KeyReservation, DbError>;
+    async fn complete_key(
+        &self,
+        owner: &str,
+        key: &str,
+        response: StoredResponseDao,
+    ) -> Result<(), DbError>;
+    /// Drops the reservation, so the request can be retried with the same key.
+    async fn release_key(&self, owner: &str, key: &str) -> Result<(), DbError>;
+    /// Deletes keys older than `ttl` and returns how many were deleted.
+    async fn purge_expired_keys(&self, ttl: Duration) -> Result<u64, DbError>;
+}
@@ -1,22 +1,67 @@
 use async_trait::async_trait;
 
 use crate::{
-    dao::{InsertItemDao, ItemDao},
+    batch::{BatchMode, BatchOperation, BatchResult},
+    dao::{InsertItemDao, ItemChangeDao, ItemDao, TableStatsDao, UpdateItemDao},
     error::DbError,
+    query::{ItemPage, ItemQuery},
+    retry::ConnectOptions,
 };
 
+/// Mutating methods take the staff member or device performing the change as
+/// `performed_by`, every change of an item is recorded with it.
 #[async_trait]
 pub trait ItemRepository {
-    async fn add_item(&self, item: InsertItemDao) -> Result<i64, DbError>;
+    async fn add_item(&self, item: InsertItemDao, performed_by: &str) -> Result<i64, DbError>;
     async fn get_item(&self, item_id: i64) -> Result<Option<ItemDao>, DbError>;
     async fn get_items_for_table(&self, table_id: i32) -> Result<Vec<ItemDao>, DbError>;
+    async fn get_items_for_tables(&self, table_ids: &[i32]) -> Result<Vec<ItemDao>, DbError>;
     async fn get_all_items(&self) -> Result<Vec<ItemDao>, DbError>;
-    async fn remove_item(&self, item_id: i64, quantity: i32) -> Result<(), DbError>;
+    async fn query_items(&self, query: ItemQuery) -> Result<ItemPage, DbError>;
+    /// Reduces the quantity of the item and returns what is left of it. When
+    /// `expected_version` is set, fails with `DbError::VersionMismatch` unless the item
+    /// exists with that version.
+    async fn remove_item(
+        &self,
+        item_id: i64,
+        quantity: i32,
+        expected_version: Option<i32>,
+        performed_by: &str,
+    ) -> Result<Option<ItemDao>, DbError>;
+    /// Applies `update` to the item and returns the updated item. Moving the item to a
+    /// table which already has an item with the same name merges both, the merged item is
+    /// returned. Returns `None` when the item does not exist.
+    async fn update_item(
+        &self,
+        item_id: i64,
+        update: UpdateItemDao,
+        expected_version: Option<i32>,
+        performed_by: &str,
+    ) -> Result<Option<ItemDao>, DbError>;
+    /// Moves every item of `from_table_id` to `to_table_id` in one transaction, merging
+    /// items whose name is already ordered on the target table. Returns the items of the
+    /// target table after the transfer.
+    async fn transfer_table(
+        &self,
+        from_table_id: i32,
+        to_table_id: i32,
+        performed_by: &str,
+    ) -> Result<Vec<ItemDao>, DbError>;
+    async fn execute_batch(
+        &self,
+        operations: Vec<BatchOperation>,
+        mode: BatchMode,
+        performed_by: &str,
+    ) -> Result<BatchResult, DbError>;
+    /// Returns the recorded changes of the item, oldest first.
+    async fn get_item_changes(&self, item_id: i64) -> Result<Vec<ItemChangeDao>, DbError>;
+    /// Returns the open items of every table which has any, ordered by table id.
+    async fn get_table_stats(&self) -> Result<Vec<TableStatsDao>, DbError>;
 }
 
 impl dyn ItemRepository {
-    pub async fn init_prod() -> Box<Self> {
-        let pg_impl = crate::postgres_item_repository::PgItemRepository::init_prod().await;
-        Box::new(pg_impl)
+    pub async fn init_prod(options: &ConnectOptions) -> Result<Box<Self>, DbError> {
+        let pg_impl = crate::postgres_item_repository::PgItemRepository::init_prod(options).await?;
+        Ok(Box::new(pg_impl))
     }
 }
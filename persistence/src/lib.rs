@@ -1,27 +1,44 @@
+use admin_repository::AdminRepository;
+use postgres_admin_repository::PgAdminRepository;
 use postgres_item_repository::PgItemRepository;
+use sqlx::migrate::Migrator;
 use sqlx::{Pool, Postgres};
 
+pub mod admin_repository;
+pub mod batch;
+pub mod cached_item_repository;
 pub mod dao;
 pub mod error;
+pub mod health_repository;
+pub mod idempotency_repository;
 pub mod item_repository;
+pub mod migrations;
+pub mod postgres_admin_repository;
+pub mod postgres_health_repository;
+pub mod postgres_idempotency_repository;
 pub mod postgres_item_repository;
 pub mod postgres_repositories;
+pub mod postgres_staff_repository;
+pub mod query;
+pub mod replicated_item_repository;
 pub mod repositories;
+pub mod resilient_item_repository;
+pub mod retry;
+pub mod staff_repository;
 
-pub async fn truncate_table(connection_pool: Pool<Postgres>) {
-    sqlx::query("DELETE FROM tbl_item")
-        .execute(&connection_pool)
+/// Migrations of the database, embedded into the binary.
+pub static MIGRATOR: Migrator = sqlx::migrate!("../migrations");
+
+/// Deletes all data of the test database.
+pub async fn reset_test_db(connection_pool: Pool<Postgres>) {
+    PgAdminRepository { connection_pool }
+        .reset("restaurant_test")
         .await
-        .and(
-            sqlx::query("ALTER SEQUENCE tbl_item_id_seq RESTART WITH 1")
-                .execute(&connection_pool)
-                .await,
-        )
         .unwrap();
 }
 
 pub async fn init_test_db() -> PgItemRepository {
     let result = PgItemRepository::init_test().await;
-    truncate_table(result.connection_pool.clone()).await;
+    reset_test_db(result.connection_pool.clone()).await;
     result
 }
@@ -0,0 +1,186 @@
+use chrono::{DateTime, Utc};
+use sqlx::migrate::Migration;
+use sqlx::{Pool, Postgres};
+
+use crate::error::DbError;
+use crate::MIGRATOR;
+
+/// A migration of the server and whether it is applied to the database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    /// Time the migration was applied, `None` while it is pending.
+    pub applied_at: Option<DateTime<Utc>>,
+    /// Whether the migration has a down migration.
+    pub reversible: bool,
+}
+
+/// Up migrations of the server, oldest first.
+fn up_migrations() -> impl Iterator<Item = &'static Migration> {
+    MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+}
+
+fn is_reversible(version: i64) -> bool {
+    MIGRATOR.iter().any(|migration| {
+        migration.version == version && migration.migration_type.is_down_migration()
+    })
+}
+
+/// Returns every migration of the server, oldest first.
+pub async fn migration_status(
+    connection_pool: &Pool<Postgres>,
+) -> Result<Vec<MigrationStatus>, DbError> {
+    let result = sqlx::query_as::<_, (i64, DateTime<Utc>)>(
+        "SELECT version, installed_on FROM _sqlx_migrations WHERE success",
+    )
+    .fetch_all(connection_pool)
+    .await;
+    let applied = match result {
+        Ok(applied) => applied,
+        // The table of sqlx only exists once the first migration was run.
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("42P01") => vec![],
+        Err(e) => return Err(DbError::from_sqlx_error(e)),
+    };
+
+    Ok(up_migrations()
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            applied_at: applied
+                .iter()
+                .find(|(version, _)| *version == migration.version)
+                .map(|(_, applied_at)| *applied_at),
+            reversible: is_reversible(migration.version),
+        })
+        .collect())
+}
+
+/// Applies the pending migrations and returns their versions.
+pub async fn migrate_up(connection_pool: &Pool<Postgres>) -> Result<Vec<i64>, DbError> {
+    let pending: Vec<i64> = migration_status(connection_pool)
+        .await?
+        .into_iter()
+        .filter(|migration| migration.applied_at.is_none())
+        .map(|migration| migration.version)
+        .collect();
+    MIGRATOR
+        .run(connection_pool)
+        .await
+        .map_err(DbError::from_migrate_error)?;
+    Ok(pending)
+}
+
+/// Reverts the applied migrations newer than `target`, newest first, and returns their
+/// versions. Nothing is reverted when one of them has no down migration. Reverting to
+/// version 0 reverts all migrations.
+pub async fn migrate_down(
+    connection_pool: &Pool<Postgres>,
+    target: i64,
+) -> Result<Vec<i64>, DbError> {
+    let mut reverted: Vec<MigrationStatus> = migration_status(connection_pool)
+        .await?
+        .into_iter()
+        .filter(|migration| migration.applied_at.is_some() && migration.version > target)
+        .collect();
+    reverted.reverse();
+    if let Some(migration) = reverted.iter().find(|migration| !migration.reversible) {
+        return Err(DbError::Irreversible(migration.version));
+    }
+    MIGRATOR
+        .undo(connection_pool, target)
+        .await
+        .map_err(DbError::from_migrate_error)?;
+    Ok(reverted
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect())
+}
+
+/// Version the last applied migration is reverted to by [`migrate_down`], `None` when no
+/// migration is applied.
+pub fn previous_version(migrations: &[MigrationStatus]) -> Option<i64> {
+    let mut applied = migrations
+        .iter()
+        .filter(|migration| migration.applied_at.is_some())
+        .map(|migration| migration.version)
+        .rev();
+    applied.next()?;
+    Some(applied.next().unwrap_or(0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::health_repository::HealthRepository;
+    use crate::postgres_health_repository::PgHealthRepository;
+    use crate::{init_test_db, reset_test_db};
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_migrations() {
+        let connection_pool = init_test_db().await.connection_pool;
+        let health_repository = PgHealthRepository {
+            connection_pool: connection_pool.clone(),
+        };
+        let migrations = migration_status(&connection_pool).await.unwrap();
+        assert_eq!(migrations.len(), 12);
+        assert!(migrations
+            .iter()
+            .all(|migration| migration.applied_at.is_some() && migration.reversible));
+        assert_eq!(migrations[0].description, "create tbl item");
+        let last = migrations.last().unwrap().version;
+        let previous = previous_version(&migrations).unwrap();
+        assert_eq!(previous, migrations[10].version);
+
+        assert_eq!(
+            migrate_down(&connection_pool, previous).await.unwrap(),
+            [last]
+        );
+        let migrations = migration_status(&connection_pool).await.unwrap();
+        assert!(migrations.last().unwrap().applied_at.is_none());
+        assert_eq!(
+            health_repository.pending_migrations().await.unwrap().len(),
+            1
+        );
+
+        // Every down migration reverts its up migration.
+        let reverted = migrate_down(&connection_pool, 0).await.unwrap();
+        assert_eq!(reverted.len(), 11);
+        assert_eq!(previous_version(&[]), None);
+        let tables: Vec<String> = sqlx::query_scalar(
+            "SELECT tablename::text FROM pg_tables WHERE tablename LIKE 'tbl_%'",
+        )
+        .fetch_all(&connection_pool)
+        .await
+        .unwrap();
+        assert!(tables.is_empty(), "{:?}", tables);
+
+        // A fresh database has no table of the applied migrations either.
+        sqlx::query("DROP TABLE _sqlx_migrations")
+            .execute(&connection_pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            health_repository.pending_migrations().await.unwrap().len(),
+            12
+        );
+        assert!(migration_status(&connection_pool)
+            .await
+            .unwrap()
+            .iter()
+            .all(|migration| migration.applied_at.is_none()));
+
+        assert_eq!(migrate_up(&connection_pool).await.unwrap().len(), 12);
+        assert!(migrate_up(&connection_pool).await.unwrap().is_empty());
+        assert!(health_repository
+            .pending_migrations()
+            .await
+            .unwrap()
+            .is_empty());
+
+        reset_test_db(connection_pool).await;
+    }
+}
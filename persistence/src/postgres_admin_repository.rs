@@ -0,0 +1,377 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use sqlx::{Pool, Postgres};
+
+use crate::admin_repository::{AdminRepository, CloseTableOutcome};
+use crate::dao::{ItemDao, ItemStatus, MenuItemDao, TableOverviewDao};
+use crate::error::DbError;
+use crate::postgres_item_repository::PgItemRepository;
+
+#[derive(Clone)]
+pub struct PgAdminRepository {
+    pub connection_pool: Pool<Postgres>,
+}
+
+#[async_trait]
+impl AdminRepository for PgAdminRepository {
+    async fn get_table_overviews(&self) -> Result<Vec<TableOverviewDao>, DbError> {
+        let result = sqlx::query_as::<_, TableOverviewDao>(
+            r#"
+            WITH tables AS (
+                SELECT table_id FROM tbl_item
+                UNION
+                SELECT table_id FROM tbl_table_assignment
+            )
+            SELECT
+                tables.table_id,
+                (SELECT COUNT(*) FROM tbl_item i WHERE i.table_id = tables.table_id) AS items,
+                (SELECT COALESCE(SUM(quantity), 0)::BIGINT
+                    FROM tbl_item i
+                    WHERE i.table_id = tables.table_id AND i.status <> 'served') AS open_items,
+                (SELECT COUNT(*)
+                    FROM tbl_table_assignment a
+                    WHERE a.table_id = tables.table_id) AS waiters,
+                (SELECT MIN(created_at)
+                    FROM tbl_item i
+                    WHERE i.table_id = tables.table_id) AS first_ordered_at
+            FROM tables
+            ORDER BY tables.table_id
+            "#,
+        )
+        .fetch_all(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(tables) => Ok(tables),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn close_table(
+        &self,
+        table_id: i32,
+        force: bool,
+        performed_by: &str,
+    ) -> Result<CloseTableOutcome, DbError> {
+        let item_repository = PgItemRepository {
+            connection_pool: self.connection_pool.clone(),
+        };
+        let mut tx = item_repository.begin_as(performed_by).await?;
+        let items = sqlx::query_as::<_, ItemDao>(
+            r#"
+            SELECT *
+            FROM tbl_item
+            WHERE table_id = $1
+            ORDER BY id
+            FOR UPDATE
+            "#,
+        )
+        .bind(table_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(DbError::from_sqlx_error)?;
+
+        let not_served: Vec<ItemDao> = items
+            .iter()
+            .filter(|item| item.status != ItemStatus::Served)
+            .cloned()
+            .collect();
+        if !force && !not_served.is_empty() {
+            return Ok(CloseTableOutcome::NotServed(not_served));
+        }
+
+        // Only the locked items, items ordered meanwhile belong to the next guests.
+        let item_ids: Vec<i64> = items.iter().map(|item| item.id).collect();
+        let result = sqlx::query("DELETE FROM tbl_item WHERE id = ANY($1)")
+            .bind(&item_ids)
+            .execute(&mut *tx)
+            .await;
+        if let Err(e) = result {
+            return Err(DbError::from_sqlx_error(e));
+        }
+        tx.commit().await.map_err(DbError::from_sqlx_error)?;
+        Ok(CloseTableOutcome::Closed(items))
+    }
+
+    async fn seed_menu(&self, menu: &[MenuItemDao]) -> Result<u64, DbError> {
+        let mut tx = self
+            .connection_pool
+            .begin()
+            .await
+            .map_err(DbError::from_sqlx_error)?;
+        for dish in menu {
+            sqlx::query(
+                r#"
+                INSERT INTO tbl_menu_item (name, time_to_prepare)
+                VALUES ($1, $2)
+                ON CONFLICT ((lower(name))) DO UPDATE
+                SET name = EXCLUDED.name,
+                    time_to_prepare = EXCLUDED.time_to_prepare,
+                    updated_at = LOCALTIMESTAMP
+                "#,
+            )
+            .bind(&dish.name)
+            .bind(dish.time_to_prepare)
+            .execute(&mut *tx)
+            .await
+            .map_err(DbError::from_sqlx_error)?;
+        }
+        tx.commit().await.map_err(DbError::from_sqlx_error)?;
+        Ok(menu.len() as u64)
+    }
+
+    async fn get_menu(&self) -> Result<Vec<MenuItemDao>, DbError> {
+        let result = sqlx::query_as::<_, MenuItemDao>(
+            r#"
+            SELECT name, time_to_prepare
+            FROM tbl_menu_item
+            ORDER BY lower(name)
+            "#,
+        )
+        .fetch_all(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(menu) => Ok(menu),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn purge_archived_changes(&self, older_than: Duration) -> Result<u64, DbError> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM tbl_item_change c
+            WHERE c.changed_at <= LOCALTIMESTAMP - make_interval(secs => $1)
+                AND NOT EXISTS (SELECT 1 FROM tbl_item i WHERE i.id = c.item_id)
+            "#,
+        )
+        .bind(older_than.as_secs_f64())
+        .execute(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(result) => Ok(result.rows_affected()),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn database_name(&self) -> Result<String, DbError> {
+        let result = sqlx::query_scalar::<_, String>("SELECT current_database()::text")
+            .fetch_one(&self.connection_pool)
+            .await;
+
+        match result {
+            Ok(name) => Ok(name),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn reset(&self, database_name: &str) -> Result<(), DbError> {
+        let connected = self.database_name().await?;
+        if connected != database_name {
+            return Err(DbError::NotConfirmed(connected));
+        }
+        let mut tx = self
+            .connection_pool
+            .begin()
+            .await
+            .map_err(DbError::from_sqlx_error)?;
+        for statement in [
+            "DELETE FROM tbl_item",
+            "ALTER SEQUENCE tbl_item_id_seq RESTART WITH 1",
+            "DELETE FROM tbl_item_change",
+            "DELETE FROM tbl_session",
+            "DELETE FROM tbl_table_assignment",
+            "DELETE FROM tbl_staff",
+            "DELETE FROM tbl_idempotency_key",
+            "DELETE FROM tbl_menu_item",
+        ] {
+            sqlx::query(statement)
+                .execute(&mut *tx)
+                .await
+                .map_err(DbError::from_sqlx_error)?;
+        }
+        tx.commit().await.map_err(DbError::from_sqlx_error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dao::{InsertItemDao, UpdateItemDao};
+    use crate::item_repository::ItemRepository;
+    use crate::postgres_staff_repository::PgStaffRepository;
+    use crate::staff_repository::StaffRepository;
+    use crate::{init_test_db, reset_test_db};
+
+    const STAFF: &str = "test";
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_close_table() {
+        let item_repository = init_test_db().await;
+        let repository = PgAdminRepository {
+            connection_pool: item_repository.connection_pool.clone(),
+        };
+        let sushi = InsertItemDao::new("sushi".to_string(), 1, 5, 2);
+        let sushi_id = item_repository.add_item(sushi, STAFF).await.unwrap();
+        let ramen = InsertItemDao::new("ramen".to_string(), 1, 10, 1);
+        item_repository.add_item(ramen, STAFF).await.unwrap();
+        let staff_id = PgStaffRepository {
+            connection_pool: item_repository.connection_pool.clone(),
+        }
+        .add_staff(crate::dao::InsertStaffDao::new(
+            "Anna".to_string(),
+            "waiter".to_string(),
+            "hash".to_string(),
+        ))
+        .await
+        .unwrap();
+        PgStaffRepository {
+            connection_pool: item_repository.connection_pool.clone(),
+        }
+        .set_table_waiters(2, &[staff_id])
+        .await
+        .unwrap();
+
+        let tables = repository.get_table_overviews().await.unwrap();
+        assert_eq!(tables.len(), 2);
+        assert_eq!(
+            (
+                tables[0].table_id,
+                tables[0].items,
+                tables[0].open_items,
+                tables[0].waiters
+            ),
+            (1, 2, 3, 0)
+        );
+        assert!(tables[0].first_ordered_at.is_some());
+        assert_eq!(
+            (tables[1].table_id, tables[1].items, tables[1].waiters),
+            (2, 0, 1)
+        );
+
+        let served = UpdateItemDao {
+            status: Some(ItemStatus::Served),
+            ..Default::default()
+        };
+        item_repository
+            .update_item(sushi_id, served, None, STAFF)
+            .await
+            .unwrap();
+        match repository.close_table(1, false, "admin").await.unwrap() {
+            CloseTableOutcome::NotServed(items) => {
+                assert_eq!(items.len(), 1);
+                assert_eq!(items[0].name, "ramen");
+            }
+            outcome => panic!("{:?}", outcome),
+        }
+        match repository.close_table(1, true, "admin").await.unwrap() {
+            CloseTableOutcome::Closed(items) => assert_eq!(items.len(), 2),
+            outcome => panic!("{:?}", outcome),
+        }
+        assert!(item_repository
+            .get_items_for_table(1)
+            .await
+            .unwrap()
+            .is_empty());
+        let changes = item_repository.get_item_changes(sushi_id).await.unwrap();
+        assert_eq!(changes.last().unwrap().operation, "delete");
+        assert_eq!(
+            changes.last().unwrap().performed_by.as_deref(),
+            Some("admin")
+        );
+
+        // The history of removed items is archived, the one of existing items is kept.
+        let pasta = InsertItemDao::new("pasta".to_string(), 3, 5, 1);
+        let pasta_id = item_repository.add_item(pasta, STAFF).await.unwrap();
+        assert_eq!(
+            repository
+                .purge_archived_changes(Duration::from_secs(60))
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            repository
+                .purge_archived_changes(Duration::ZERO)
+                .await
+                .unwrap(),
+            5
+        );
+        assert!(item_repository
+            .get_item_changes(sushi_id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            item_repository
+                .get_item_changes(pasta_id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_menu() {
+        let item_repository = init_test_db().await;
+        let repository = PgAdminRepository {
+            connection_pool: item_repository.connection_pool.clone(),
+        };
+        let menu = [
+            MenuItemDao::new("Sushi".to_string(), 12),
+            MenuItemDao::new("Ramen".to_string(), 8),
+        ];
+        assert_eq!(repository.seed_menu(&menu).await.unwrap(), 2);
+        let update = [MenuItemDao::new("sushi".to_string(), 14)];
+        assert_eq!(repository.seed_menu(&update).await.unwrap(), 1);
+        assert_eq!(
+            repository.get_menu().await.unwrap(),
+            [
+                MenuItemDao::new("Ramen".to_string(), 8),
+                MenuItemDao::new("sushi".to_string(), 14),
+            ]
+        );
+        let invalid = [MenuItemDao::new("udon".to_string(), 0)];
+        assert!(repository.seed_menu(&invalid).await.is_err());
+
+        // Ordered dishes take the time to prepare of the menu.
+        let sushi = InsertItemDao::new("SUSHI".to_string(), 1, 5, 1);
+        let sushi_id = item_repository.add_item(sushi, STAFF).await.unwrap();
+        let sushi = item_repository.get_item(sushi_id).await.unwrap().unwrap();
+        assert_eq!(sushi.time_to_prepare, 14);
+        let udon = InsertItemDao::new("udon".to_string(), 1, 5, 1);
+        let udon_id = item_repository.add_item(udon, STAFF).await.unwrap();
+        let udon = item_repository.get_item(udon_id).await.unwrap().unwrap();
+        assert_eq!(udon.time_to_prepare, 5);
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_reset() {
+        let item_repository = init_test_db().await;
+        let repository = PgAdminRepository {
+            connection_pool: item_repository.connection_pool.clone(),
+        };
+        let item = InsertItemDao::new("sushi".to_string(), 1, 5, 1);
+        item_repository.add_item(item, STAFF).await.unwrap();
+        assert_eq!(repository.database_name().await.unwrap(), "restaurant_test");
+
+        match repository.reset("restaurant").await {
+            Err(DbError::NotConfirmed(name)) => assert_eq!(name, "restaurant_test"),
+            result => panic!("{:?}", result),
+        }
+        assert_eq!(item_repository.get_all_items().await.unwrap().len(), 1);
+
+        repository.reset("restaurant_test").await.unwrap();
+        assert!(item_repository.get_all_items().await.unwrap().is_empty());
+    }
+}
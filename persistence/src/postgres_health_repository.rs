@@ -0,0 +1,63 @@
+use async_trait::async_trait;
+use sqlx::{Pool, Postgres};
+
+use crate::error::DbError;
+use crate::health_repository::HealthRepository;
+use crate::MIGRATOR;
+
+#[derive(Clone)]
+pub struct PgHealthRepository {
+    pub connection_pool: Pool<Postgres>,
+}
+
+#[async_trait]
+impl HealthRepository for PgHealthRepository {
+    async fn ping(&self) -> Result<(), DbError> {
+        let result = sqlx::query("SELECT 1").execute(&self.connection_pool).await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn pending_migrations(&self) -> Result<Vec<String>, DbError> {
+        let result =
+            sqlx::query_scalar::<_, i64>("SELECT version FROM _sqlx_migrations WHERE success")
+                .fetch_all(&self.connection_pool)
+                .await;
+        let applied = match result {
+            Ok(applied) => applied,
+            // The table of sqlx only exists once the first migration was run.
+            Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("42P01") => vec![],
+            Err(e) => return Err(DbError::from_sqlx_error(e)),
+        };
+
+        Ok(MIGRATOR
+            .iter()
+            .filter(|migration| !migration.migration_type.is_down_migration())
+            .filter(|migration| !applied.contains(&migration.version))
+            .map(|migration| format!("{} {}", migration.version, migration.description))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::init_test_db;
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_health() {
+        let connection_pool = init_test_db().await.connection_pool;
+        let repository = PgHealthRepository {
+            connection_pool: connection_pool.clone(),
+        };
+        assert!(repository.ping().await.is_ok());
+        assert!(repository.pending_migrations().await.unwrap().is_empty());
+
+        connection_pool.close().await;
+        assert!(repository.ping().await.is_err());
+    }
+}
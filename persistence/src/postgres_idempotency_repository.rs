@@ -0,0 +1,317 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use sqlx::{Pool, Postgres};
+
+use crate::dao::{IdempotencyKeyDao, StoredResponseDao};
+use crate::error::DbError;
+use crate::idempotency_repository::{IdempotencyRepository, KeyReservation};
+
+#[derive(Clone)]
+pub struct PgIdempotencyRepository {
+    pub connection_pool: Pool<Postgres>,
+}
+
+#[async_trait]
+impl IdempotencyRepository for PgIdempotencyRepository {
+    async fn reserve_key(
+        &self,
+        owner: &str,
+        key: &str,
+        request_hash: &str,
+        ttl: Duration,
+        lock_timeout: Duration,
+    ) -> Result<KeyReservation, DbError> {
+        sqlx::query(
+            r#"
+            DELETE FROM tbl_idempotency_key
+            WHERE owner = $1 AND key = $2
+                AND created_at < LOCALTIMESTAMP - make_interval(secs => $3)
+            "#,
+        )
+        .bind(owner)
+        .bind(key)
+        .bind(ttl.as_secs_f64())
+        .execute(&self.connection_pool)
+        .await
+        .map_err(DbError::from_sqlx_error)?;
+
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO tbl_idempotency_key (owner, key, request_hash, locked_until)
+            VALUES ($1, $2, $3, LOCALTIMESTAMP + make_interval(secs => $4))
+            ON CONFLICT (owner, key) DO NOTHING
+            "#,
+        )
+        .bind(owner)
+        .bind(key)
+        .bind(request_hash)
+        .bind(lock_timeout.as_secs_f64())
+        .execute(&self.connection_pool)
+        .await
+        .map_err(DbError::from_sqlx_error)?;
+        if inserted.rows_affected() == 1 {
+            return Ok(KeyReservation::Reserved);
+        }
+
+        // Takes over a reservation of the same request which was abandoned.
+        let taken_over = sqlx::query(
+            r#"
+            UPDATE tbl_idempotency_key
+            SET locked_until = LOCALTIMESTAMP + make_interval(secs => $4)
+            WHERE owner = $1 AND key = $2 AND request_hash = $3 AND status_code IS NULL
+                AND (locked_until IS NULL OR locked_until <= LOCALTIMESTAMP)
+            "#,
+        )
+        .bind(owner)
+        .bind(key)
+        .bind(request_hash)
+        .bind(lock_timeout.as_secs_f64())
+        .execute(&self.connection_pool)
+        .await
+        .map_err(DbError::from_sqlx_error)?;
+        if taken_over.rows_affected() == 1 {
+            return Ok(KeyReservation::Reserved);
+        }
+
+        let existing = sqlx::query_as::<_, IdempotencyKeyDao>(
+            r#"
+            SELECT *
+            FROM tbl_idempotency_key
+            WHERE owner = $1 AND key = $2
+            "#,
+        )
+        .bind(owner)
+        .bind(key)
+        .fetch_optional(&self.connection_pool)
+        .await
+        .map_err(DbError::from_sqlx_error)?;
+
+        match existing {
+            // Released between the insert and the select, let the client retry.
+            None => Ok(KeyReservation::InProgress),
+            Some(existing) if existing.request_hash != request_hash => Ok(KeyReservation::Mismatch),
+            Some(IdempotencyKeyDao {
+                status_code: Some(status_code),
+                content_type,
+                response_body,
+                ..
+            }) => Ok(KeyReservation::Completed(StoredResponseDao::new(
+                status_code,
+                content_type,
+                response_body.unwrap_or_default(),
+            ))),
+            Some(_) => Ok(KeyReservation::InProgress),
+        }
+    }
+
+    async fn complete_key(
+        &self,
+        owner: &str,
+        key: &str,
+        response: StoredResponseDao,
+    ) -> Result<(), DbError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE tbl_idempotency_key
+            SET status_code = $3, content_type = $4, response_body = $5, locked_until = NULL
+            WHERE owner = $1 AND key = $2
+            "#,
+        )
+        .bind(owner)
+        .bind(key)
+        .bind(response.status_code)
+        .bind(response.content_type)
+        .bind(response.response_body)
+        .execute(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn release_key(&self, owner: &str, key: &str) -> Result<(), DbError> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM tbl_idempotency_key
+            WHERE owner = $1 AND key = $2 AND status_code IS NULL
+            "#,
+        )
+        .bind(owner)
+        .bind(key)
+        .execute(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn purge_expired_keys(&self, ttl: Duration) -> Result<u64, DbError> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM tbl_idempotency_key
+            WHERE created_at < LOCALTIMESTAMP - make_interval(secs => $1)
+            "#,
+        )
+        .bind(ttl.as_secs_f64())
+        .execute(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(result) => Ok(result.rows_affected()),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::init_test_db;
+
+    const TTL: Duration = Duration::from_secs(60);
+    const LOCK: Duration = Duration::from_secs(30);
+    const OWNER: &str = "tablet-1";
+
+    async fn init_test_repository() -> PgIdempotencyRepository {
+        let connection_pool = init_test_db().await.connection_pool;
+        sqlx::query("DELETE FROM tbl_idempotency_key")
+            .execute(&connection_pool)
+            .await
+            .unwrap();
+        PgIdempotencyRepository { connection_pool }
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_reserve_complete_and_replay_key() {
+        let repository = init_test_repository().await;
+
+        let reservation = repository
+            .reserve_key(OWNER, "key-1", "hash", TTL, LOCK)
+            .await
+            .unwrap();
+        assert!(matches!(reservation, KeyReservation::Reserved));
+
+        let reservation = repository
+            .reserve_key(OWNER, "key-1", "hash", TTL, LOCK)
+            .await
+            .unwrap();
+        assert!(matches!(reservation, KeyReservation::InProgress));
+
+        let response =
+            StoredResponseDao::new(200, Some("application/json".to_string()), b"{}".to_vec());
+        repository
+            .complete_key(OWNER, "key-1", response)
+            .await
+            .unwrap();
+
+        let reservation = repository
+            .reserve_key(OWNER, "key-1", "hash", TTL, LOCK)
+            .await
+            .unwrap();
+        match reservation {
+            KeyReservation::Completed(stored) => {
+                assert_eq!(stored.status_code, 200);
+                assert_eq!(stored.response_body, b"{}".to_vec());
+            }
+            other => panic!("unexpected reservation {:?}", other),
+        }
+
+        let reservation = repository
+            .reserve_key(OWNER, "key-1", "other-hash", TTL, LOCK)
+            .await
+            .unwrap();
+        assert!(matches!(reservation, KeyReservation::Mismatch));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_release_and_expire_key() {
+        let repository = init_test_repository().await;
+
+        repository
+            .reserve_key(OWNER, "key-2", "hash", TTL, LOCK)
+            .await
+            .unwrap();
+        repository.release_key(OWNER, "key-2").await.unwrap();
+        let reservation = repository
+            .reserve_key(OWNER, "key-2", "hash", TTL, LOCK)
+            .await
+            .unwrap();
+        assert!(matches!(reservation, KeyReservation::Reserved));
+
+        let response = StoredResponseDao::new(200, None, Vec::new());
+        repository
+            .complete_key(OWNER, "key-2", response)
+            .await
+            .unwrap();
+        let reservation = repository
+            .reserve_key(OWNER, "key-2", "other-hash", Duration::ZERO, LOCK)
+            .await
+            .unwrap();
+        assert!(matches!(reservation, KeyReservation::Reserved));
+
+        assert_eq!(repository.purge_expired_keys(TTL).await.unwrap(), 0);
+        assert_eq!(
+            repository.purge_expired_keys(Duration::ZERO).await.unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_abandoned_and_scoped_key() {
+        let repository = init_test_repository().await;
+
+        // The first request was dropped without completing or releasing its key.
+        let lock = Duration::from_millis(100);
+        let reservation = repository
+            .reserve_key(OWNER, "key-3", "hash", TTL, lock)
+            .await
+            .unwrap();
+        assert!(matches!(reservation, KeyReservation::Reserved));
+        let reservation = repository
+            .reserve_key(OWNER, "key-3", "hash", TTL, lock)
+            .await
+            .unwrap();
+        assert!(matches!(reservation, KeyReservation::InProgress));
+        tokio::time::sleep(lock).await;
+        let reservation = repository
+            .reserve_key(OWNER, "key-3", "other-hash", TTL, lock)
+            .await
+            .unwrap();
+        assert!(matches!(reservation, KeyReservation::Mismatch));
+        let reservation = repository
+            .reserve_key(OWNER, "key-3", "hash", TTL, LOCK)
+            .await
+            .unwrap();
+        assert!(matches!(reservation, KeyReservation::Reserved));
+        let reservation = repository
+            .reserve_key(OWNER, "key-3", "hash", TTL, LOCK)
+            .await
+            .unwrap();
+        assert!(matches!(reservation, KeyReservation::InProgress));
+
+        // Other devices use the same key independently.
+        let reservation = repository
+            .reserve_key("tablet-2", "key-3", "other-hash", TTL, LOCK)
+            .await
+            .unwrap();
+        assert!(matches!(reservation, KeyReservation::Reserved));
+        let response = StoredResponseDao::new(201, None, Vec::new());
+        repository
+            .complete_key("tablet-2", "key-3", response)
+            .await
+            .unwrap();
+        let reservation = repository
+            .reserve_key(OWNER, "key-3", "hash", TTL, LOCK)
+            .await
+            .unwrap();
+        assert!(matches!(reservation, KeyReservation::InProgress));
+    }
+}
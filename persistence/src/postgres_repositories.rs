@@ -1,26 +1,127 @@
-use crate::{postgres_item_repository::PgItemRepository, repositories::Repositories};
+use crate::{
+    cached_item_repository::{CacheConfig, CachedItemRepository},
+    error::DbError,
+    postgres_health_repository::PgHealthRepository,
+    postgres_idempotency_repository::PgIdempotencyRepository,
+    postgres_item_repository::PgItemRepository,
+    postgres_staff_repository::PgStaffRepository,
+    replicated_item_repository::{ReplicaConfig, ReplicatedItemRepository},
+    repositories::Repositories,
+    resilient_item_repository::{ResilienceConfig, ResilientItemRepository},
+    retry::ConnectOptions,
+};
+use sqlx::{Pool, Postgres};
 
 #[derive(Clone)]
 pub struct PgRepositories {
-    pub item_repository: PgItemRepository,
+    pub item_repository: CachedItemRepository<ResilientItemRepository<ReplicatedItemRepository>>,
+    pub idempotency_repository: PgIdempotencyRepository,
+    pub staff_repository: PgStaffRepository,
+    pub health_repository: PgHealthRepository,
 }
 
 impl Repositories for PgRepositories {
-    type ItemRepository = PgItemRepository;
+    type ItemRepository = CachedItemRepository<ResilientItemRepository<ReplicatedItemRepository>>;
+    type IdempotencyRepository = PgIdempotencyRepository;
+    type StaffRepository = PgStaffRepository;
+    type HealthRepository = PgHealthRepository;
 
     fn item_repository(&self) -> &Self::ItemRepository {
         &self.item_repository
     }
+
+    fn idempotency_repository(&self) -> &Self::IdempotencyRepository {
+        &self.idempotency_repository
+    }
+
+    fn staff_repository(&self) -> &Self::StaffRepository {
+        &self.staff_repository
+    }
+
+    fn health_repository(&self) -> &Self::HealthRepository {
+        &self.health_repository
+    }
 }
 
 impl PgRepositories {
-    pub async fn init_prod() -> PgRepositories {
-        let item_repository = PgItemRepository::init_prod().await;
-        PgRepositories { item_repository }
+    /// Creates all repositories on the connection pool of `item_repository`.
+    pub fn new(item_repository: PgItemRepository) -> PgRepositories {
+        PgRepositories::with_config(
+            item_repository,
+            ResilienceConfig::default(),
+            CacheConfig::default(),
+        )
+    }
+
+    /// Creates all repositories on the connection pool of `item_repository`, retrying its
+    /// queries as configured by `resilience` and caching their results as configured by
+    /// `cache`.
+    pub fn with_config(
+        item_repository: PgItemRepository,
+        resilience: ResilienceConfig,
+        cache: CacheConfig,
+    ) -> PgRepositories {
+        let connection_pool = item_repository.connection_pool.clone();
+        let item_repository = ReplicatedItemRepository::new(item_repository);
+        let item_repository = ResilientItemRepository::new(item_repository, resilience);
+        PgRepositories {
+            item_repository: CachedItemRepository::new(item_repository, cache),
+            idempotency_repository: PgIdempotencyRepository {
+                connection_pool: connection_pool.clone(),
+            },
+            staff_repository: PgStaffRepository {
+                connection_pool: connection_pool.clone(),
+            },
+            health_repository: PgHealthRepository { connection_pool },
+        }
+    }
+
+    /// Sends lists of items and reports to `replica`, as long as it keeps up with the
+    /// primary.
+    pub fn with_read_replica(
+        mut self,
+        replica: PgItemRepository,
+        config: ReplicaConfig,
+    ) -> PgRepositories {
+        let replicated = &mut self.item_repository.inner.inner;
+        *replicated = replicated.clone().with_replica(replica, config);
+        self
+    }
+
+    /// Routing of reads between the primary and the read replica.
+    pub fn replicated_item_repository(&self) -> &ReplicatedItemRepository {
+        &self.item_repository.inner.inner
+    }
+
+    pub async fn init_prod(
+        options: &ConnectOptions,
+        resilience: ResilienceConfig,
+        cache: CacheConfig,
+    ) -> Result<PgRepositories, DbError> {
+        let item_repository = PgItemRepository::init_prod(options).await?;
+        Ok(PgRepositories::with_config(
+            item_repository,
+            resilience,
+            cache,
+        ))
     }
 
     pub async fn init_test() -> PgRepositories {
         let item_repository = PgItemRepository::init_test().await;
-        PgRepositories { item_repository }
+        PgRepositories::new(item_repository)
+    }
+
+    /// Closes the connections of all repositories, waiting for the ones in use to be
+    /// returned first.
+    pub async fn close(&self) {
+        if let Some(replica_pool) = self.replicated_item_repository().replica_pool() {
+            replica_pool.close().await;
+        }
+        self.connection_pool().close().await;
+    }
+
+    /// Pool of connections shared by all repositories.
+    pub fn connection_pool(&self) -> &Pool<Postgres> {
+        &self.health_repository.connection_pool
     }
 }
@@ -0,0 +1,462 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use sqlx::{Pool, Postgres};
+
+use crate::dao::{InsertStaffDao, StaffDao};
+use crate::error::DbError;
+use crate::staff_repository::StaffRepository;
+
+#[derive(Clone)]
+pub struct PgStaffRepository {
+    pub connection_pool: Pool<Postgres>,
+}
+
+#[async_trait]
+impl StaffRepository for PgStaffRepository {
+    async fn add_staff(&self, staff: InsertStaffDao) -> Result<i32, DbError> {
+        let result = sqlx::query_scalar::<_, i32>(
+            r#"
+            INSERT INTO tbl_staff (name, role, pin_hash)
+            VALUES ($1, $2, $3)
+            RETURNING id
+            "#,
+        )
+        .bind(staff.name)
+        .bind(staff.role)
+        .bind(staff.pin_hash)
+        .fetch_one(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(id) => Ok(id),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn get_staff(&self, staff_id: i32) -> Result<Option<StaffDao>, DbError> {
+        let result = sqlx::query_as::<_, StaffDao>(
+            r#"
+            SELECT *
+            FROM tbl_staff
+            WHERE id = $1
+            "#,
+        )
+        .bind(staff_id)
+        .fetch_optional(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(staff) => Ok(staff),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn login_locked_for(&self, staff_id: i32) -> Result<Option<Duration>, DbError> {
+        let result = sqlx::query_scalar::<_, f64>(
+            r#"
+            SELECT EXTRACT(EPOCH FROM login_locked_until - LOCALTIMESTAMP)::FLOAT8
+            FROM tbl_staff
+            WHERE id = $1 AND login_locked_until > LOCALTIMESTAMP
+            "#,
+        )
+        .bind(staff_id)
+        .fetch_optional(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(seconds) => Ok(seconds.map(Duration::from_secs_f64)),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn record_failed_login(
+        &self,
+        staff_id: i32,
+        max_failed_logins: u32,
+        lockout: Duration,
+    ) -> Result<(), DbError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE tbl_staff
+            SET failed_logins = CASE WHEN failed_logins + 1 >= $2 THEN 0 ELSE failed_logins + 1 END,
+                login_locked_until = CASE
+                    WHEN failed_logins + 1 >= $2 THEN LOCALTIMESTAMP + make_interval(secs => $3)
+                    ELSE login_locked_until
+                END
+            WHERE id = $1
+            "#,
+        )
+        .bind(staff_id)
+        .bind(max_failed_logins as i32)
+        .bind(lockout.as_secs_f64())
+        .execute(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn reset_failed_logins(&self, staff_id: i32) -> Result<(), DbError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE tbl_staff
+            SET failed_logins = 0
+            WHERE id = $1 AND failed_logins > 0
+            "#,
+        )
+        .bind(staff_id)
+        .execute(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn create_session(
+        &self,
+        staff_id: i32,
+        token_hash: &str,
+        lifetime: Duration,
+    ) -> Result<(), DbError> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO tbl_session (token_hash, staff_id, expires_at)
+            VALUES ($1, $2, LOCALTIMESTAMP + make_interval(secs => $3))
+            "#,
+        )
+        .bind(token_hash)
+        .bind(staff_id)
+        .bind(lifetime.as_secs_f64())
+        .execute(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn touch_session(
+        &self,
+        token_hash: &str,
+        idle_timeout: Duration,
+    ) -> Result<Option<StaffDao>, DbError> {
+        let result = sqlx::query_as::<_, StaffDao>(
+            r#"
+            UPDATE tbl_session
+            SET last_seen_at = LOCALTIMESTAMP
+            FROM tbl_staff
+            WHERE tbl_session.token_hash = $1
+                AND tbl_staff.id = tbl_session.staff_id
+                AND tbl_staff.active
+                AND tbl_session.expires_at > LOCALTIMESTAMP
+                AND tbl_session.last_seen_at > LOCALTIMESTAMP - make_interval(secs => $2)
+            RETURNING tbl_staff.*
+            "#,
+        )
+        .bind(token_hash)
+        .bind(idle_timeout.as_secs_f64())
+        .fetch_optional(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(staff) => Ok(staff),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn end_session(&self, token_hash: &str) -> Result<bool, DbError> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM tbl_session
+            WHERE token_hash = $1
+            "#,
+        )
+        .bind(token_hash)
+        .execute(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(result) => Ok(result.rows_affected() == 1),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn purge_expired_sessions(&self, idle_timeout: Duration) -> Result<u64, DbError> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM tbl_session
+            WHERE expires_at <= LOCALTIMESTAMP
+                OR last_seen_at <= LOCALTIMESTAMP - make_interval(secs => $1)
+            "#,
+        )
+        .bind(idle_timeout.as_secs_f64())
+        .execute(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(result) => Ok(result.rows_affected()),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn set_table_waiters(&self, table_id: i32, staff_ids: &[i32]) -> Result<(), DbError> {
+        let mut tx = self
+            .connection_pool
+            .begin()
+            .await
+            .map_err(DbError::from_sqlx_error)?;
+        sqlx::query(
+            r#"
+            DELETE FROM tbl_table_assignment
+            WHERE table_id = $1
+            "#,
+        )
+        .bind(table_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(DbError::from_sqlx_error)?;
+        sqlx::query(
+            r#"
+            INSERT INTO tbl_table_assignment (table_id, staff_id)
+            SELECT DISTINCT $1, staff_id
+            FROM UNNEST($2::INT[]) AS staff_id
+            "#,
+        )
+        .bind(table_id)
+        .bind(staff_ids)
+        .execute(&mut *tx)
+        .await
+        .map_err(DbError::from_sqlx_error)?;
+
+        match tx.commit().await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn get_table_waiters(&self, table_id: i32) -> Result<Vec<i32>, DbError> {
+        let result = sqlx::query_scalar::<_, i32>(
+            r#"
+            SELECT staff_id
+            FROM tbl_table_assignment
+            WHERE table_id = $1
+            ORDER BY staff_id
+            "#,
+        )
+        .bind(table_id)
+        .fetch_all(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(staff_ids) => Ok(staff_ids),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+
+    async fn get_assigned_tables(&self, staff_id: i32) -> Result<Vec<i32>, DbError> {
+        let result = sqlx::query_scalar::<_, i32>(
+            r#"
+            SELECT table_id
+            FROM tbl_table_assignment
+            WHERE staff_id = $1
+            ORDER BY table_id
+            "#,
+        )
+        .bind(staff_id)
+        .fetch_all(&self.connection_pool)
+        .await;
+
+        match result {
+            Ok(table_ids) => Ok(table_ids),
+            Err(e) => Err(DbError::from_sqlx_error(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{init_test_db, reset_test_db};
+
+    const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+    const LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_sessions() {
+        let connection_pool = init_test_db().await.connection_pool;
+        let repository = PgStaffRepository {
+            connection_pool: connection_pool.clone(),
+        };
+        let staff_id = repository
+            .add_staff(InsertStaffDao::new(
+                "Anna".to_string(),
+                "waiter".to_string(),
+                "hash".to_string(),
+            ))
+            .await
+            .unwrap();
+        let staff = repository.get_staff(staff_id).await.unwrap().unwrap();
+        assert_eq!(staff.name, "Anna");
+        assert!(staff.active);
+
+        repository
+            .create_session(staff_id, "token-1", LIFETIME)
+            .await
+            .unwrap();
+        let staff = repository
+            .touch_session("token-1", IDLE_TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(staff.map(|staff| staff.id), Some(staff_id));
+        assert!(repository
+            .touch_session("token-2", IDLE_TIMEOUT)
+            .await
+            .unwrap()
+            .is_none());
+
+        // A session which was not used for longer than the timeout is logged out.
+        assert!(repository
+            .touch_session("token-1", Duration::ZERO)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            repository
+                .purge_expired_sessions(Duration::ZERO)
+                .await
+                .unwrap(),
+            1
+        );
+
+        repository
+            .create_session(staff_id, "token-3", Duration::ZERO)
+            .await
+            .unwrap();
+        assert!(repository
+            .touch_session("token-3", IDLE_TIMEOUT)
+            .await
+            .unwrap()
+            .is_none());
+
+        repository
+            .create_session(staff_id, "token-4", LIFETIME)
+            .await
+            .unwrap();
+        assert!(repository.end_session("token-4").await.unwrap());
+        assert!(!repository.end_session("token-4").await.unwrap());
+
+        reset_test_db(connection_pool).await;
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_login_lockout() {
+        let connection_pool = init_test_db().await.connection_pool;
+        let repository = PgStaffRepository {
+            connection_pool: connection_pool.clone(),
+        };
+        let staff_id = repository
+            .add_staff(InsertStaffDao::new(
+                "Anna".to_string(),
+                "waiter".to_string(),
+                "hash".to_string(),
+            ))
+            .await
+            .unwrap();
+        let lockout = Duration::from_secs(60);
+
+        for _ in 0..2 {
+            repository
+                .record_failed_login(staff_id, 3, lockout)
+                .await
+                .unwrap();
+        }
+        // A successful login starts the count again.
+        repository.reset_failed_logins(staff_id).await.unwrap();
+        for _ in 0..2 {
+            repository
+                .record_failed_login(staff_id, 3, lockout)
+                .await
+                .unwrap();
+        }
+        assert!(repository
+            .login_locked_for(staff_id)
+            .await
+            .unwrap()
+            .is_none());
+
+        repository
+            .record_failed_login(staff_id, 3, lockout)
+            .await
+            .unwrap();
+        let locked_for = repository
+            .login_locked_for(staff_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(locked_for > Duration::from_secs(50) && locked_for <= lockout);
+
+        repository
+            .record_failed_login(staff_id, 1, Duration::ZERO)
+            .await
+            .unwrap();
+        assert!(repository
+            .login_locked_for(staff_id)
+            .await
+            .unwrap()
+            .is_none());
+
+        reset_test_db(connection_pool).await;
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_table_assignments() {
+        let connection_pool = init_test_db().await.connection_pool;
+        let repository = PgStaffRepository {
+            connection_pool: connection_pool.clone(),
+        };
+        let mut waiters = Vec::new();
+        for name in ["Anna", "Ben"] {
+            let staff_id = repository
+                .add_staff(InsertStaffDao::new(
+                    name.to_string(),
+                    "waiter".to_string(),
+                    "hash".to_string(),
+                ))
+                .await
+                .unwrap();
+            waiters.push(staff_id);
+        }
+        let (anna, ben) = (waiters[0], waiters[1]);
+
+        repository.set_table_waiters(1, &[anna, ben]).await.unwrap();
+        repository.set_table_waiters(2, &[ben, ben]).await.unwrap();
+        assert_eq!(
+            repository.get_table_waiters(1).await.unwrap(),
+            vec![anna, ben]
+        );
+        assert_eq!(
+            repository.get_assigned_tables(ben).await.unwrap(),
+            vec![1, 2]
+        );
+
+        repository.set_table_waiters(1, &[anna]).await.unwrap();
+        assert_eq!(repository.get_table_waiters(1).await.unwrap(), vec![anna]);
+        assert_eq!(repository.get_assigned_tables(ben).await.unwrap(), vec![2]);
+
+        repository.set_table_waiters(2, &[]).await.unwrap();
+        assert!(repository.get_table_waiters(2).await.unwrap().is_empty());
+        assert!(repository.set_table_waiters(3, &[ben + 100]).await.is_err());
+        assert!(repository.get_table_waiters(3).await.unwrap().is_empty());
+
+        reset_test_db(connection_pool).await;
+    }
+}
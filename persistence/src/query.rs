@@ -0,0 +1,183 @@
+use chrono::NaiveDateTime;
+use derive_new::new;
+
+use crate::dao::ItemDao;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ItemSortField {
+    #[default]
+    TableId,
+    CreatedAt,
+    Name,
+    TimeToPrepare,
+}
+
+impl ItemSortField {
+    pub fn column(&self) -> &'static str {
+        match self {
+            ItemSortField::TableId => "table_id",
+            ItemSortField::CreatedAt => "created_at",
+            ItemSortField::Name => "name",
+            ItemSortField::TimeToPrepare => "time_to_prepare",
+        }
+    }
+
+    pub fn cursor_value(&self, item: &ItemDao) -> ItemCursorValue {
+        match self {
+            ItemSortField::TableId => ItemCursorValue::TableId(item.table_id),
+            ItemSortField::CreatedAt => ItemCursorValue::CreatedAt(item.created_at),
+            ItemSortField::Name => ItemCursorValue::Name(item.name.clone()),
+            ItemSortField::TimeToPrepare => ItemCursorValue::TimeToPrepare(item.time_to_prepare),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortDirection {
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            SortDirection::Asc => "ASC",
+            SortDirection::Desc => "DESC",
+        }
+    }
+
+    fn comparison(&self) -> &'static str {
+        match self {
+            SortDirection::Asc => ">",
+            SortDirection::Desc => "<",
+        }
+    }
+}
+
+/// Value of the sort column of the last item of a page.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ItemCursorValue {
+    TableId(i32),
+    CreatedAt(NaiveDateTime),
+    Name(String),
+    TimeToPrepare(i32),
+}
+
+impl ItemCursorValue {
+    pub fn sort_field(&self) -> ItemSortField {
+        match self {
+            ItemCursorValue::TableId(_) => ItemSortField::TableId,
+            ItemCursorValue::CreatedAt(_) => ItemSortField::CreatedAt,
+            ItemCursorValue::Name(_) => ItemSortField::Name,
+            ItemCursorValue::TimeToPrepare(_) => ItemSortField::TimeToPrepare,
+        }
+    }
+}
+
+/// Position after which the next page starts. The item id breaks ties between items
+/// with the same value of the sort column.
+#[derive(new, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ItemCursor {
+    pub value: ItemCursorValue,
+    pub id: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ItemFilter {
+    pub name_contains: Option<String>,
+    pub table_ids: Option<Vec<i32>>,
+    pub table_id_from: Option<i32>,
+    pub table_id_to: Option<i32>,
+    pub created_after: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ItemQuery {
+    pub filter: ItemFilter,
+    pub sort: ItemSortField,
+    pub direction: SortDirection,
+    pub limit: i64,
+    pub cursor: Option<ItemCursor>,
+}
+
+impl ItemQuery {
+    pub fn sql(&self) -> sqlx::QueryBuilder<'_, sqlx::Postgres> {
+        let mut builder = sqlx::QueryBuilder::new("SELECT * FROM tbl_item WHERE TRUE");
+
+        if let Some(name) = &self.filter.name_contains {
+            builder
+                .push(" AND strpos(lower(name), lower(")
+                .push_bind(name)
+                .push(")) > 0");
+        }
+        if let Some(table_ids) = &self.filter.table_ids {
+            builder
+                .push(" AND table_id = ANY(")
+                .push_bind(table_ids)
+                .push(")");
+        }
+        if let Some(table_id_from) = self.filter.table_id_from {
+            builder.push(" AND table_id >= ").push_bind(table_id_from);
+        }
+        if let Some(table_id_to) = self.filter.table_id_to {
+            builder.push(" AND table_id <= ").push_bind(table_id_to);
+        }
+        if let Some(created_after) = self.filter.created_after {
+            builder.push(" AND created_at > ").push_bind(created_after);
+        }
+        if let Some(cursor) = &self.cursor {
+            builder.push(format!(
+                " AND ({}, id) {} (",
+                self.sort.column(),
+                self.direction.comparison()
+            ));
+            match &cursor.value {
+                ItemCursorValue::TableId(value) => builder.push_bind(*value),
+                ItemCursorValue::CreatedAt(value) => builder.push_bind(*value),
+                ItemCursorValue::Name(value) => builder.push_bind(value),
+                ItemCursorValue::TimeToPrepare(value) => builder.push_bind(*value),
+            };
+            builder.push(", ").push_bind(cursor.id).push(")");
+        }
+
+        builder.push(format!(
+            " ORDER BY {column} {direction}, id {direction} LIMIT ",
+            column = self.sort.column(),
+            direction = self.direction.keyword()
+        ));
+        // One extra row tells whether there is a next page.
+        builder.push_bind(self.limit + 1);
+        builder
+    }
+
+    /// The table the query is limited to, if it is limited to a single one.
+    pub fn single_table(&self) -> Option<i32> {
+        match (self.filter.table_id_from, self.filter.table_id_to) {
+            (Some(from), Some(to)) if from == to => Some(from),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ItemPage {
+    pub items: Vec<ItemDao>,
+    pub next_cursor: Option<ItemCursor>,
+}
+
+impl ItemPage {
+    /// Builds a page from rows fetched with `limit + 1`.
+    pub fn from_rows(mut rows: Vec<ItemDao>, query: &ItemQuery) -> ItemPage {
+        let has_next = rows.len() as i64 > query.limit;
+        rows.truncate(query.limit.max(0) as usize);
+        let next_cursor = match rows.last() {
+            Some(last) if has_next => Some(ItemCursor::new(query.sort.cursor_value(last), last.id)),
+            _ => None,
+        };
+        ItemPage {
+            items: rows,
+            next_cursor,
+        }
+    }
+}
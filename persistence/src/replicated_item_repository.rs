@@ -0,0 +1,470 @@
+use async_trait::async_trait;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Pool, Postgres};
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::batch::{BatchMode, BatchOperation, BatchResult};
+use crate::dao::{InsertItemDao, ItemChangeDao, ItemDao, TableStatsDao, UpdateItemDao};
+use crate::error::DbError;
+use crate::item_repository::ItemRepository;
+use crate::postgres_item_repository::PgItemRepository;
+use crate::query::{ItemPage, ItemQuery};
+
+/// Position in the write-ahead log of the primary, written like Postgres does, e.g.
+/// `16/B374D848`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Lsn(pub u64);
+
+impl fmt::Display for Lsn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:X}/{:X}", self.0 >> 32, self.0 & 0xFFFF_FFFF)
+    }
+}
+
+impl FromStr for Lsn {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid log position {}", value);
+        let (high, low) = value.split_once('/').ok_or_else(invalid)?;
+        let high = u32::from_str_radix(high, 16).map_err(|_| invalid())?;
+        let low = u32::from_str_radix(low, 16).map_err(|_| invalid())?;
+        Ok(Lsn(u64::from(high) << 32 | u64::from(low)))
+    }
+}
+
+tokio::task_local! {
+    static READ_SESSION: ReadSession;
+}
+
+/// Last write a client has seen. Reads in the session only go to the replica once it
+/// replayed that write, so that clients read their own writes.
+#[derive(Debug, Clone, Default)]
+pub struct ReadSession(Arc<AtomicU64>);
+
+impl ReadSession {
+    /// Starts a session of a client which has seen the writes up to `last_write`.
+    pub fn new(last_write: Option<Lsn>) -> ReadSession {
+        ReadSession(Arc::new(AtomicU64::new(last_write.unwrap_or_default().0)))
+    }
+
+    /// Runs `future` in the session, its writes move the session forward.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        READ_SESSION.scope(self, future).await
+    }
+
+    pub fn last_write(&self) -> Option<Lsn> {
+        match self.0.load(Ordering::SeqCst) {
+            0 => None,
+            position => Some(Lsn(position)),
+        }
+    }
+
+    fn observe(&self, lsn: Lsn) {
+        self.0.fetch_max(lsn.0, Ordering::SeqCst);
+    }
+
+    fn current() -> Option<ReadSession> {
+        READ_SESSION.try_with(ReadSession::clone).ok()
+    }
+}
+
+/// When reads are sent to the read replica.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplicaConfig {
+    /// Reads go to the primary while the replica is further behind than this.
+    pub max_lag: Duration,
+    /// How long the checked lag of the replica is relied on.
+    pub check_interval: Duration,
+    /// How long reads go to the primary after the replica could not be reached.
+    pub retry_after: Duration,
+    /// Time to wait for a connection of the replica before falling back to the primary.
+    pub acquire_timeout: Duration,
+}
+
+impl Default for ReplicaConfig {
+    fn default() -> Self {
+        ReplicaConfig {
+            max_lag: Duration::from_secs(5),
+            check_interval: Duration::from_secs(1),
+            retry_after: Duration::from_secs(10),
+            acquire_timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Reads served by the replica and reads which fell back to the primary since the start.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplicaStats {
+    pub replica_reads: u64,
+    pub primary_fallbacks: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ReplicaState {
+    Unchecked,
+    Checked {
+        at: Instant,
+        replayed: Lsn,
+        lag: Duration,
+    },
+    Down {
+        until: Instant,
+    },
+}
+
+#[derive(Clone)]
+struct Replica {
+    repository: PgItemRepository,
+    config: ReplicaConfig,
+    state: Arc<Mutex<ReplicaState>>,
+    reads: Arc<AtomicU64>,
+    fallbacks: Arc<AtomicU64>,
+}
+
+impl Replica {
+    /// Replayed position and lag of the replica. A primary used as replica counts as
+    /// replica without lag.
+    async fn check(&self) -> Result<ReplicaState, DbError> {
+        let (replayed, lag): (i64, f64) = sqlx::query_as(
+            r#"
+            SELECT
+                (CASE WHEN pg_is_in_recovery() THEN pg_last_wal_replay_lsn()
+                    ELSE pg_current_wal_lsn() END - '0/0'::pg_lsn)::bigint,
+                CASE WHEN NOT pg_is_in_recovery()
+                        OR pg_last_wal_receive_lsn() = pg_last_wal_replay_lsn() THEN 0
+                    ELSE COALESCE(
+                        extract(epoch FROM now() - pg_last_xact_replay_timestamp()), 0)
+                END::float8
+            "#,
+        )
+        .fetch_one(&self.repository.connection_pool)
+        .await
+        .map_err(DbError::from_sqlx_error)?;
+        Ok(ReplicaState::Checked {
+            at: Instant::now(),
+            replayed: Lsn(replayed as u64),
+            lag: Duration::from_secs_f64(lag.max(0.0)),
+        })
+    }
+
+    fn mark_down(&self, error: &DbError) {
+        log::warn!(
+            "reading from the primary for {} s, the replica failed: {}",
+            self.config.retry_after.as_secs(),
+            error
+        );
+        *self.state.lock().unwrap() = ReplicaState::Down {
+            until: Instant::now() + self.config.retry_after,
+        };
+    }
+
+    /// Whether the replica is reachable, close enough to the primary and has replayed
+    /// the last write of the session. Checks it again when needed.
+    async fn is_usable(&self, last_write: Lsn) -> bool {
+        let now = Instant::now();
+        let state = *self.state.lock().unwrap();
+        let state = match state {
+            ReplicaState::Down { until } if now < until => return false,
+            ReplicaState::Checked { at, replayed, .. }
+                if now < at + self.config.check_interval && last_write <= replayed =>
+            {
+                state
+            }
+            _ => match self.check().await {
+                Ok(state) => {
+                    *self.state.lock().unwrap() = state;
+                    state
+                }
+                Err(e) => {
+                    self.mark_down(&e);
+                    return false;
+                }
+            },
+        };
+        match state {
+            ReplicaState::Checked { replayed, lag, .. } => {
+                lag <= self.config.max_lag && last_write <= replayed
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Item repository sending lists of items and reports to an optional read replica.
+/// Single items and tables, which staff act on, are always read from the primary, like
+/// all writes. Reads fall back to the primary while the replica is down, lags behind or
+/// has not replayed the last write of the current [`ReadSession`] yet.
+#[derive(Clone)]
+pub struct ReplicatedItemRepository {
+    pub primary: PgItemRepository,
+    replica: Option<Replica>,
+}
+
+impl ReplicatedItemRepository {
+    pub fn new(primary: PgItemRepository) -> ReplicatedItemRepository {
+        ReplicatedItemRepository {
+            primary,
+            replica: None,
+        }
+    }
+
+    pub fn with_replica(
+        self,
+        replica: PgItemRepository,
+        config: ReplicaConfig,
+    ) -> ReplicatedItemRepository {
+        ReplicatedItemRepository {
+            replica: Some(Replica {
+                repository: replica,
+                config,
+                state: Arc::new(Mutex::new(ReplicaState::Unchecked)),
+                reads: Arc::default(),
+                fallbacks: Arc::default(),
+            }),
+            ..self
+        }
+    }
+
+    /// Pool of the replica, which is connected to on first use so that the server starts
+    /// while the replica is down.
+    pub fn connect_replica(
+        connection_url: &str,
+        config: &ReplicaConfig,
+    ) -> Result<PgItemRepository, DbError> {
+        let connection_pool = PgPoolOptions::new()
+            .acquire_timeout(config.acquire_timeout)
+            .connect_lazy(connection_url)
+            .map_err(DbError::from_sqlx_error)?;
+        Ok(PgItemRepository { connection_pool })
+    }
+
+    pub fn replica_pool(&self) -> Option<&Pool<Postgres>> {
+        self.replica
+            .as_ref()
+            .map(|replica| &replica.repository.connection_pool)
+    }
+
+    pub fn stats(&self) -> ReplicaStats {
+        match &self.replica {
+            Some(replica) => ReplicaStats {
+                replica_reads: replica.reads.load(Ordering::Relaxed),
+                primary_fallbacks: replica.fallbacks.load(Ordering::Relaxed),
+            },
+            None => ReplicaStats::default(),
+        }
+    }
+
+    /// Runs `query` on the replica when it is usable, and on the primary otherwise or when
+    /// the replica fails to answer.
+    async fn read<'a, T, F, Fut>(&'a self, query: F) -> Result<T, DbError>
+    where
+        F: Fn(&'a PgItemRepository) -> Fut,
+        Fut: Future<Output = Result<T, DbError>>,
+    {
+        let Some(replica) = &self.replica else {
+            return query(&self.primary).await;
+        };
+        let last_write = ReadSession::current()
+            .and_then(|session| session.last_write())
+            .unwrap_or_default();
+        if replica.is_usable(last_write).await {
+            match query(&replica.repository).await {
+                Err(e) if e.is_connection_failure() => replica.mark_down(&e),
+                result => {
+                    replica.reads.fetch_add(1, Ordering::Relaxed);
+                    return result;
+                }
+            }
+        }
+        replica.fallbacks.fetch_add(1, Ordering::Relaxed);
+        query(&self.primary).await
+    }
+
+    /// Moves the current session past the write, which may have been committed even when
+    /// it failed.
+    async fn written<T>(&self, result: Result<T, DbError>) -> Result<T, DbError> {
+        let Some(session) = self.replica.as_ref().and(ReadSession::current()) else {
+            return result;
+        };
+        let position: Result<i64, _> =
+            sqlx::query_scalar("SELECT (pg_current_wal_lsn() - '0/0'::pg_lsn)::bigint")
+                .fetch_one(&self.primary.connection_pool)
+                .await;
+        match position {
+            Ok(position) => session.observe(Lsn(position as u64)),
+            Err(e) => log::warn!("failed to get the log position of a write: {}", e),
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl ItemRepository for ReplicatedItemRepository {
+    async fn add_item(&self, item: InsertItemDao, performed_by: &str) -> Result<i64, DbError> {
+        self.written(self.primary.add_item(item, performed_by).await)
+            .await
+    }
+
+    async fn get_item(&self, item_id: i64) -> Result<Option<ItemDao>, DbError> {
+        self.primary.get_item(item_id).await
+    }
+
+    async fn get_items_for_table(&self, table_id: i32) -> Result<Vec<ItemDao>, DbError> {
+        self.primary.get_items_for_table(table_id).await
+    }
+
+    async fn get_items_for_tables(&self, table_ids: &[i32]) -> Result<Vec<ItemDao>, DbError> {
+        self.read(|repository| repository.get_items_for_tables(table_ids))
+            .await
+    }
+
+    async fn get_all_items(&self) -> Result<Vec<ItemDao>, DbError> {
+        self.read(|repository| repository.get_all_items()).await
+    }
+
+    async fn query_items(&self, query: ItemQuery) -> Result<ItemPage, DbError> {
+        if query.single_table().is_some() {
+            return self.primary.query_items(query).await;
+        }
+        self.read(|repository| repository.query_items(query.clone()))
+            .await
+    }
+
+    async fn remove_item(
+        &self,
+        item_id: i64,
+        quantity: i32,
+        expected_version: Option<i32>,
+        performed_by: &str,
+    ) -> Result<Option<ItemDao>, DbError> {
+        let result = self
+            .primary
+            .remove_item(item_id, quantity, expected_version, performed_by)
+            .await;
+        self.written(result).await
+    }
+
+    async fn update_item(
+        &self,
+        item_id: i64,
+        update: UpdateItemDao,
+        expected_version: Option<i32>,
+        performed_by: &str,
+    ) -> Result<Option<ItemDao>, DbError> {
+        let result = self
+            .primary
+            .update_item(item_id, update, expected_version, performed_by)
+            .await;
+        self.written(result).await
+    }
+
+    async fn transfer_table(
+        &self,
+        from_table_id: i32,
+        to_table_id: i32,
+        performed_by: &str,
+    ) -> Result<Vec<ItemDao>, DbError> {
+        let result = self
+            .primary
+            .transfer_table(from_table_id, to_table_id, performed_by)
+            .await;
+        self.written(result).await
+    }
+
+    async fn execute_batch(
+        &self,
+        operations: Vec<BatchOperation>,
+        mode: BatchMode,
+        performed_by: &str,
+    ) -> Result<BatchResult, DbError> {
+        let result = self
+            .primary
+            .execute_batch(operations, mode, performed_by)
+            .await;
+        self.written(result).await
+    }
+
+    async fn get_item_changes(&self, item_id: i64) -> Result<Vec<ItemChangeDao>, DbError> {
+        self.read(|repository| repository.get_item_changes(item_id))
+            .await
+    }
+
+    async fn get_table_stats(&self) -> Result<Vec<TableStatsDao>, DbError> {
+        self.read(|repository| repository.get_table_stats()).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{init_test_db, reset_test_db};
+
+    #[test]
+    fn test_lsn() {
+        let lsn: Lsn = "16/B374D848".parse().unwrap();
+        assert_eq!(lsn, Lsn(0x16_B374_D848));
+        assert_eq!(lsn.to_string(), "16/B374D848");
+        assert_eq!(Lsn(0).to_string(), "0/0");
+        for invalid in ["", "16", "16/", "x/1", "1/2/3", "100000000/0"] {
+            assert!(invalid.parse::<Lsn>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_replica_routing() {
+        let primary = init_test_db().await;
+        // The test database stands in for the replica, it never lags behind.
+        let replica = PgItemRepository::init_test().await;
+        let repository = ReplicatedItemRepository::new(primary.clone())
+            .with_replica(replica.clone(), ReplicaConfig::default());
+        let item = InsertItemDao::new("sushi".to_string(), 1, 5, 2);
+
+        let session = ReadSession::default();
+        let items = session
+            .clone()
+            .scope(async {
+                repository.add_item(item, "test").await.unwrap();
+                repository.get_all_items().await.unwrap()
+            })
+            .await;
+        assert_eq!(items.len(), 1);
+        let last_write = session.last_write().unwrap();
+        assert!(last_write > Lsn(0));
+        assert!(repository.get_item(1).await.unwrap().is_some());
+        assert_eq!(
+            repository.stats(),
+            ReplicaStats {
+                replica_reads: 1,
+                primary_fallbacks: 0
+            }
+        );
+
+        // A session which has seen writes the replica has not replayed reads the primary.
+        let ahead = ReadSession::new(Some(Lsn(u64::MAX)));
+        let stats = ahead.scope(repository.get_table_stats()).await.unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(repository.stats().primary_fallbacks, 1);
+
+        // Reads fall back to the primary while the replica is down.
+        replica.connection_pool.close().await;
+        for _ in 0..2 {
+            assert_eq!(repository.get_all_items().await.unwrap().len(), 1);
+        }
+        assert_eq!(
+            repository.stats(),
+            ReplicaStats {
+                replica_reads: 1,
+                primary_fallbacks: 3
+            }
+        );
+
+        reset_test_db(primary.connection_pool).await;
+    }
+}
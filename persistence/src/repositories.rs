@@ -1,6 +1,15 @@
+use crate::health_repository::HealthRepository;
+use crate::idempotency_repository::IdempotencyRepository;
 use crate::item_repository::ItemRepository;
+use crate::staff_repository::StaffRepository;
 
 pub trait Repositories {
     type ItemRepository: ItemRepository;
+    type IdempotencyRepository: IdempotencyRepository;
+    type StaffRepository: StaffRepository;
+    type HealthRepository: HealthRepository;
     fn item_repository(&self) -> &Self::ItemRepository;
+    fn idempotency_repository(&self) -> &Self::IdempotencyRepository;
+    fn staff_repository(&self) -> &Self::StaffRepository;
+    fn health_repository(&self) -> &Self::HealthRepository;
 }
@@ -0,0 +1,451 @@
+use async_trait::async_trait;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::batch::{BatchMode, BatchOperation, BatchResult};
+use crate::dao::{InsertItemDao, ItemChangeDao, ItemDao, TableStatsDao, UpdateItemDao};
+use crate::error::DbError;
+use crate::item_repository::ItemRepository;
+use crate::query::{ItemPage, ItemQuery};
+use crate::retry::Backoff;
+
+/// Retries of failed queries and the circuit breaker protecting the database.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResilienceConfig {
+    /// How often a query failing with a retryable error is run again.
+    pub retries: u32,
+    pub backoff: Backoff,
+    /// Consecutive connection failures opening the circuit.
+    pub failure_threshold: u32,
+    /// How long queries fail fast once the circuit is open, before one is let through to
+    /// check whether the database is back.
+    pub open_duration: Duration,
+}
+
+impl Default for ResilienceConfig {
+    fn default() -> Self {
+        ResilienceConfig {
+            retries: 2,
+            backoff: Backoff {
+                initial: Duration::from_millis(50),
+                max: Duration::from_secs(1),
+            },
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CircuitState {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// A single query checks whether the database is back. Another one is let through
+    /// after `until`, in case the first one was cancelled.
+    HalfOpen {
+        until: Instant,
+    },
+}
+
+struct CircuitBreaker {
+    config: ResilienceConfig,
+    state: Mutex<CircuitState>,
+}
+
+impl CircuitBreaker {
+    /// Fails with the time until the next query is let through while the circuit is open.
+    fn admit(&self, now: Instant) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            CircuitState::Closed { .. } => Ok(()),
+            CircuitState::Open { until } | CircuitState::HalfOpen { until } if now >= until => {
+                *state = CircuitState::HalfOpen {
+                    until: now + self.config.open_duration,
+                };
+                Ok(())
+            }
+            CircuitState::Open { until } | CircuitState::HalfOpen { until } => Err(until - now),
+        }
+    }
+
+    fn record<T>(&self, result: &Result<T, DbError>, now: Instant) {
+        let failed = matches!(result, Err(e) if e.is_connection_failure());
+        let mut state = self.state.lock().unwrap();
+        *state = match (*state, failed) {
+            (_, false) => CircuitState::Closed { failures: 0 },
+            (CircuitState::Closed { failures }, true)
+                if failures + 1 < self.config.failure_threshold =>
+            {
+                CircuitState::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (CircuitState::Open { until }, true) => CircuitState::Open { until },
+            (_, true) => {
+                log::warn!(
+                    "the database is unavailable, failing fast for {} s",
+                    self.config.open_duration.as_secs()
+                );
+                CircuitState::Open {
+                    until: now + self.config.open_duration,
+                }
+            }
+        };
+    }
+}
+
+/// Runs queries with the retries and the circuit breaker of its configuration.
+#[derive(Clone)]
+pub struct Resilience {
+    config: ResilienceConfig,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl Resilience {
+    pub fn new(config: ResilienceConfig) -> Resilience {
+        Resilience {
+            config,
+            breaker: Arc::new(CircuitBreaker {
+                config,
+                state: Mutex::new(CircuitState::Closed { failures: 0 }),
+            }),
+        }
+    }
+
+    /// Runs `query` until it succeeds, fails with an error which is not retryable or runs
+    /// out of retries. Fails with `DbError::Unavailable` without running it while the
+    /// circuit is open.
+    pub async fn run<T, F, Fut>(&self, query: F) -> Result<T, DbError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, DbError>>,
+    {
+        self.run_retrying(query, DbError::is_retryable).await
+    }
+
+    /// Runs `change` like [`Resilience::run`], but only retries it when it was certainly
+    /// not applied, so a change is never applied twice.
+    pub async fn run_change<T, F, Fut>(&self, change: F) -> Result<T, DbError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, DbError>>,
+    {
+        self.run_retrying(change, DbError::is_retryable_change)
+            .await
+    }
+
+    async fn run_retrying<T, F, Fut>(
+        &self,
+        query: F,
+        is_retryable: fn(&DbError) -> bool,
+    ) -> Result<T, DbError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, DbError>>,
+    {
+        let mut attempt = 0;
+        loop {
+            self.breaker
+                .admit(Instant::now())
+                .map_err(DbError::Unavailable)?;
+            let result = query().await;
+            self.breaker.record(&result, Instant::now());
+            match result {
+                Err(e) if is_retryable(&e) && attempt < self.config.retries => {
+                    let delay = self.config.backoff.jittered_delay(attempt);
+                    log::warn!("retrying query in {} ms: {}", delay.as_millis(), e);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Item repository retrying transient failures of `inner` and failing fast while the
+/// database is down. Changes are only retried when they were rolled back: one losing its
+/// connection may have been committed before the result arrived, so it fails instead.
+#[derive(Clone)]
+pub struct ResilientItemRepository<R> {
+    pub inner: R,
+    resilience: Resilience,
+}
+
+impl<R> ResilientItemRepository<R> {
+    pub fn new(inner: R, config: ResilienceConfig) -> ResilientItemRepository<R> {
+        ResilientItemRepository {
+            inner,
+            resilience: Resilience::new(config),
+        }
+    }
+}
+
+#[async_trait]
+impl<R: ItemRepository + Send + Sync> ItemRepository for ResilientItemRepository<R> {
+    async fn add_item(&self, item: InsertItemDao, performed_by: &str) -> Result<i64, DbError> {
+        self.resilience
+            .run_change(|| self.inner.add_item(item.clone(), performed_by))
+            .await
+    }
+
+    async fn get_item(&self, item_id: i64) -> Result<Option<ItemDao>, DbError> {
+        self.resilience.run(|| self.inner.get_item(item_id)).await
+    }
+
+    async fn get_items_for_table(&self, table_id: i32) -> Result<Vec<ItemDao>, DbError> {
+        self.resilience
+            .run(|| self.inner.get_items_for_table(table_id))
+            .await
+    }
+
+    async fn get_items_for_tables(&self, table_ids: &[i32]) -> Result<Vec<ItemDao>, DbError> {
+        self.resilience
+            .run(|| self.inner.get_items_for_tables(table_ids))
+            .await
+    }
+
+    async fn get_all_items(&self) -> Result<Vec<ItemDao>, DbError> {
+        self.resilience.run(|| self.inner.get_all_items()).await
+    }
+
+    async fn query_items(&self, query: ItemQuery) -> Result<ItemPage, DbError> {
+        self.resilience
+            .run(|| self.inner.query_items(query.clone()))
+            .await
+    }
+
+    async fn remove_item(
+        &self,
+        item_id: i64,
+        quantity: i32,
+        expected_version: Option<i32>,
+        performed_by: &str,
+    ) -> Result<Option<ItemDao>, DbError> {
+        self.resilience
+            .run_change(|| {
+                self.inner
+                    .remove_item(item_id, quantity, expected_version, performed_by)
+            })
+            .await
+    }
+
+    async fn update_item(
+        &self,
+        item_id: i64,
+        update: UpdateItemDao,
+        expected_version: Option<i32>,
+        performed_by: &str,
+    ) -> Result<Option<ItemDao>, DbError> {
+        self.resilience
+            .run_change(|| {
+                self.inner
+                    .update_item(item_id, update.clone(), expected_version, performed_by)
+            })
+            .await
+    }
+
+    async fn transfer_table(
+        &self,
+        from_table_id: i32,
+        to_table_id: i32,
+        performed_by: &str,
+    ) -> Result<Vec<ItemDao>, DbError> {
+        self.resilience
+            .run_change(|| {
+                self.inner
+                    .transfer_table(from_table_id, to_table_id, performed_by)
+            })
+            .await
+    }
+
+    async fn execute_batch(
+        &self,
+        operations: Vec<BatchOperation>,
+        mode: BatchMode,
+        performed_by: &str,
+    ) -> Result<BatchResult, DbError> {
+        self.resilience
+            .run_change(|| {
+                self.inner
+                    .execute_batch(operations.clone(), mode, performed_by)
+            })
+            .await
+    }
+
+    async fn get_item_changes(&self, item_id: i64) -> Result<Vec<ItemChangeDao>, DbError> {
+        self.resilience
+            .run(|| self.inner.get_item_changes(item_id))
+            .await
+    }
+
+    async fn get_table_stats(&self) -> Result<Vec<TableStatsDao>, DbError> {
+        self.resilience.run(|| self.inner.get_table_stats()).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{init_test_db, reset_test_db};
+    use std::io;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn config() -> ResilienceConfig {
+        ResilienceConfig {
+            retries: 2,
+            backoff: Backoff {
+                initial: Duration::from_millis(1),
+                max: Duration::from_millis(5),
+            },
+            failure_threshold: 3,
+            open_duration: Duration::from_millis(200),
+        }
+    }
+
+    fn connection_reset() -> DbError {
+        DbError::from_sqlx_error(sqlx::Error::Io(io::ErrorKind::ConnectionReset.into()))
+    }
+
+    #[tokio::test]
+    async fn test_retries() {
+        let resilience = Resilience::new(config());
+        let calls = AtomicU32::new(0);
+        let result = resilience
+            .run(|| async {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 | 1 => Err(connection_reset()),
+                    _ => Ok(42),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Reads waiting too long for a connection of the pool are retried too.
+        calls.store(0, Ordering::SeqCst);
+        let result = resilience
+            .run(|| async {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(DbError::from_sqlx_error(sqlx::Error::PoolTimedOut)),
+                    _ => Ok(42),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        calls.store(0, Ordering::SeqCst);
+        let result: Result<(), _> = resilience
+            .run(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(DbError::from_sqlx_error(sqlx::Error::RowNotFound))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_change_lost_after_commit() {
+        let item_repository = init_test_db().await;
+        let resilience = Resilience::new(config());
+        let calls = AtomicU32::new(0);
+        // The change is committed, but the connection is lost before its result arrives.
+        let result = resilience
+            .run_change(|| async {
+                let item = InsertItemDao::new("sushi".to_string(), 1, 5, 1);
+                let item_id = item_repository.add_item(item, "test").await?;
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(connection_reset()),
+                    _ => Ok(item_id),
+                }
+            })
+            .await;
+        assert!(matches!(
+            result,
+            Err(DbError::SqlxError(sqlx::Error::Io(_)))
+        ));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let items = item_repository.get_items_for_table(1).await.unwrap();
+        assert_eq!(items[0].quantity, 1);
+
+        // Changes which did not start are retried.
+        calls.store(0, Ordering::SeqCst);
+        let result = resilience
+            .run_change(|| async {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(DbError::from_sqlx_error(sqlx::Error::PoolTimedOut)),
+                    _ => Ok(1),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let resilience = Resilience::new(config());
+        let calls = AtomicU32::new(0);
+        let failing = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(connection_reset())
+        };
+
+        // The third failure of the first query opens the circuit.
+        assert!(matches!(
+            resilience.run(failing).await,
+            Err(DbError::SqlxError(_))
+        ));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        match resilience.run(failing).await {
+            Err(DbError::Unavailable(retry_after)) => {
+                assert!(retry_after <= Duration::from_millis(200))
+            }
+            result => panic!("{:?}", result),
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // After the open duration a failing check opens the circuit again right away.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(resilience.run(failing).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        assert!(matches!(
+            resilience.run(|| async { Ok(1) }).await,
+            Err(DbError::Unavailable(_))
+        ));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(resilience.run(|| async { Ok(1) }).await.unwrap(), 1);
+        assert_eq!(resilience.run(|| async { Ok(2) }).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_closed_pool() {
+        let item_repository = init_test_db().await;
+        let repository = ResilientItemRepository::new(item_repository.clone(), config());
+        assert!(repository.get_item(1).await.unwrap().is_none());
+
+        item_repository.connection_pool.close().await;
+        for _ in 0..3 {
+            assert!(matches!(
+                repository.get_item(1).await,
+                Err(DbError::SqlxError(sqlx::Error::PoolClosed))
+            ));
+        }
+        assert!(matches!(
+            repository.get_item(1).await,
+            Err(DbError::Unavailable(_))
+        ));
+    }
+}
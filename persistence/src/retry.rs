@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+/// Exponentially growing delays between attempts, starting at `initial` and doubling up
+/// to `max`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(5),
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay before the retry following the failed `attempt`, counted from 0.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.initial
+            .saturating_mul(2_u32.saturating_pow(attempt))
+            .min(self.max)
+    }
+
+    /// Random delay up to [`Backoff::delay`], so that clients failing together don't
+    /// retry together.
+    pub fn jittered_delay(&self, attempt: u32) -> Duration {
+        self.delay(attempt).mul_f64(rand::random::<f64>())
+    }
+}
+
+/// How long to keep connecting to a database which is not up yet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConnectOptions {
+    /// Connecting fails with the last error once this time has passed.
+    pub timeout: Duration,
+    pub backoff: Backoff,
+    /// Whether the pending migrations are applied once connected.
+    pub migrate: bool,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        ConnectOptions {
+            timeout: Duration::from_secs(30),
+            backoff: Backoff::default(),
+            migrate: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(500),
+        };
+        let delays: Vec<_> = (0..5).map(|attempt| backoff.delay(attempt)).collect();
+        assert_eq!(delays, [100, 200, 400, 500, 500].map(Duration::from_millis));
+        assert_eq!(backoff.delay(u32::MAX), backoff.max);
+        for attempt in 0..5 {
+            assert!(backoff.jittered_delay(attempt) <= backoff.delay(attempt));
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::{
+    dao::{InsertStaffDao, StaffDao},
+    error::DbError,
+};
+
+#[async_trait]
+pub trait StaffRepository {
+    async fn add_staff(&self, staff: InsertStaffDao) -> Result<i32, DbError>;
+    async fn get_staff(&self, staff_id: i32) -> Result<Option<StaffDao>, DbError>;
+    /// Returns how long logins of the staff member are still refused after too many failed
+    /// ones, `None` when they are not locked.
+    async fn login_locked_for(&self, staff_id: i32) -> Result<Option<Duration>, DbError>;
+    /// Counts a failed login of the staff member. The `max_failed_logins`-th one in a row
+    /// locks logins for `lockout`.
+    async fn record_failed_login(
+        &self,
+        staff_id: i32,
+        max_failed_logins: u32,
+        lockout: Duration,
+    ) -> Result<(), DbError>;
+    /// Forgets the failed logins of the staff member after a successful one.
+    async fn reset_failed_logins(&self, staff_id: i32) -> Result<(), DbError>;
+    /// Starts a session of the staff member, ending at the latest after `lifetime`.
+    async fn create_session(
+        &self,
+        staff_id: i32,
+        token_hash: &str,
+        lifetime: Duration,
+    ) -> Result<(), DbError>;
+    /// Returns the staff member of the session and marks the session as used now. Returns
+    /// `None` when the session does not exist, expired, was not used for longer than
+    /// `idle_timeout` or the staff member is not active anymore.
+    async fn touch_session(
+        &self,
+        token_hash: &str,
+        idle_timeout: Duration,
+    ) -> Result<Option<StaffDao>, DbError>;
+    /// Ends the session and returns whether it existed.
+    async fn end_session(&self, token_hash: &str) -> Result<bool, DbError>;
+    /// Deletes expired and idle sessions and returns how many were deleted.
+    async fn purge_expired_sessions(&self, idle_timeout: Duration) -> Result<u64, DbError>;
+    /// Replaces the waiters serving the table with `staff_ids`.
+    async fn set_table_waiters(&self, table_id: i32, staff_ids: &[i32]) -> Result<(), DbError>;
+    /// Returns the ids of the waiters serving the table, ordered by id.
+    async fn get_table_waiters(&self, table_id: i32) -> Result<Vec<i32>, DbError>;
+    /// Returns the tables the staff member is assigned to, ordered by table id.
+    async fn get_assigned_tables(&self, staff_id: i32) -> Result<Vec<i32>, DbError>;
+}
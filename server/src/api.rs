@@ -0,0 +1,93 @@
+//! Versioned REST API.
+//!
+//! Every version lives in its own submodule with a `SCOPE` and a `configure` function
+//! registering its handlers, so a new version with different DTOs can be mounted next to
+//! the existing ones without changing the routes handhelds already use.
+
+use actix_web::middleware::{from_fn, DefaultHeaders};
+use actix_web::web::{self, ServiceConfig};
+
+use crate::idempotency::idempotency;
+
+pub mod v1;
+
+/// Date the unversioned routes were deprecated, as a structured field date (RFC 9745).
+pub const LEGACY_DEPRECATION: &str = "@1792368000";
+/// Date after which the unversioned routes may be removed (RFC 8594).
+pub const LEGACY_SUNSET: &str = "Thu, 01 Apr 2027 00:00:00 GMT";
+
+fn legacy_headers() -> DefaultHeaders {
+    DefaultHeaders::new()
+        .add(("Deprecation", LEGACY_DEPRECATION))
+        .add(("Sunset", LEGACY_SUNSET))
+        .add((
+            "Link",
+            format!("<{}>; rel=\"successor-version\"", v1::SCOPE),
+        ))
+}
+
+/// Registers all API versions and the deprecated unversioned aliases of v1.
+///
+/// The aliases are mounted in a scope without prefix which matches every path, so this
+/// must be the last configuration applied to the app.
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope(v1::SCOPE)
+            .wrap(from_fn(idempotency))
+            .configure(v1::configure),
+    )
+    .service(
+        web::scope("")
+            .wrap(from_fn(idempotency))
+            .wrap(legacy_headers())
+            .configure(v1::configure),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::auth::authenticate_as_manager;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App};
+    use persistence::postgres_repositories::PgRepositories;
+    use persistence::{init_test_db, reset_test_db};
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_versioned_and_legacy_routes() {
+        let item_repository = init_test_db().await;
+        let repositories = PgRepositories::new(item_repository.clone());
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate_as_manager))
+                .app_data(web::Data::new(repositories))
+                .configure(configure),
+        )
+        .await;
+
+        let request = test::TestRequest::get().uri("/api/v1/items").to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 200);
+        assert!(result.headers().get("Deprecation").is_none());
+
+        let request = test::TestRequest::get().uri("/items").to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 200);
+        assert_eq!(
+            result.headers().get("Deprecation").unwrap(),
+            LEGACY_DEPRECATION
+        );
+        assert_eq!(result.headers().get("Sunset").unwrap(), LEGACY_SUNSET);
+        assert_eq!(
+            result.headers().get("Link").unwrap(),
+            "</api/v1>; rel=\"successor-version\""
+        );
+
+        let request = test::TestRequest::get().uri("/api/v2/items").to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 404);
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+}
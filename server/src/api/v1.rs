@@ -0,0 +1,23 @@
+use actix_web::web::ServiceConfig;
+
+use crate::handlers::*;
+
+pub const SCOPE: &str = "/api/v1";
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(add_item)
+        .service(get_item)
+        .service(get_items_for_table)
+        .service(get_all_items)
+        .service(remove_item)
+        .service(update_item)
+        .service(set_item_status)
+        .service(transfer_table)
+        .service(execute_batch)
+        .service(login)
+        .service(logout)
+        .service(add_staff)
+        .service(assign_waiters)
+        .service(get_staff_tables)
+        .service(notifications);
+}
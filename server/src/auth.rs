@@ -0,0 +1,418 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, AUTHORIZATION};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, FromRequest, HttpMessage, HttpRequest};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use persistence::postgres_repositories::PgRepositories;
+use persistence::staff_repository::StaffRepository;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::{ready, Ready};
+use std::io;
+use std::time::Duration;
+
+use crate::api::v1;
+use crate::config::AuthConfig;
+use crate::errors::ServerError;
+use crate::policy::Role;
+use crate::session::session_token_hash;
+
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// How a request was authenticated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Credential {
+    ApiKey,
+    Jwt,
+    /// Session token of a staff member logged in with a PIN.
+    Session,
+}
+
+/// Staff member or device a request was made by, attached to the request by
+/// [`authenticate`]. Handlers take it as an argument to require an authenticated request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaffIdentity {
+    /// Device id of an API key, the `sub` claim of a token or the id of a logged in staff
+    /// member. Changes of items are recorded with it.
+    pub id: String,
+    pub name: Option<String>,
+    pub role: Role,
+    pub credential: Credential,
+}
+
+impl StaffIdentity {
+    /// Id of a staff member logged in with their PIN. The ids of API keys and tokens are
+    /// chosen by whoever configured the key or issued the token, so they don't prove to
+    /// be a staff member even when they are numbers.
+    pub fn staff_id(&self) -> Option<i32> {
+        match self.credential {
+            Credential::Session => self.id.parse().ok(),
+            Credential::ApiKey | Credential::Jwt => None,
+        }
+    }
+}
+
+impl FromRequest for StaffIdentity {
+    type Error = ServerError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            request
+                .extensions()
+                .get::<StaffIdentity>()
+                .cloned()
+                .ok_or_else(|| ServerError::Unauthorized("authentication required".to_string())),
+        )
+    }
+}
+
+/// Claims of the tokens issued to staff members.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub role: Role,
+    pub exp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Returns the token of an `Authorization: Bearer` header.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+/// Checks API keys and tokens against the locally configured credentials, and session
+/// tokens against the sessions of staff members.
+pub struct Authenticator {
+    /// Device ids and roles by SHA-256 of their API key, so keys are never compared byte
+    /// by byte.
+    api_keys: HashMap<[u8; 32], (String, Role)>,
+    jwt_keys: Vec<(Algorithm, DecodingKey)>,
+    session_idle_timeout: Duration,
+}
+
+fn hash_api_key(key: &str) -> [u8; 32] {
+    Sha256::digest(key.as_bytes()).into()
+}
+
+impl Authenticator {
+    pub fn from_config(config: &AuthConfig) -> io::Result<Authenticator> {
+        let api_keys = config
+            .api_keys
+            .iter()
+            .map(|(device, device_key)| {
+                (
+                    hash_api_key(&device_key.key),
+                    (device.clone(), device_key.role),
+                )
+            })
+            .collect();
+
+        let mut jwt_keys = Vec::new();
+        if let Some(secret) = &config.jwt_hs256_secret {
+            jwt_keys.push((
+                Algorithm::HS256,
+                DecodingKey::from_secret(secret.as_bytes()),
+            ));
+        }
+        if let Some(path) = &config.jwt_rs256_public_key_path {
+            let pem = std::fs::read(path)?;
+            let key = DecodingKey::from_rsa_pem(&pem)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            jwt_keys.push((Algorithm::RS256, key));
+        }
+
+        if config.api_keys.is_empty() && jwt_keys.is_empty() {
+            log::warn!(
+                "no API keys or token keys are configured, only staff sessions are accepted"
+            );
+        }
+        Ok(Authenticator {
+            api_keys,
+            jwt_keys,
+            session_idle_timeout: config.session.idle_timeout,
+        })
+    }
+
+    pub fn authenticate_api_key(&self, key: &str) -> Option<StaffIdentity> {
+        self.api_keys
+            .get(&hash_api_key(key))
+            .map(|(device, role)| StaffIdentity {
+                id: device.clone(),
+                name: None,
+                role: *role,
+                credential: Credential::ApiKey,
+            })
+    }
+
+    pub fn authenticate_token(&self, token: &str) -> Option<StaffIdentity> {
+        let algorithm = jsonwebtoken::decode_header(token).ok()?.alg;
+        let (_, key) = self.jwt_keys.iter().find(|(alg, _)| *alg == algorithm)?;
+        let claims = jsonwebtoken::decode::<Claims>(token, key, &Validation::new(algorithm))
+            .map_err(|e| log::debug!("rejected token: {}", e))
+            .ok()?
+            .claims;
+        Some(StaffIdentity {
+            id: claims.sub,
+            name: claims.name,
+            role: claims.role,
+            credential: Credential::Jwt,
+        })
+    }
+
+    /// Returns the staff member of an active session and keeps the session alive. Sessions
+    /// which were not used for longer than the idle timeout are logged out.
+    pub async fn authenticate_session(
+        &self,
+        repositories: &PgRepositories,
+        token: &str,
+    ) -> Result<Option<StaffIdentity>, ServerError> {
+        let staff = repositories
+            .staff_repository
+            .touch_session(&session_token_hash(token), self.session_idle_timeout)
+            .await?;
+        Ok(staff.and_then(|staff| {
+            let role = staff
+                .role
+                .parse()
+                .map_err(|e| log::warn!("staff member {} has {}", staff.id, e))
+                .ok()?;
+            Some(StaffIdentity {
+                id: staff.id.to_string(),
+                name: Some(staff.name),
+                role,
+                credential: Credential::Session,
+            })
+        }))
+    }
+
+    async fn authenticate(&self, request: &ServiceRequest) -> Result<StaffIdentity, ServerError> {
+        let invalid = || ServerError::Unauthorized("invalid credentials".to_string());
+        if let Some(key) = request.headers().get(API_KEY_HEADER) {
+            let key = key.to_str().map_err(|_| invalid())?;
+            return self.authenticate_api_key(key).ok_or_else(invalid);
+        }
+        if request.headers().contains_key(AUTHORIZATION) {
+            let token = bearer_token(request.headers()).ok_or_else(invalid)?;
+            // JWTs consist of three dot separated parts, session tokens never contain a dot.
+            if token.contains('.') {
+                return self.authenticate_token(token).ok_or_else(invalid);
+            }
+            let repositories = request
+                .app_data::<web::Data<PgRepositories>>()
+                .ok_or_else(invalid)?;
+            return self
+                .authenticate_session(repositories, token)
+                .await?
+                .ok_or_else(|| {
+                    ServerError::Unauthorized(
+                        "the session expired or was logged out, log in again".to_string(),
+                    )
+                });
+        }
+        Err(ServerError::Unauthorized(format!(
+            "an {} header or a bearer token is required",
+            API_KEY_HEADER
+        )))
+    }
+}
+
+/// Documentation and the GraphiQL page are readable without credentials, and logging in
+/// obviously works without them.
+fn is_public(request: &ServiceRequest) -> bool {
+    let path = request.path();
+    let api_path = path.strip_prefix(v1::SCOPE).unwrap_or(path);
+    path == "/openapi.json"
+        || path.starts_with("/health/")
+        || path.starts_with("/swagger-ui/")
+        || (path == "/graphql" && request.method() == Method::GET)
+        || (api_path == "/session" && request.method() == Method::POST)
+}
+
+/// Rejects requests without valid credentials with 401 and attaches the
+/// [`StaffIdentity`] of the others to the request.
+pub async fn authenticate(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if !is_public(&request) {
+        let identity = match request.app_data::<web::Data<Authenticator>>() {
+            Some(authenticator) => authenticator.authenticate(&request).await,
+            None => Err(ServerError::Unauthorized(
+                "authentication is not configured".to_string(),
+            )),
+        };
+        match identity {
+            Ok(identity) => {
+                request.extensions_mut().insert(identity);
+            }
+            Err(e) => return Ok(request.error_response(e)),
+        }
+    }
+    next.call(request).await.map(|r| r.map_into_boxed_body())
+}
+
+#[cfg(test)]
+pub(crate) fn test_identity(role: Role) -> StaffIdentity {
+    StaffIdentity {
+        id: role.to_string(),
+        name: None,
+        role,
+        credential: Credential::ApiKey,
+    }
+}
+
+/// Attaches a manager identity to every request, for tests of handlers which are not
+/// about authorization.
+#[cfg(test)]
+pub(crate) async fn authenticate_as_manager(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    request
+        .extensions_mut()
+        .insert(test_identity(Role::Manager));
+    next.call(request).await.map(|r| r.map_into_boxed_body())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::DeviceKey;
+    use actix_web::middleware::from_fn;
+    use actix_web::{get, test, App, HttpResponse};
+    use jsonwebtoken::{EncodingKey, Header};
+    use std::path::PathBuf;
+
+    const HS256_SECRET: &str = "test-secret";
+
+    #[get("/whoami")]
+    async fn whoami(identity: StaffIdentity) -> HttpResponse {
+        HttpResponse::Ok().body(format!("{} {}", identity.id, identity.role))
+    }
+
+    fn test_config() -> AuthConfig {
+        AuthConfig {
+            api_keys: HashMap::from([(
+                "kitchen-display".to_string(),
+                DeviceKey {
+                    key: "key-1".to_string(),
+                    role: Role::Kitchen,
+                },
+            )]),
+            jwt_hs256_secret: Some(HS256_SECRET.to_string()),
+            jwt_rs256_public_key_path: Some(
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata/jwt_rs256_public.pem"),
+            ),
+            session: Default::default(),
+        }
+    }
+
+    fn token(algorithm: Algorithm, key: &EncodingKey, exp: u64) -> String {
+        let claims = Claims {
+            sub: "7".to_string(),
+            role: Role::Waiter,
+            exp,
+            name: Some("Anna".to_string()),
+        };
+        jsonwebtoken::encode(&Header::new(algorithm), &claims, key).unwrap()
+    }
+
+    fn in_one_hour() -> u64 {
+        jsonwebtoken::get_current_timestamp() + 60 * 60
+    }
+
+    #[actix_web::test]
+    async fn test_requests_are_authenticated() {
+        let authenticator = Authenticator::from_config(&test_config()).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(authenticator))
+                .wrap(from_fn(authenticate))
+                .service(whoami),
+        )
+        .await;
+        let hs256_key = EncodingKey::from_secret(HS256_SECRET.as_bytes());
+        let rs256_key =
+            EncodingKey::from_rsa_pem(include_bytes!("../testdata/jwt_rs256_private.pem")).unwrap();
+
+        let accepted = [
+            (
+                API_KEY_HEADER,
+                "key-1".to_string(),
+                "kitchen-display kitchen",
+            ),
+            (
+                "Authorization",
+                format!(
+                    "Bearer {}",
+                    token(Algorithm::HS256, &hs256_key, in_one_hour())
+                ),
+                "7 waiter",
+            ),
+            (
+                "Authorization",
+                format!(
+                    "Bearer {}",
+                    token(Algorithm::RS256, &rs256_key, in_one_hour())
+                ),
+                "7 waiter",
+            ),
+        ];
+        for (header, value, id) in accepted {
+            let request = test::TestRequest::get()
+                .uri("/whoami")
+                .insert_header((header, value))
+                .to_request();
+            let result = test::call_and_read_body(&app, request).await;
+            assert_eq!(result, id);
+        }
+
+        let expired = jsonwebtoken::get_current_timestamp() - 60 * 60;
+        let wrong_secret = EncodingKey::from_secret(b"other-secret");
+        let rejected = [
+            (API_KEY_HEADER, "key-2".to_string()),
+            (
+                "Authorization",
+                format!("Bearer {}", token(Algorithm::HS256, &hs256_key, expired)),
+            ),
+            (
+                "Authorization",
+                format!(
+                    "Bearer {}",
+                    token(Algorithm::HS256, &wrong_secret, in_one_hour())
+                ),
+            ),
+            (
+                "Authorization",
+                format!(
+                    "Bearer {}",
+                    token(Algorithm::HS384, &hs256_key, in_one_hour())
+                ),
+            ),
+            ("Authorization", "Basic a2V5LTE=".to_string()),
+        ];
+        for (header, value) in rejected {
+            let request = test::TestRequest::get()
+                .uri("/whoami")
+                .insert_header((header, value.clone()))
+                .to_request();
+            let result = test::call_service(&app, request).await;
+            assert_eq!(result.status(), 401, "{}: {}", header, value);
+        }
+
+        let request = test::TestRequest::get().uri("/whoami").to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 401);
+        assert_eq!(result.headers().get("WWW-Authenticate").unwrap(), "Bearer");
+    }
+}
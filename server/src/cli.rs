@@ -0,0 +1,96 @@
+//! Command line of the server binary.
+
+pub const USAGE: &str = "\
+usage: server [--no-migrate]
+       server migrate up
+       server migrate status
+       server migrate down [--to VERSION]
+
+  --no-migrate     start without applying pending migrations, the server is not ready
+                   until they are applied with `server migrate up`
+  migrate up       apply the pending migrations
+  migrate status   list the migrations and whether they are applied
+  migrate down     revert the last applied migration, or all applied migrations newer
+                   than VERSION";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Serves the API, migrating the database first when `migrate` is set.
+    Serve {
+        migrate: bool,
+    },
+    Migrate(MigrateCommand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MigrateCommand {
+    Up,
+    Status,
+    /// Reverts the migrations newer than `to`, or the last one.
+    Down {
+        to: Option<i64>,
+    },
+}
+
+impl Command {
+    /// Parses the arguments without the name of the binary.
+    pub fn parse<I, S>(args: I) -> Result<Command, String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let args: Vec<S> = args.into_iter().collect();
+        let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+        match args.as_slice() {
+            [] => Ok(Command::Serve { migrate: true }),
+            ["--no-migrate"] => Ok(Command::Serve { migrate: false }),
+            ["migrate", "up"] => Ok(Command::Migrate(MigrateCommand::Up)),
+            ["migrate", "status"] => Ok(Command::Migrate(MigrateCommand::Status)),
+            ["migrate", "down"] => Ok(Command::Migrate(MigrateCommand::Down { to: None })),
+            ["migrate", "down", "--to", version] => version
+                .parse()
+                .ok()
+                .filter(|version| *version >= 0)
+                .map(|version| Command::Migrate(MigrateCommand::Down { to: Some(version) }))
+                .ok_or_else(|| format!("invalid version {}", version)),
+            _ => Err(format!("invalid arguments: {}", args.join(" "))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let no_args: [&str; 0] = [];
+        assert_eq!(
+            Command::parse(no_args),
+            Ok(Command::Serve { migrate: true })
+        );
+        assert_eq!(
+            Command::parse(["--no-migrate"]),
+            Ok(Command::Serve { migrate: false })
+        );
+        assert_eq!(
+            Command::parse(["migrate", "status"]),
+            Ok(Command::Migrate(MigrateCommand::Status))
+        );
+        assert_eq!(
+            Command::parse(["migrate", "down", "--to", "202610191700"]),
+            Ok(Command::Migrate(MigrateCommand::Down {
+                to: Some(202610191700)
+            }))
+        );
+        for invalid in [
+            &["migrate"][..],
+            &["migrate", "sideways"],
+            &["migrate", "down", "--to", "x"],
+            &["migrate", "down", "--to", "-1"],
+            &["--no-migrate", "migrate", "up"],
+        ] {
+            assert!(Command::parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+}
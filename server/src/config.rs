@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::policy::Role;
+use persistence::cached_item_repository::CacheConfig;
+use persistence::replicated_item_repository::ReplicaConfig;
+use persistence::resilient_item_repository::ResilienceConfig;
+use persistence::retry::{Backoff, ConnectOptions};
+use std::time::Duration;
+
+/// Settings of the server, read from environment variables with defaults suitable for
+/// the docker-compose setup.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub idempotency: IdempotencyConfig,
+    pub auth: AuthConfig,
+    pub limits: LimitsConfig,
+    pub tracing: TracingConfig,
+    pub lifecycle: LifecycleConfig,
+    pub resilience: ResilienceConfig,
+    pub cache: CacheConfig,
+    pub read_replica: ReadReplicaConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct IdempotencyConfig {
+    /// How long a stored response is replayed for a repeated `Idempotency-Key`.
+    pub key_ttl: Duration,
+    /// How long a key is reserved for a request being processed. A repeated request takes
+    /// over the key once a reservation was neither completed nor released in this time.
+    pub lock_timeout: Duration,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        IdempotencyConfig {
+            key_ttl: Duration::from_secs(24 * 60 * 60),
+            lock_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceKey {
+    pub key: String,
+    pub role: Role,
+}
+
+/// Credentials accepted from staff devices.
+#[derive(Debug, Clone, Default)]
+pub struct AuthConfig {
+    /// API key and role of every registered device, by device id.
+    pub api_keys: HashMap<String, DeviceKey>,
+    /// Shared secret of HS256 signed tokens.
+    pub jwt_hs256_secret: Option<String>,
+    /// PEM file with the public key of RS256 signed tokens.
+    pub jwt_rs256_public_key_path: Option<PathBuf>,
+    pub session: SessionConfig,
+}
+
+/// Sessions of staff members logged in with their PIN.
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// Sessions not used for this long are logged out.
+    pub idle_timeout: Duration,
+    /// Sessions end after this time even when they are used.
+    pub lifetime: Duration,
+    /// Failed logins in a row after which logins of the staff member are locked.
+    pub max_failed_logins: u32,
+    /// How long logins stay locked after too many failed ones.
+    pub login_lockout: Duration,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            idle_timeout: Duration::from_secs(15 * 60),
+            lifetime: Duration::from_secs(8 * 60 * 60),
+            max_failed_logins: 5,
+            login_lockout: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+/// Export of traces to an OpenTelemetry collector.
+#[derive(Debug, Clone)]
+pub struct TracingConfig {
+    /// Base URL of the OTLP/HTTP endpoint of the collector, e.g. `http://localhost:4318`.
+    /// Traces are not exported without it.
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        TracingConfig {
+            otlp_endpoint: None,
+            service_name: "restaurant-server".to_string(),
+        }
+    }
+}
+
+impl TracingConfig {
+    /// Reads the configuration on its own, as logging is set up before the rest.
+    pub fn from_env() -> TracingConfig {
+        TracingConfig {
+            otlp_endpoint: env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+                .ok()
+                .filter(|endpoint| !endpoint.is_empty()),
+            service_name: env::var("OTEL_SERVICE_NAME")
+                .unwrap_or_else(|_| TracingConfig::default().service_name),
+        }
+    }
+}
+
+/// Startup and shutdown of the server.
+#[derive(Debug, Clone)]
+pub struct LifecycleConfig {
+    /// Retries of connecting to the database at startup.
+    pub connect: ConnectOptions,
+    /// How long requests still in flight are waited for on shutdown before their
+    /// connections are dropped.
+    pub shutdown_timeout: Duration,
+}
+
+impl Default for LifecycleConfig {
+    fn default() -> Self {
+        LifecycleConfig {
+            connect: ConnectOptions::default(),
+            shutdown_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Optional read replica serving lists of items and reports.
+#[derive(Debug, Clone, Default)]
+pub struct ReadReplicaConfig {
+    /// Connection URL of the replica, all queries go to the primary without it.
+    pub url: Option<String>,
+    pub routing: ReplicaConfig,
+}
+
+/// Token bucket allowing bursts of `burst` requests, refilled with `per_second` tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_second: f64,
+}
+
+impl FromStr for RateLimit {
+    type Err = String;
+
+    /// Parses `burst:per_second`, e.g. `20:5`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid rate limit {}", value);
+        let (burst, per_second) = value.split_once(':').ok_or_else(invalid)?;
+        let burst = burst.trim().parse().map_err(|_| invalid())?;
+        let per_second: f64 = per_second.trim().parse().map_err(|_| invalid())?;
+        if burst == 0 || !per_second.is_finite() || per_second <= 0.0 {
+            return Err(invalid());
+        }
+        Ok(RateLimit { burst, per_second })
+    }
+}
+
+/// Limits protecting the server and the database from misbehaving clients.
+#[derive(Debug, Clone)]
+pub struct LimitsConfig {
+    /// Rate of the requests of every client to routes without their own rate.
+    pub rate: RateLimit,
+    /// Rates of single routes by `METHOD /path` without the API version prefix, e.g.
+    /// `POST /item`. Each of them has its own bucket per client.
+    pub route_rates: HashMap<String, RateLimit>,
+    /// Rate of the requests of an IP address which are rejected with 401, e.g. because of
+    /// an invalid API key, token or PIN.
+    pub failed_authentication_rate: RateLimit,
+    /// Largest JSON body accepted by any route.
+    pub max_json_payload: usize,
+    /// Largest body accepted when adding an item.
+    pub max_add_item_payload: usize,
+    /// Most buckets kept at once, requests of new clients are rejected beyond it.
+    pub max_buckets: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            rate: RateLimit {
+                burst: 60,
+                per_second: 10.0,
+            },
+            // Logging in checks a PIN of only a few digits, guessing has to be slow.
+            route_rates: HashMap::from([(
+                "POST /session".to_string(),
+                RateLimit {
+                    burst: 5,
+                    per_second: 0.1,
+                },
+            )]),
+            failed_authentication_rate: RateLimit {
+                burst: 10,
+                per_second: 0.1,
+            },
+            max_json_payload: 64 * 1024,
+            max_add_item_payload: 1024,
+            max_buckets: 10_000,
+        }
+    }
+}
+
+/// Parses `METHOD /path=burst:per_second` entries separated by commas.
+fn parse_route_rates(value: &str) -> HashMap<String, RateLimit> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            let parsed = entry.rsplit_once('=').and_then(|(route, rate)| {
+                let (method, path) = route.trim().split_once(' ')?;
+                let rate = rate.parse().ok()?;
+                let route = format!("{} {}", method.to_uppercase(), path.trim());
+                Some((route, rate))
+            });
+            if parsed.is_none() {
+                log::warn!("ignoring invalid entry of RATE_LIMIT_ROUTES");
+            }
+            parsed
+        })
+        .collect()
+}
+
+/// Parses `device=role:key` entries separated by commas.
+fn parse_api_keys(value: &str) -> HashMap<String, DeviceKey> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            let parsed = entry.split_once('=').and_then(|(device, role_and_key)| {
+                let (role, key) = role_and_key.split_once(':')?;
+                let role = role.parse().ok()?;
+                (!device.is_empty() && !key.is_empty()).then(|| {
+                    let key = key.to_string();
+                    (device.to_string(), DeviceKey { key, role })
+                })
+            });
+            if parsed.is_none() {
+                log::warn!("ignoring invalid entry of AUTH_API_KEYS");
+            }
+            parsed
+        })
+        .collect()
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            log::warn!("invalid value of {}: {}, using the default", name, value);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+impl ServerConfig {
+    pub fn from_env() -> ServerConfig {
+        let idempotency = IdempotencyConfig {
+            key_ttl: Duration::from_secs(env_or(
+                "IDEMPOTENCY_KEY_TTL_SECS",
+                IdempotencyConfig::default().key_ttl.as_secs(),
+            )),
+            lock_timeout: Duration::from_secs(env_or(
+                "IDEMPOTENCY_LOCK_TIMEOUT_SECS",
+                IdempotencyConfig::default().lock_timeout.as_secs(),
+            )),
+        };
+
+        let auth = AuthConfig {
+            api_keys: env::var("AUTH_API_KEYS")
+                .map(|value| parse_api_keys(&value))
+                .unwrap_or_default(),
+            jwt_hs256_secret: env::var("AUTH_JWT_HS256_SECRET").ok(),
+            jwt_rs256_public_key_path: env::var("AUTH_JWT_RS256_PUBLIC_KEY_FILE")
+                .ok()
+                .map(PathBuf::from),
+            session: SessionConfig {
+                idle_timeout: Duration::from_secs(env_or(
+                    "SESSION_IDLE_TIMEOUT_SECS",
+                    SessionConfig::default().idle_timeout.as_secs(),
+                )),
+                lifetime: Duration::from_secs(env_or(
+                    "SESSION_LIFETIME_SECS",
+                    SessionConfig::default().lifetime.as_secs(),
+                )),
+                max_failed_logins: env_or(
+                    "LOGIN_MAX_FAILED_ATTEMPTS",
+                    SessionConfig::default().max_failed_logins,
+                ),
+                login_lockout: Duration::from_secs(env_or(
+                    "LOGIN_LOCKOUT_SECS",
+                    SessionConfig::default().login_lockout.as_secs(),
+                )),
+            },
+        };
+
+        let default_limits = LimitsConfig::default();
+        let limits = LimitsConfig {
+            rate: env_or("RATE_LIMIT", default_limits.rate),
+            route_rates: default_limits
+                .route_rates
+                .into_iter()
+                .chain(
+                    env::var("RATE_LIMIT_ROUTES")
+                        .map(|value| parse_route_rates(&value))
+                        .unwrap_or_default(),
+                )
+                .collect(),
+            failed_authentication_rate: env_or(
+                "RATE_LIMIT_FAILED_AUTHENTICATIONS",
+                default_limits.failed_authentication_rate,
+            ),
+            max_json_payload: env_or("MAX_JSON_PAYLOAD_BYTES", default_limits.max_json_payload),
+            max_add_item_payload: env_or(
+                "MAX_ADD_ITEM_PAYLOAD_BYTES",
+                default_limits.max_add_item_payload,
+            ),
+            max_buckets: env_or("RATE_LIMIT_MAX_BUCKETS", default_limits.max_buckets),
+        };
+
+        let default_lifecycle = LifecycleConfig::default();
+        let lifecycle = LifecycleConfig {
+            connect: ConnectOptions {
+                timeout: Duration::from_secs(env_or(
+                    "DB_CONNECT_TIMEOUT_SECS",
+                    default_lifecycle.connect.timeout.as_secs(),
+                )),
+                backoff: Backoff {
+                    max: Duration::from_millis(env_or(
+                        "DB_CONNECT_MAX_BACKOFF_MS",
+                        default_lifecycle.connect.backoff.max.as_millis() as u64,
+                    )),
+                    ..default_lifecycle.connect.backoff
+                },
+                ..default_lifecycle.connect
+            },
+            shutdown_timeout: Duration::from_secs(env_or(
+                "SHUTDOWN_TIMEOUT_SECS",
+                default_lifecycle.shutdown_timeout.as_secs(),
+            )),
+        };
+
+        let default_resilience = ResilienceConfig::default();
+        let resilience = ResilienceConfig {
+            retries: env_or("DB_RETRIES", default_resilience.retries),
+            failure_threshold: env_or("DB_BREAKER_FAILURES", default_resilience.failure_threshold),
+            open_duration: Duration::from_secs(env_or(
+                "DB_BREAKER_OPEN_SECS",
+                default_resilience.open_duration.as_secs(),
+            )),
+            ..default_resilience
+        };
+
+        let default_cache = CacheConfig::default();
+        let cache = CacheConfig {
+            ttl: Duration::from_secs(env_or("ITEM_CACHE_TTL_SECS", default_cache.ttl.as_secs())),
+            max_entries: env_or("ITEM_CACHE_MAX_ENTRIES", default_cache.max_entries),
+        };
+
+        let default_replica = ReplicaConfig::default();
+        let read_replica = ReadReplicaConfig {
+            url: env::var("DB_REPLICA_URL")
+                .ok()
+                .filter(|url| !url.is_empty()),
+            routing: ReplicaConfig {
+                max_lag: Duration::from_secs(env_or(
+                    "DB_REPLICA_MAX_LAG_SECS",
+                    default_replica.max_lag.as_secs(),
+                )),
+                ..default_replica
+            },
+        };
+
+        ServerConfig {
+            idempotency,
+            auth,
+            limits,
+            tracing: TracingConfig::from_env(),
+            lifecycle,
+            resilience,
+            cache,
+            read_replica,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_api_keys() {
+        let api_keys = parse_api_keys("kitchen-1=kitchen:k1, tablet-2=waiter:a:b,bad=chef:k,x");
+        assert_eq!(
+            api_keys,
+            HashMap::from([
+                (
+                    "kitchen-1".to_string(),
+                    DeviceKey {
+                        key: "k1".to_string(),
+                        role: Role::Kitchen
+                    }
+                ),
+                (
+                    "tablet-2".to_string(),
+                    DeviceKey {
+                        key: "a:b".to_string(),
+                        role: Role::Waiter
+                    }
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_route_rates() {
+        assert_eq!(
+            "20:0.5".parse(),
+            Ok(RateLimit {
+                burst: 20,
+                per_second: 0.5
+            })
+        );
+        assert!("20".parse::<RateLimit>().is_err());
+        assert!("0:1".parse::<RateLimit>().is_err());
+        assert!("5:0".parse::<RateLimit>().is_err());
+
+        let route_rates =
+            parse_route_rates("post /item=10:1, DELETE /item/{item_id}/{quantity}=5:2,/x=1:1");
+        assert_eq!(
+            route_rates,
+            HashMap::from([
+                (
+                    "POST /item".to_string(),
+                    RateLimit {
+                        burst: 10,
+                        per_second: 1.0
+                    }
+                ),
+                (
+                    "DELETE /item/{item_id}/{quantity}".to_string(),
+                    RateLimit {
+                        burst: 5,
+                        per_second: 2.0
+                    }
+                ),
+            ])
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Reading own writes while lists of items are served by a read replica.
+//!
+//! Every request runs in a [`ReadSession`]. Its writes are returned as log position in
+//! the `X-Read-After` header, clients send the last position they got back with their
+//! next requests, which are then only served by the replica once it replayed that write.
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use persistence::replicated_item_repository::{Lsn, ReadSession};
+
+pub const READ_AFTER_HEADER: &str = "X-Read-After";
+
+/// Runs the request in a session starting at the position of its `X-Read-After` header,
+/// and returns the position of the last write of the session in the response.
+pub async fn read_your_writes(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let last_write = request
+        .headers()
+        .get(READ_AFTER_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<Lsn>().ok());
+    let session = ReadSession::new(last_write);
+    let mut response = session
+        .clone()
+        .scope(next.call(request))
+        .await?
+        .map_into_boxed_body();
+    if let Some(last_write) = session.last_write() {
+        // Positions are hexadecimal numbers, which are valid header values.
+        if let Ok(value) = HeaderValue::from_str(&last_write.to_string()) {
+            response
+                .headers_mut()
+                .insert(HeaderName::from_static("x-read-after"), value);
+        }
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::auth::authenticate_as_manager;
+    use crate::dto::{AddItemRequest, GetAllItemsResponse};
+    use crate::handlers::{add_item, get_all_items};
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App};
+    use persistence::postgres_item_repository::PgItemRepository;
+    use persistence::postgres_repositories::PgRepositories;
+    use persistence::replicated_item_repository::ReplicaConfig;
+    use persistence::{init_test_db, reset_test_db};
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_read_your_writes() {
+        let item_repository = init_test_db().await;
+        // The test database stands in for the replica.
+        let replica = PgItemRepository::init_test().await;
+        let repositories = PgRepositories::new(item_repository.clone())
+            .with_read_replica(replica, ReplicaConfig::default());
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate_as_manager))
+                .wrap(from_fn(read_your_writes))
+                .app_data(web::Data::new(repositories.clone()))
+                .service(add_item)
+                .service(get_all_items),
+        )
+        .await;
+
+        let request = test::TestRequest::post()
+            .uri("/item")
+            .set_json(AddItemRequest {
+                name: "sushi".to_string(),
+                table_id: 1,
+                quantity: 1,
+            })
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 200);
+        let last_write = result.headers().get(READ_AFTER_HEADER).unwrap().clone();
+        assert!(last_write.to_str().unwrap().parse::<Lsn>().is_ok());
+
+        let request = test::TestRequest::get()
+            .uri("/items")
+            .insert_header((READ_AFTER_HEADER, last_write.clone()))
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.headers().get(READ_AFTER_HEADER), Some(&last_write));
+        let result: GetAllItemsResponse = test::read_body_json(result).await;
+        assert_eq!(result.items.len(), 1);
+        let stats = repositories.replicated_item_repository().stats();
+        assert_eq!((stats.replica_reads, stats.primary_fallbacks), (1, 0));
+
+        // Writes the replica has not replayed yet are read from the primary.
+        let request = test::TestRequest::get()
+            .uri("/items")
+            .insert_header((READ_AFTER_HEADER, "FFFFFFFF/0"))
+            .to_request();
+        let result: GetAllItemsResponse = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(result.items.len(), 1);
+        let stats = repositories.replicated_item_repository().stats();
+        assert_eq!((stats.replica_reads, stats.primary_fallbacks), (1, 1));
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+}
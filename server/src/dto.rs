@@ -1,34 +1,107 @@
+use chrono::NaiveDateTime;
 use derive_new::new;
 use domain::item::Item;
+use persistence::batch::{BatchOutcome, BatchResult};
+use persistence::dao;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Debug, Deserialize, Serialize)]
+use crate::policy::Role;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
 pub struct AddItemRequest {
     pub name: String,
     pub table_id: i32,
     pub quantity: i32,
 }
 
-#[derive(Debug, Deserialize, Serialize, new)]
+/// Changes of an ordered item, absent fields are left as they are.
+#[derive(Debug, Default, Deserialize, Serialize, ToSchema)]
+pub struct UpdateItemRequest {
+    /// New quantity of the item, at least 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantity: Option<i32>,
+    /// Table to move the item to. If the table already has the same dish, both are merged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub table_id: Option<i32>,
+    /// New notes of the item, an empty string removes them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+/// Preparation status of an item, in the order the kitchen moves items through them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemStatus {
+    #[default]
+    Ordered,
+    Preparing,
+    Ready,
+    Served,
+}
+
+impl From<dao::ItemStatus> for ItemStatus {
+    fn from(status: dao::ItemStatus) -> Self {
+        match status {
+            dao::ItemStatus::Ordered => ItemStatus::Ordered,
+            dao::ItemStatus::Preparing => ItemStatus::Preparing,
+            dao::ItemStatus::Ready => ItemStatus::Ready,
+            dao::ItemStatus::Served => ItemStatus::Served,
+        }
+    }
+}
+
+impl From<ItemStatus> for dao::ItemStatus {
+    fn from(status: ItemStatus) -> Self {
+        match status {
+            ItemStatus::Ordered => dao::ItemStatus::Ordered,
+            ItemStatus::Preparing => dao::ItemStatus::Preparing,
+            ItemStatus::Ready => dao::ItemStatus::Ready,
+            ItemStatus::Served => dao::ItemStatus::Served,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct SetItemStatusRequest {
+    pub status: ItemStatus,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema, new)]
 pub struct AddItemResponse {
     pub added_item_id: i64,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct GetItemResponse {
+    #[serde(default)]
+    pub id: i64,
     pub name: String,
     pub table_id: i32,
+    /// Minutes to prepare the dish, from the menu or random for dishes not on it.
     pub time_to_prepare: i32,
     pub quantity: i32,
+    /// Version of the item, the same value as in its `ETag`.
+    #[serde(default)]
+    pub version: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub status: ItemStatus,
 }
 
 impl GetItemResponse {
     pub fn from_domain_item(item: Item) -> GetItemResponse {
         GetItemResponse {
+            id: item.id,
             name: item.name,
             table_id: item.table_id,
             time_to_prepare: item.time_to_prepare,
             quantity: item.quantity,
+            version: item.version,
+            notes: item.notes,
+            status: item.status.into(),
         }
     }
 }
@@ -36,32 +109,298 @@ impl GetItemResponse {
 fn map_domain_items_to_dto_items(items: Vec<Item>) -> Vec<GetItemResponse> {
     items
         .into_iter()
-        .map(|item| GetItemResponse::from_domain_item(item))
+        .map(GetItemResponse::from_domain_item)
         .collect()
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemSort {
+    #[default]
+    TableId,
+    CreatedAt,
+    Name,
+    TimeToPrepare,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageQuery {
+    /// Maximum number of items in the page, from 1 to 500. Defaults to 50.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+    /// Opaque cursor taken from `next_cursor` of the previous page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<ItemSort>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<SortOrder>,
+    /// Case-insensitive substring of the item name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Lowest table number, inclusive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub table_from: Option<i32>,
+    /// Highest table number, inclusive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub table_to: Option<i32>,
+    /// Only items created after this moment.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_after: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct GetItemForTableResponse {
     pub items: Vec<GetItemResponse>,
+    /// Cursor of the next page, absent on the last page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    /// Link to the next page, absent on the last page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
 }
 
 impl GetItemForTableResponse {
-    pub fn from_domain_items(items: Vec<Item>) -> GetItemForTableResponse {
+    pub fn from_domain_items(
+        items: Vec<Item>,
+        next_cursor: Option<String>,
+        next: Option<String>,
+    ) -> GetItemForTableResponse {
         GetItemForTableResponse {
             items: map_domain_items_to_dto_items(items),
+            next_cursor,
+            next,
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct GetAllItemsResponse {
     pub items: Vec<GetItemResponse>,
+    /// Cursor of the next page, absent on the last page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    /// Link to the next page, absent on the last page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
 }
 
 impl GetAllItemsResponse {
-    pub fn from_domain_items(items: Vec<Item>) -> GetAllItemsResponse {
+    pub fn from_domain_items(
+        items: Vec<Item>,
+        next_cursor: Option<String>,
+        next: Option<String>,
+    ) -> GetAllItemsResponse {
         GetAllItemsResponse {
             items: map_domain_items_to_dto_items(items),
+            next_cursor,
+            next,
         }
     }
 }
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOperationRequest {
+    Add {
+        name: String,
+        table_id: i32,
+        quantity: i32,
+    },
+    Remove {
+        item_id: i64,
+        quantity: i32,
+        /// Expected version of the item. The operation fails when the item was changed.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        version: Option<i32>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchModeRequest {
+    /// All operations are applied in one transaction or none of them.
+    #[default]
+    Atomic,
+    /// Every operation is applied on its own and reported separately.
+    Partial,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct BatchRequest {
+    #[serde(default)]
+    pub mode: BatchModeRequest,
+    pub operations: Vec<BatchOperationRequest>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BatchOperationResponse {
+    Added {
+        item_id: i64,
+    },
+    Removed,
+    Failed {
+        error: String,
+    },
+    /// Succeeded, but was rolled back because another operation of an atomic batch failed.
+    RolledBack,
+    /// Not executed because an earlier operation of an atomic batch failed.
+    Skipped,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct BatchResponse {
+    pub committed: bool,
+    pub results: Vec<BatchOperationResponse>,
+}
+
+impl BatchResponse {
+    pub fn from_batch_result(result: BatchResult, operations_count: usize) -> BatchResponse {
+        let committed = result.committed;
+        let mut results: Vec<BatchOperationResponse> = result
+            .outcomes
+            .into_iter()
+            .map(|outcome| match outcome {
+                Err(e) => BatchOperationResponse::Failed {
+                    error: e.to_string(),
+                },
+                Ok(_) if !committed => BatchOperationResponse::RolledBack,
+                Ok(BatchOutcome::Added(item_id)) => BatchOperationResponse::Added { item_id },
+                Ok(BatchOutcome::Removed) => BatchOperationResponse::Removed,
+            })
+            .collect();
+        results.resize_with(operations_count, || BatchOperationResponse::Skipped);
+        BatchResponse { committed, results }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct LoginRequest {
+    pub staff_id: i32,
+    pub pin: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct SessionResponse {
+    /// Bearer token of the session, sent as `Authorization: Bearer <token>`.
+    pub token: String,
+    pub staff_id: i32,
+    pub name: String,
+    pub role: Role,
+    /// The session is logged out when it is not used for this many seconds.
+    pub idle_timeout_secs: u64,
+    /// The session ends after this many seconds even when it is used.
+    pub expires_in_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct AddStaffRequest {
+    pub name: String,
+    pub role: Role,
+    /// PIN of 4 to 8 digits the staff member logs in with.
+    pub pin: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema, new)]
+pub struct AddStaffResponse {
+    pub staff_id: i32,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct AssignWaitersRequest {
+    /// Waiters serving the table, replacing the ones assigned before.
+    pub staff_ids: Vec<i32>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema, new)]
+pub struct TableWaitersResponse {
+    pub table_id: i32,
+    pub staff_ids: Vec<i32>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema, new)]
+pub struct TableItemsResponse {
+    pub table_id: i32,
+    pub items: Vec<GetItemResponse>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct GetStaffTablesResponse {
+    pub staff_id: i32,
+    /// Tables the staff member is assigned to, ordered by table id.
+    pub tables: Vec<TableItemsResponse>,
+}
+
+impl GetStaffTablesResponse {
+    /// Groups `items` by the tables in `table_ids`, tables without items are kept empty.
+    pub fn from_domain_items(
+        staff_id: i32,
+        table_ids: Vec<i32>,
+        items: Vec<Item>,
+    ) -> GetStaffTablesResponse {
+        let mut tables: Vec<TableItemsResponse> = table_ids
+            .into_iter()
+            .map(|table_id| TableItemsResponse::new(table_id, Vec::new()))
+            .collect();
+        for item in items {
+            if let Some(table) = tables
+                .iter_mut()
+                .find(|table| table.table_id == item.table_id)
+            {
+                table.items.push(GetItemResponse::from_domain_item(item));
+            }
+        }
+        GetStaffTablesResponse { staff_id, tables }
+    }
+}
+
+/// Event pushed to staff members on `GET /notifications`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Notification {
+    /// The kitchen marked an item of a table the waiter is assigned to as ready.
+    ItemReady {
+        item_id: i64,
+        table_id: i32,
+        name: String,
+        quantity: i32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Up,
+    Down,
+}
+
+/// Result of checking one dependency of the server.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DependencyHealth {
+    pub status: HealthStatus,
+    /// How long the check took.
+    pub duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Migrations which are not applied to the database yet.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pending_migrations: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct HealthResponse {
+    /// `up` when every dependency is up.
+    pub status: HealthStatus,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checks: BTreeMap<String, DependencyHealth>,
+}
@@ -1,24 +1,97 @@
+use actix_web::http::header;
 use actix_web::{HttpResponse, ResponseError};
 use derive_more::{Display, From};
 use persistence::error::DbError;
+use std::time::Duration;
+
+use crate::policy::Permission;
 
 #[derive(Debug, Display, From)]
 pub enum ServerError {
     NotFound,
+    #[from(ignore)]
+    BadRequest(String),
+    #[from(ignore)]
+    Unauthorized(String),
+    #[display(fmt = "missing permission {}", _0)]
+    Forbidden(Permission),
+    #[from(ignore)]
+    Internal(String),
+    /// The client sent too many requests and may retry after the given time.
+    #[display(fmt = "too many requests")]
+    #[from(ignore)]
+    TooManyRequests(Duration),
+    /// The body is larger than the given number of bytes.
+    #[display(fmt = "the request body must not be larger than {} bytes", _0)]
+    #[from(ignore)]
+    PayloadTooLarge(usize),
     DbError(DbError),
 }
 impl std::error::Error for ServerError {}
 
+impl ServerError {
+    /// Name of the variant, used as label of the error metrics.
+    pub fn variant(&self) -> &'static str {
+        match self {
+            ServerError::NotFound => "not_found",
+            ServerError::BadRequest(_) => "bad_request",
+            ServerError::Unauthorized(_) => "unauthorized",
+            ServerError::Forbidden(_) => "forbidden",
+            ServerError::Internal(_) => "internal",
+            ServerError::TooManyRequests(_) => "too_many_requests",
+            ServerError::PayloadTooLarge(_) => "payload_too_large",
+            ServerError::DbError(DbError::Unavailable(_)) => "db_unavailable",
+            ServerError::DbError(_) => "db_error",
+        }
+    }
+}
+
+/// Retry-After only has a precision of seconds, rounding down would make clients retry
+/// too early.
+fn retry_after_seconds(retry_after: Duration) -> u64 {
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    seconds.max(1)
+}
+
 impl ResponseError for ServerError {
     fn error_response(&self) -> HttpResponse {
         match self {
             ServerError::NotFound => HttpResponse::NotFound().finish(),
+            ServerError::BadRequest(message) => HttpResponse::BadRequest().body(message.clone()),
+            ServerError::Unauthorized(message) => HttpResponse::Unauthorized()
+                .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+                .body(message.clone()),
+            ServerError::Forbidden(permission) => {
+                HttpResponse::Forbidden().body(format!("missing permission {}", permission))
+            }
+            ServerError::Internal(message) => {
+                HttpResponse::InternalServerError().body(message.clone())
+            }
+            ServerError::TooManyRequests(retry_after) => HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after_seconds(*retry_after)))
+                .body("too many requests"),
+            ServerError::PayloadTooLarge(_) => {
+                HttpResponse::PayloadTooLarge().body(self.to_string())
+            }
             ServerError::DbError(DbError::MigrateError(e)) => {
                 HttpResponse::InternalServerError().body(e.to_string())
             }
+            ServerError::DbError(e @ (DbError::Irreversible(_) | DbError::NotConfirmed(_))) => {
+                HttpResponse::InternalServerError().body(e.to_string())
+            }
             ServerError::DbError(DbError::SqlxError(e)) => {
                 HttpResponse::InternalServerError().body(e.to_string())
             }
+            ServerError::DbError(e @ DbError::MergedNotesTooLong(_)) => {
+                HttpResponse::Conflict().body(e.to_string())
+            }
+            ServerError::DbError(DbError::VersionMismatch) => HttpResponse::PreconditionFailed()
+                .body("the item was changed or removed since it was read"),
+            ServerError::DbError(DbError::Unavailable(retry_after)) => {
+                HttpResponse::ServiceUnavailable()
+                    .insert_header((header::RETRY_AFTER, retry_after_seconds(*retry_after)))
+                    .body("the database is unavailable")
+            }
         }
     }
 }
@@ -0,0 +1,94 @@
+use actix_web::http::header::{EntityTag, IfMatch, IfNoneMatch};
+use persistence::dao::ItemDao;
+use persistence::error::DbError;
+use sha2::{Digest, Sha256};
+
+use crate::errors::ServerError;
+
+pub fn item_etag(version: i32) -> EntityTag {
+    EntityTag::new_strong(version.to_string())
+}
+
+/// Tag of a list of items, which changes whenever an item is added, removed or updated.
+pub fn items_etag(items: &[ItemDao]) -> EntityTag {
+    let mut hasher = Sha256::new();
+    for item in items {
+        hasher.update(format!("{}:{};", item.id, item.version));
+    }
+    let hash = format!("{:x}", hasher.finalize());
+    EntityTag::new_strong(hash[..16].to_string())
+}
+
+/// Returns the item version expected by an `If-Match` header. `If-Match: *` only requires
+/// the item to exist and yields no version.
+pub fn expected_version(if_match: Option<&IfMatch>) -> Result<Option<i32>, ServerError> {
+    match if_match {
+        None | Some(IfMatch::Any) => Ok(None),
+        Some(IfMatch::Items(tags)) => match tags.as_slice() {
+            // The typed header parses a missing header as an empty list.
+            [] => Ok(None),
+            // Weak tags never match in If-Match, as it uses the strong comparison.
+            [tag] if !tag.weak => tag
+                .tag()
+                .parse()
+                .map(Some)
+                .map_err(|_| ServerError::DbError(DbError::VersionMismatch)),
+            [_] => Err(ServerError::DbError(DbError::VersionMismatch)),
+            _ => Err(ServerError::BadRequest(
+                "If-Match must contain a single entity tag".to_string(),
+            )),
+        },
+    }
+}
+
+/// Whether an `If-None-Match` header matches `etag`, so the client's copy is up to date.
+pub fn is_not_modified(if_none_match: Option<&IfNoneMatch>, etag: &EntityTag) -> bool {
+    match if_none_match {
+        None => false,
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_expected_version() {
+        assert_eq!(expected_version(None).unwrap(), None);
+        assert_eq!(
+            expected_version(Some(&IfMatch::Items(vec![]))).unwrap(),
+            None
+        );
+        assert_eq!(expected_version(Some(&IfMatch::Any)).unwrap(), None);
+
+        let if_match = IfMatch::Items(vec![item_etag(3)]);
+        assert_eq!(expected_version(Some(&if_match)).unwrap(), Some(3));
+
+        let weak = IfMatch::Items(vec![EntityTag::new_weak("3".to_string())]);
+        assert!(matches!(
+            expected_version(Some(&weak)),
+            Err(ServerError::DbError(_))
+        ));
+
+        let several = IfMatch::Items(vec![item_etag(3), item_etag(4)]);
+        assert!(matches!(
+            expected_version(Some(&several)),
+            Err(ServerError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_items_etag_changes_with_versions() {
+        let item = ItemDao::test();
+        let mut updated = item.clone();
+        updated.version += 1;
+
+        let items = vec![item];
+
+        assert_eq!(items_etag(&items), items_etag(&items.clone()));
+        assert_ne!(items_etag(&items), items_etag(&[updated]));
+        assert_ne!(items_etag(&items), items_etag(&[]));
+    }
+}
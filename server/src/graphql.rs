@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use actix_web::{get, post, web, HttpResponse};
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{
+    ComplexObject, Context, EmptySubscription, InputObject, Object, Schema, SimpleObject,
+};
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use domain::item::Item;
+use persistence::dao::ItemDao;
+use persistence::error::DbError;
+use persistence::item_repository::ItemRepository;
+use persistence::postgres_repositories::PgRepositories;
+use persistence::query::{self, ItemQuery, ItemSortField, SortDirection};
+
+use crate::auth::StaffIdentity;
+use crate::handlers::{
+    random_time_to_prepare, validate_quantity_to_add, validate_quantity_to_remove,
+    version_to_remove,
+};
+use crate::pagination::MAX_PAGE_LIMIT;
+use crate::policy::Permission;
+
+pub type RestaurantSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+/// Checks that the staff member sending the request has `permission`.
+fn require(ctx: &Context<'_>, permission: Permission) -> async_graphql::Result<()> {
+    ctx.data::<StaffIdentity>()?.require(permission)?;
+    Ok(())
+}
+
+/// Tables and items refer to each other, so without limits a single query could nest
+/// `items { table { items ... } }` as deep as it likes. Lists of table ids are capped at
+/// 100 by the validators of the `tables` and `items` arguments.
+const MAX_QUERY_DEPTH: usize = 8;
+const MAX_QUERY_COMPLEXITY: usize = 1000;
+const MAX_RECURSIVE_DEPTH: usize = 16;
+
+pub fn build_schema(repositories: PgRepositories) -> RestaurantSchema {
+    Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+        .limit_depth(MAX_QUERY_DEPTH)
+        .limit_complexity(MAX_QUERY_COMPLEXITY)
+        .limit_recursive_depth(MAX_RECURSIVE_DEPTH)
+        .data(repositories)
+        .finish()
+}
+
+/// Returns the first `MAX_PAGE_LIMIT` items matching `filter`, ordered by table.
+async fn query_items(
+    ctx: &Context<'_>,
+    filter: query::ItemFilter,
+) -> async_graphql::Result<Vec<ItemObject>> {
+    let repositories = ctx.data::<PgRepositories>()?;
+    let page = repositories
+        .item_repository
+        .query_items(ItemQuery {
+            filter,
+            sort: ItemSortField::TableId,
+            direction: SortDirection::Asc,
+            limit: MAX_PAGE_LIMIT,
+            cursor: None,
+        })
+        .await?;
+    Ok(page.items.into_iter().map(ItemObject::from_dao).collect())
+}
+
+/// Loads the items of many tables with a single repository call, so that resolving
+/// `items` on a list of tables does not issue one query per table.
+pub struct TableItemsLoader {
+    repositories: PgRepositories,
+}
+
+impl Loader<i32> for TableItemsLoader {
+    type Value = Vec<ItemDao>;
+    type Error = Arc<DbError>;
+
+    async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+        let items = self
+            .repositories
+            .item_repository
+            .get_items_for_tables(keys)
+            .await
+            .map_err(Arc::new)?;
+
+        let mut result: HashMap<i32, Vec<ItemDao>> =
+            keys.iter().map(|key| (*key, Vec::new())).collect();
+        for item in items {
+            result.entry(item.table_id).or_default().push(item);
+        }
+        Ok(result)
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Item", complex)]
+pub struct ItemObject {
+    pub id: i64,
+    pub name: String,
+    pub table_id: i32,
+    pub time_to_prepare: i32,
+    pub quantity: i32,
+}
+
+impl ItemObject {
+    pub fn from_dao(item_dao: ItemDao) -> ItemObject {
+        ItemObject {
+            id: item_dao.id,
+            name: item_dao.name,
+            table_id: item_dao.table_id,
+            time_to_prepare: item_dao.time_to_prepare,
+            quantity: item_dao.quantity,
+        }
+    }
+}
+
+#[ComplexObject]
+impl ItemObject {
+    async fn table(&self) -> TableObject {
+        TableObject { id: self.table_id }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Table", complex)]
+pub struct TableObject {
+    pub id: i32,
+}
+
+#[ComplexObject]
+impl TableObject {
+    async fn items(
+        &self,
+        ctx: &Context<'_>,
+        name_contains: Option<String>,
+    ) -> async_graphql::Result<Vec<ItemObject>> {
+        require(ctx, Permission::ViewItems)?;
+        if name_contains.is_some() {
+            return query_items(
+                ctx,
+                query::ItemFilter {
+                    name_contains,
+                    table_id_from: Some(self.id),
+                    table_id_to: Some(self.id),
+                    ..Default::default()
+                },
+            )
+            .await;
+        }
+        let loader = ctx.data_unchecked::<DataLoader<TableItemsLoader>>();
+        let items = loader.load_one(self.id).await?.unwrap_or_default();
+        Ok(items.into_iter().map(ItemObject::from_dao).collect())
+    }
+}
+
+#[derive(InputObject, Default)]
+pub struct ItemFilter {
+    #[graphql(validator(max_items = 100))]
+    pub table_ids: Option<Vec<i32>>,
+    pub name_contains: Option<String>,
+}
+
+#[derive(InputObject)]
+pub struct AddItemInput {
+    pub name: String,
+    pub table_id: i32,
+    pub quantity: i32,
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn item(&self, ctx: &Context<'_>, id: i64) -> async_graphql::Result<Option<ItemObject>> {
+        require(ctx, Permission::ViewItems)?;
+        let repositories = ctx.data::<PgRepositories>()?;
+        let item = repositories.item_repository.get_item(id).await?;
+        Ok(item.map(ItemObject::from_dao))
+    }
+
+    async fn items(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: ItemFilter,
+    ) -> async_graphql::Result<Vec<ItemObject>> {
+        require(
+            ctx,
+            match filter.table_ids {
+                Some(_) => Permission::ViewItems,
+                None => Permission::ViewAllItems,
+            },
+        )?;
+        query_items(
+            ctx,
+            query::ItemFilter {
+                name_contains: filter.name_contains,
+                table_ids: filter.table_ids,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    async fn table(&self, id: i32) -> TableObject {
+        TableObject { id }
+    }
+
+    #[graphql(complexity = "ids.len() * child_complexity")]
+    async fn tables(
+        &self,
+        #[graphql(validator(max_items = 100))] ids: Vec<i32>,
+    ) -> Vec<TableObject> {
+        ids.into_iter().map(|id| TableObject { id }).collect()
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    async fn add_item(
+        &self,
+        ctx: &Context<'_>,
+        input: AddItemInput,
+    ) -> async_graphql::Result<ItemObject> {
+        require(ctx, Permission::AddItem)?;
+        validate_quantity_to_add(input.quantity)?;
+        let staff = ctx.data::<StaffIdentity>()?;
+        let repositories = ctx.data::<PgRepositories>()?;
+        let item = Item::new(
+            input.name,
+            input.table_id,
+            random_time_to_prepare(),
+            input.quantity,
+        );
+        let item_id = repositories
+            .item_repository
+            .add_item(item.to_insert_dao(), &staff.id)
+            .await?;
+        let item = repositories
+            .item_repository
+            .get_item(item_id)
+            .await?
+            .ok_or("added item was not found")?;
+        Ok(ItemObject::from_dao(item))
+    }
+
+    async fn remove_item(
+        &self,
+        ctx: &Context<'_>,
+        id: i64,
+        quantity: i32,
+    ) -> async_graphql::Result<bool> {
+        require(ctx, Permission::RemoveItem)?;
+        let staff = ctx.data::<StaffIdentity>()?;
+        let repositories = ctx.data::<PgRepositories>()?;
+        validate_quantity_to_remove(quantity)?;
+        let expected_version = version_to_remove(staff, repositories, id, None).await?;
+        repositories
+            .item_repository
+            .remove_item(id, quantity, expected_version, &staff.id)
+            .await?;
+        Ok(true)
+    }
+}
+
+#[post("/graphql")]
+pub async fn graphql(
+    staff: StaffIdentity,
+    schema: web::Data<RestaurantSchema>,
+    repositories: web::Data<PgRepositories>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    let loader = DataLoader::new(
+        TableItemsLoader {
+            repositories: repositories.get_ref().clone(),
+        },
+        actix_web::rt::spawn,
+    );
+    schema
+        .execute(request.into_inner().data(loader).data(staff))
+        .await
+        .into()
+}
+
+#[get("/graphql")]
+pub async fn graphiql() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::auth::{authenticate_as_manager, test_identity};
+    use crate::policy::Role;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App};
+    use async_graphql::Request;
+    use persistence::{init_test_db, reset_test_db};
+    use serde_json::{json, Value};
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_query_tables_with_items() {
+        let item_repository = init_test_db().await;
+        let repositories = PgRepositories::new(item_repository.clone());
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate_as_manager))
+                .app_data(web::Data::new(build_schema(repositories.clone())))
+                .app_data(web::Data::new(repositories))
+                .service(graphql),
+        )
+        .await;
+
+        for item in [
+            Item::new("sushi".to_string(), 1, 10, 2),
+            Item::new("onigiri".to_string(), 1, 10, 1),
+            Item::new("ramen".to_string(), 2, 10, 1),
+            Item::new("sushi".to_string(), 3, 10, 1),
+        ] {
+            item_repository
+                .add_item(item.to_insert_dao(), "test")
+                .await
+                .unwrap();
+        }
+
+        let request = test::TestRequest::post()
+            .uri("/graphql")
+            .set_json(json!({
+                "query": "{ tables(ids: [1, 2]) { id items { name quantity } } }"
+            }))
+            .to_request();
+        let result: Value = test::call_and_read_body_json(&app, request).await;
+
+        assert_eq!(
+            result["data"]["tables"],
+            json!([
+                { "id": 1, "items": [
+                    { "name": "sushi", "quantity": 2 },
+                    { "name": "onigiri", "quantity": 1 }
+                ] },
+                { "id": 2, "items": [{ "name": "ramen", "quantity": 1 }] }
+            ])
+        );
+
+        let request = test::TestRequest::post()
+            .uri("/graphql")
+            .set_json(json!({
+                "query": r#"{ items(filter: { nameContains: "sush" }) { tableId table { id } } }"#
+            }))
+            .to_request();
+        let result: Value = test::call_and_read_body_json(&app, request).await;
+
+        assert_eq!(
+            result["data"]["items"],
+            json!([
+                { "tableId": 1, "table": { "id": 1 } },
+                { "tableId": 3, "table": { "id": 3 } }
+            ])
+        );
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_add_and_remove_item_mutations() {
+        let item_repository = init_test_db().await;
+        let schema = build_schema(PgRepositories::new(item_repository.clone()));
+
+        let manager = || test_identity(Role::Manager);
+
+        let result = schema
+            .execute(Request::new(r#"mutation { addItem(input: { name: "sushi", tableId: 4, quantity: 3 }) { id quantity timeToPrepare } }"#).data(manager()))
+            .await;
+        assert!(result.errors.is_empty());
+        let data = result.data.into_json().unwrap();
+        let item_id = data["addItem"]["id"].as_i64().unwrap();
+        let time_to_prepare = data["addItem"]["timeToPrepare"].as_i64().unwrap();
+        assert_eq!(data["addItem"]["quantity"], 3);
+        assert!((5..=15).contains(&time_to_prepare));
+
+        let result = schema
+            .execute(
+                Request::new(format!(
+                    "mutation {{ removeItem(id: {}, quantity: 3) }}",
+                    item_id
+                ))
+                .data(manager()),
+            )
+            .await;
+        assert!(result.errors.is_empty());
+
+        let item = item_repository.get_item(item_id).await.unwrap();
+        assert!(item.is_none());
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_limits_of_queries() {
+        let item_repository = init_test_db().await;
+        let schema = build_schema(PgRepositories::new(item_repository.clone()));
+        let manager = || test_identity(Role::Manager);
+
+        let too_deep =
+            "{ tables(ids: [1]) { items { table { items { table { items { table { items { name } } } } } } } } }";
+        let result = schema.execute(Request::new(too_deep).data(manager())).await;
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].message, "Query is nested too deep.");
+
+        let ids: Vec<String> = (1..=101).map(|id| id.to_string()).collect();
+        for query in [
+            format!("{{ tables(ids: [{}]) {{ id }} }}", ids.join(", ")),
+            format!(
+                "{{ items(filter: {{ tableIds: [{}] }}) {{ id }} }}",
+                ids.join(", ")
+            ),
+        ] {
+            let result = schema.execute(Request::new(query).data(manager())).await;
+            assert_eq!(result.errors.len(), 1);
+        }
+
+        let query = format!("{{ tables(ids: [{}]) {{ id }} }}", ids[..100].join(", "));
+        let result = schema.execute(Request::new(query).data(manager())).await;
+        assert!(result.errors.is_empty());
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+}
@@ -1,118 +1,842 @@
+use crate::auth::{bearer_token, StaffIdentity};
+use crate::config::SessionConfig;
 use crate::dto::*;
 use crate::errors::ServerError;
+use crate::etag::{expected_version, is_not_modified, item_etag, items_etag};
+use crate::logging::{record_item_id, record_table_id};
+use crate::notifications::{event_stream, notify_when_ready, Notifier};
+use crate::policy::{require, Authorized, Permission, Role};
+use actix_web::http::header::{ETag, IfMatch, IfNoneMatch};
 use actix_web::web::{self, Json};
-use actix_web::{delete, get, post, HttpResponse};
+use actix_web::{delete, get, patch, post, put, HttpRequest, HttpResponse};
 use domain::item::Item;
 
+use persistence::batch::{BatchMode, BatchOperation};
+use persistence::dao::{InsertStaffDao, UpdateItemDao, MAX_NOTES_LENGTH};
 use persistence::item_repository::ItemRepository;
 use persistence::postgres_repositories::PgRepositories;
+use persistence::staff_repository::StaffRepository;
 use rand::Rng;
 
+use crate::session::{
+    hash_pin, new_session_token, session_token_hash, validate_pin, verify_pin_blocking,
+};
+
+pub(crate) fn random_time_to_prepare() -> i32 {
+    rand::thread_rng().gen_range(5..=15)
+}
+
+/// Returns the version the item has to have when it is removed by `staff`. Prepared items
+/// can only be removed with `RemovePreparedItem`, for everyone else the version is pinned
+/// to the one just checked, so that the item can't become prepared before it is removed.
+pub(crate) async fn version_to_remove(
+    staff: &StaffIdentity,
+    repositories: &PgRepositories,
+    item_id: i64,
+    expected_version: Option<i32>,
+) -> Result<Option<i32>, ServerError> {
+    if staff.role.has(Permission::RemovePreparedItem) {
+        return Ok(expected_version);
+    }
+    match repositories.item_repository.get_item(item_id).await? {
+        Some(item) if item.status.is_prepared() => {
+            Err(ServerError::Forbidden(Permission::RemovePreparedItem))
+        }
+        Some(item) => Ok(expected_version.or(Some(item.version))),
+        None => Ok(expected_version),
+    }
+}
+
+/// Returns the version the item has to have when its quantity is changed to `quantity`
+/// by `staff`. Lowering the quantity removes part of the item, so it follows the rules of
+/// [`version_to_remove`].
+pub(crate) async fn version_to_update(
+    staff: &StaffIdentity,
+    repositories: &PgRepositories,
+    item_id: i64,
+    quantity: Option<i32>,
+    expected_version: Option<i32>,
+) -> Result<Option<i32>, ServerError> {
+    let Some(quantity) = quantity else {
+        return Ok(expected_version);
+    };
+    if staff.role.has(Permission::RemovePreparedItem) {
+        return Ok(expected_version);
+    }
+    match repositories.item_repository.get_item(item_id).await? {
+        Some(item) if quantity < item.quantity && item.status.is_prepared() => {
+            Err(ServerError::Forbidden(Permission::RemovePreparedItem))
+        }
+        Some(item) if quantity < item.quantity => Ok(expected_version.or(Some(item.version))),
+        _ => Ok(expected_version),
+    }
+}
+
+/// Rejects adding less than one of a dish, which would reduce an ordered item instead.
+pub(crate) fn validate_quantity_to_add(quantity: i32) -> Result<(), ServerError> {
+    if quantity < 1 {
+        return Err(ServerError::BadRequest(
+            "quantity must be at least 1".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Rejects removing less than one of a dish, which would add portions to the item instead.
+pub(crate) fn validate_quantity_to_remove(quantity: i32) -> Result<(), ServerError> {
+    validate_quantity_to_add(quantity)
+}
+
+#[utoipa::path(
+    post,
+    path = "/item",
+    tag = "items",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Replays the stored response when the request is repeated with the same key")),
+    request_body = AddItemRequest,
+    responses(
+        (status = 200, description = "Item added or merged into an existing one. A dish on the menu takes its time to prepare, other dishes a random time between 5 and 15 minutes", body = AddItemResponse),
+        (status = 400, description = "The quantity is less than 1", body = String),
+        (status = 403, description = "Missing `add_item`", body = String),
+        (status = 413, description = "The body is larger than the configured limit", body = String),
+        (status = 500, description = "Database error", body = String),
+    )
+)]
 #[post("/item")]
 pub async fn add_item(
+    staff: Authorized<require::AddItem>,
     item: Json<AddItemRequest>,
     repositories: web::Data<PgRepositories>,
 ) -> Result<HttpResponse, ServerError> {
-    let mut rnd = rand::thread_rng();
+    record_table_id(item.table_id);
+    validate_quantity_to_add(item.quantity)?;
     let item = Item::new(
         item.name.clone(),
         item.table_id,
-        rnd.gen_range(5..=15),
+        random_time_to_prepare(),
         item.quantity,
     );
 
     let result = repositories
         .item_repository
-        .add_item(item.to_insert_dao())
+        .add_item(item.to_insert_dao(), &staff.identity.id)
         .await;
 
     match result {
-        Ok(item_id) => Ok(HttpResponse::Ok().json(AddItemResponse::new(item_id))),
+        Ok(item_id) => {
+            record_item_id(item_id);
+            Ok(HttpResponse::Ok().json(AddItemResponse::new(item_id)))
+        }
         Err(e) => Err(ServerError::from(e)),
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/item/{item_id}",
+    tag = "items",
+    params(
+        ("item_id" = i64, Path, description = "Id of the item"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a cached copy of the item"),
+    ),
+    responses(
+        (status = 200, description = "Item found", body = GetItemResponse,
+            headers(("ETag" = String, description = "Version of the item"))),
+        (status = 304, description = "The cached copy is up to date"),
+        (status = 404, description = "Item not found"),
+        (status = 403, description = "Missing `view_items`", body = String),
+        (status = 500, description = "Database error", body = String),
+    )
+)]
 #[get("/item/{item_id}")]
 pub async fn get_item(
+    _: Authorized<require::ViewItems>,
     item_id: web::Path<i64>,
+    if_none_match: Option<web::Header<IfNoneMatch>>,
     repositories: web::Data<PgRepositories>,
 ) -> Result<HttpResponse, ServerError> {
+    record_item_id(*item_id);
     let result = repositories.item_repository.get_item(*item_id).await;
 
     match result {
         Ok(Some(item)) => {
-            Ok(HttpResponse::Ok().json(GetItemResponse::from_domain_item(Item::from_dao(item))))
+            let etag = item_etag(item.version);
+            if is_not_modified(if_none_match.as_deref(), &etag) {
+                return Ok(HttpResponse::NotModified()
+                    .insert_header(ETag(etag))
+                    .finish());
+            }
+            Ok(HttpResponse::Ok()
+                .insert_header(ETag(etag))
+                .json(GetItemResponse::from_domain_item(Item::from_dao(item))))
         }
         Ok(None) => Err(ServerError::NotFound),
         Err(e) => Err(ServerError::from(e)),
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/table/{table_id}",
+    tag = "tables",
+    params(
+        ("table_id" = i32, Path, description = "Number of the table"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a cached copy of the page"),
+        PageQuery,
+    ),
+    responses(
+        (status = 200, description = "Page of items ordered for the table", body = GetItemForTableResponse,
+            headers(("ETag" = String, description = "Changes whenever an item of the page changes"))),
+        (status = 304, description = "The cached copy is up to date"),
+        (status = 400, description = "Invalid pagination parameters", body = String),
+        (status = 403, description = "Missing `view_items`", body = String),
+        (status = 500, description = "Database error", body = String),
+    )
+)]
 #[get("/table/{table_id}")]
 pub async fn get_items_for_table(
+    _: Authorized<require::ViewItems>,
+    request: HttpRequest,
     table_id: web::Path<i32>,
+    page: web::Query<PageQuery>,
+    if_none_match: Option<web::Header<IfNoneMatch>>,
     repositories: web::Data<PgRepositories>,
 ) -> Result<HttpResponse, ServerError> {
-    let result = repositories
-        .item_repository
-        .get_items_for_table(*table_id)
-        .await;
+    record_table_id(*table_id);
+    let query = page.to_item_query(Some(*table_id))?;
+    let result = repositories.item_repository.query_items(query).await;
     match result {
-        Ok(items) => {
-            let items = items.into_iter().map(|item| Item::from_dao(item)).collect();
-            Ok(HttpResponse::Ok().json(GetItemForTableResponse::from_domain_items(items)))
+        Ok(item_page) => {
+            let etag = items_etag(&item_page.items);
+            if is_not_modified(if_none_match.as_deref(), &etag) {
+                return Ok(HttpResponse::NotModified()
+                    .insert_header(ETag(etag))
+                    .finish());
+            }
+            let (next_cursor, next) =
+                page.next_page(request.path(), item_page.next_cursor.as_ref());
+            let items = item_page.items.into_iter().map(Item::from_dao).collect();
+            Ok(HttpResponse::Ok().insert_header(ETag(etag)).json(
+                GetItemForTableResponse::from_domain_items(items, next_cursor, next),
+            ))
         }
         Err(e) => Err(ServerError::from(e)),
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/items",
+    tag = "items",
+    params(PageQuery),
+    responses(
+        (status = 200, description = "Page of items of all tables", body = GetAllItemsResponse),
+        (status = 400, description = "Invalid pagination parameters", body = String),
+        (status = 403, description = "Missing `view_all_items`", body = String),
+        (status = 500, description = "Database error", body = String),
+    )
+)]
 #[get("/items")]
 pub async fn get_all_items(
+    _: Authorized<require::ViewAllItems>,
+    request: HttpRequest,
+    page: web::Query<PageQuery>,
     repositories: web::Data<PgRepositories>,
 ) -> Result<HttpResponse, ServerError> {
-    let result = repositories.item_repository.get_all_items().await;
+    let query = page.to_item_query(None)?;
+    let result = repositories.item_repository.query_items(query).await;
     match result {
-        Ok(items) => {
-            let items = items.into_iter().map(|item| Item::from_dao(item)).collect();
-            Ok(HttpResponse::Ok().json(GetAllItemsResponse::from_domain_items(items)))
+        Ok(item_page) => {
+            let (next_cursor, next) =
+                page.next_page(request.path(), item_page.next_cursor.as_ref());
+            let items = item_page.items.into_iter().map(Item::from_dao).collect();
+            Ok(
+                HttpResponse::Ok().json(GetAllItemsResponse::from_domain_items(
+                    items,
+                    next_cursor,
+                    next,
+                )),
+            )
         }
         Err(e) => Err(ServerError::from(e)),
     }
 }
 
+#[utoipa::path(
+    delete,
+    path = "/item/{item_id}/{quantity}",
+    tag = "items",
+    params(
+        ("item_id" = i64, Path, description = "Id of the item"),
+        ("quantity" = i32, Path, description = "Quantity to remove. The item is deleted when it reaches zero"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Replays the stored response when the request is repeated with the same key"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the item. The removal fails when the item was changed since"),
+    ),
+    responses(
+        (status = 200, description = "Quantity removed",
+            headers(("ETag" = String, description = "New version of the item, absent when it was deleted"))),
+        (status = 400, description = "Quantity below 1, or If-Match contains more than one entity tag", body = String),
+        (status = 403, description = "Missing `remove_item`, or `remove_prepared_item` for items which are ready or served", body = String),
+        (status = 412, description = "The item was changed or removed since it was read", body = String),
+        (status = 500, description = "Database error", body = String),
+    )
+)]
 #[delete("/item/{item_id}/{quantity}")]
 pub async fn remove_item(
+    staff: Authorized<require::RemoveItem>,
     path: web::Path<(i64, i32)>,
+    if_match: Option<web::Header<IfMatch>>,
     repositories: web::Data<PgRepositories>,
 ) -> Result<HttpResponse, ServerError> {
     let (item_id, quantity) = path.into_inner();
+    record_item_id(item_id);
+    validate_quantity_to_remove(quantity)?;
+    let expected_version = expected_version(if_match.as_deref())?;
+    let expected_version =
+        version_to_remove(&staff.identity, &repositories, item_id, expected_version).await?;
+    let result = repositories
+        .item_repository
+        .remove_item(item_id, quantity, expected_version, &staff.identity.id)
+        .await;
+    match result {
+        Ok(Some(item)) => Ok(HttpResponse::Ok()
+            .insert_header(ETag(item_etag(item.version)))
+            .finish()),
+        Ok(None) => Ok(HttpResponse::Ok().finish()),
+        Err(e) => Err(ServerError::from(e)),
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/item/{item_id}",
+    tag = "items",
+    params(
+        ("item_id" = i64, Path, description = "Id of the item"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Replays the stored response when the request is repeated with the same key"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the item. The update fails when the item was changed since"),
+    ),
+    request_body = UpdateItemRequest,
+    responses(
+        (status = 200, description = "Updated item. When it was moved onto the same dish of another table, the merged item with the notes of both", body = GetItemResponse,
+            headers(("ETag" = String, description = "New version of the item"))),
+        (status = 400, description = "Invalid changes", body = String),
+        (status = 404, description = "Item not found"),
+        (status = 409, description = "The notes of the merged items are too long together", body = String),
+        (status = 412, description = "The item was changed or removed since it was read", body = String),
+        (status = 403, description = "Missing `update_item`, or `remove_prepared_item` to lower the quantity of items which are ready or served", body = String),
+        (status = 500, description = "Database error", body = String),
+    )
+)]
+#[patch("/item/{item_id}")]
+pub async fn update_item(
+    staff: Authorized<require::UpdateItem>,
+    item_id: web::Path<i64>,
+    update: Json<UpdateItemRequest>,
+    if_match: Option<web::Header<IfMatch>>,
+    repositories: web::Data<PgRepositories>,
+) -> Result<HttpResponse, ServerError> {
+    record_item_id(*item_id);
+    let update = update.into_inner();
+    if update.quantity.is_none() && update.table_id.is_none() && update.notes.is_none() {
+        return Err(ServerError::BadRequest("nothing to update".to_string()));
+    }
+    if update.quantity.is_some_and(|quantity| quantity < 1) {
+        return Err(ServerError::BadRequest(
+            "quantity must be at least 1, use DELETE to remove the item".to_string(),
+        ));
+    }
+    if update
+        .notes
+        .as_ref()
+        .is_some_and(|notes| notes.chars().count() > MAX_NOTES_LENGTH)
+    {
+        return Err(ServerError::BadRequest(format!(
+            "notes can be at most {} characters long",
+            MAX_NOTES_LENGTH
+        )));
+    }
+
+    let expected_version = expected_version(if_match.as_deref())?;
+    let expected_version = version_to_update(
+        &staff.identity,
+        &repositories,
+        *item_id,
+        update.quantity,
+        expected_version,
+    )
+    .await?;
+    let result = repositories
+        .item_repository
+        .update_item(
+            *item_id,
+            UpdateItemDao::new(update.quantity, update.table_id, update.notes),
+            expected_version,
+            &staff.identity.id,
+        )
+        .await;
+    match result {
+        Ok(Some(item)) => Ok(HttpResponse::Ok()
+            .insert_header(ETag(item_etag(item.version)))
+            .json(GetItemResponse::from_domain_item(Item::from_dao(item)))),
+        Ok(None) => Err(ServerError::NotFound),
+        Err(e) => Err(ServerError::from(e)),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/item/{item_id}/status",
+    tag = "items",
+    params(
+        ("item_id" = i64, Path, description = "Id of the item"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Replays the stored response when the request is repeated with the same key"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the item. The change fails when the item was changed since"),
+    ),
+    request_body = SetItemStatusRequest,
+    responses(
+        (status = 200, description = "Item with the new status. When it is ready, the waiters of its table are notified", body = GetItemResponse,
+            headers(("ETag" = String, description = "New version of the item"))),
+        (status = 403, description = "Missing `change_item_status`", body = String),
+        (status = 404, description = "Item not found"),
+        (status = 412, description = "The item was changed or removed since it was read", body = String),
+        (status = 500, description = "Database error", body = String),
+    )
+)]
+#[put("/item/{item_id}/status")]
+pub async fn set_item_status(
+    staff: Authorized<require::ChangeItemStatus>,
+    item_id: web::Path<i64>,
+    request: Json<SetItemStatusRequest>,
+    if_match: Option<web::Header<IfMatch>>,
+    repositories: web::Data<PgRepositories>,
+    notifier: web::Data<Notifier>,
+) -> Result<HttpResponse, ServerError> {
+    record_item_id(*item_id);
+    let expected_version = expected_version(if_match.as_deref())?;
+    let update = UpdateItemDao {
+        status: Some(request.status.into()),
+        ..Default::default()
+    };
     let result = repositories
         .item_repository
-        .remove_item(item_id, quantity)
+        .update_item(*item_id, update, expected_version, &staff.identity.id)
         .await;
     match result {
-        Ok(_) => Ok(HttpResponse::Ok().finish()),
+        Ok(Some(item)) => {
+            notify_when_ready(&repositories, &notifier, &item).await;
+            Ok(HttpResponse::Ok()
+                .insert_header(ETag(item_etag(item.version)))
+                .json(GetItemResponse::from_domain_item(Item::from_dao(item))))
+        }
+        Ok(None) => Err(ServerError::NotFound),
         Err(e) => Err(ServerError::from(e)),
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/table/{from_table_id}/transfer/{to_table_id}",
+    tag = "tables",
+    params(
+        ("from_table_id" = i32, Path, description = "Number of the table the items are moved from"),
+        ("to_table_id" = i32, Path, description = "Number of the table the items are moved to"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Replays the stored response when the request is repeated with the same key"),
+    ),
+    responses(
+        (status = 200, description = "All items of the target table after the transfer", body = GetItemForTableResponse),
+        (status = 400, description = "Both tables are the same", body = String),
+        (status = 403, description = "Missing `transfer_table`", body = String),
+        (status = 409, description = "The notes of a dish ordered on both tables are too long together", body = String),
+        (status = 500, description = "Database error", body = String),
+    )
+)]
+#[post("/table/{from_table_id}/transfer/{to_table_id}")]
+pub async fn transfer_table(
+    staff: Authorized<require::TransferTable>,
+    path: web::Path<(i32, i32)>,
+    repositories: web::Data<PgRepositories>,
+) -> Result<HttpResponse, ServerError> {
+    let (from_table_id, to_table_id) = path.into_inner();
+    record_table_id(from_table_id);
+    if from_table_id == to_table_id {
+        return Err(ServerError::BadRequest(
+            "items can't be transferred to the same table".to_string(),
+        ));
+    }
+
+    let result = repositories
+        .item_repository
+        .transfer_table(from_table_id, to_table_id, &staff.identity.id)
+        .await;
+    match result {
+        Ok(items) => {
+            let items = items.into_iter().map(Item::from_dao).collect();
+            Ok(
+                HttpResponse::Ok().json(GetItemForTableResponse::from_domain_items(
+                    items, None, None,
+                )),
+            )
+        }
+        Err(e) => Err(ServerError::from(e)),
+    }
+}
+
+pub const MAX_BATCH_OPERATIONS: usize = 100;
+
+#[utoipa::path(
+    post,
+    path = "/items:batch",
+    tag = "items",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Replays the stored response when the request is repeated with the same key")),
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "Batch executed, see the result of every operation", body = BatchResponse),
+        (status = 400, description = "Too many operations", body = String),
+        (status = 403, description = "Missing a permission required by an operation", body = String),
+        (status = 422, description = "Atomic batch rolled back because an operation failed", body = BatchResponse),
+        (status = 500, description = "Database error", body = String),
+    )
+)]
+#[post("/items:batch")]
+pub async fn execute_batch(
+    staff: StaffIdentity,
+    batch: Json<BatchRequest>,
+    repositories: web::Data<PgRepositories>,
+) -> Result<HttpResponse, ServerError> {
+    let batch = batch.into_inner();
+    if batch.operations.len() > MAX_BATCH_OPERATIONS {
+        return Err(ServerError::BadRequest(format!(
+            "a batch can contain at most {} operations",
+            MAX_BATCH_OPERATIONS
+        )));
+    }
+
+    let operations_count = batch.operations.len();
+    let mut operations = Vec::with_capacity(operations_count);
+    for operation in batch.operations {
+        let operation = match operation {
+            BatchOperationRequest::Add {
+                name,
+                table_id,
+                quantity,
+            } => {
+                staff.require(Permission::AddItem)?;
+                validate_quantity_to_add(quantity)?;
+                BatchOperation::Add(
+                    Item::new(name, table_id, random_time_to_prepare(), quantity).to_insert_dao(),
+                )
+            }
+            BatchOperationRequest::Remove {
+                item_id,
+                quantity,
+                version,
+            } => {
+                staff.require(Permission::RemoveItem)?;
+                validate_quantity_to_remove(quantity)?;
+                BatchOperation::Remove {
+                    item_id,
+                    quantity,
+                    expected_version: version_to_remove(&staff, &repositories, item_id, version)
+                        .await?,
+                }
+            }
+        };
+        operations.push(operation);
+    }
+    let mode = match batch.mode {
+        BatchModeRequest::Atomic => BatchMode::Atomic,
+        BatchModeRequest::Partial => BatchMode::Partial,
+    };
+
+    let result = repositories
+        .item_repository
+        .execute_batch(operations, mode, &staff.id)
+        .await;
+    match result {
+        Ok(result) => {
+            let response = BatchResponse::from_batch_result(result, operations_count);
+            if response.committed {
+                Ok(HttpResponse::Ok().json(response))
+            } else {
+                Ok(HttpResponse::UnprocessableEntity().json(response))
+            }
+        }
+        Err(e) => Err(ServerError::from(e)),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/session",
+    tag = "staff",
+    request_body = LoginRequest,
+    security(()),
+    responses(
+        (status = 200, description = "Logged in", body = SessionResponse),
+        (status = 401, description = "Unknown staff member or wrong PIN", body = String),
+        (status = 500, description = "Database error", body = String),
+    )
+)]
+#[post("/session")]
+pub async fn login(
+    login: Json<LoginRequest>,
+    config: web::Data<SessionConfig>,
+    repositories: web::Data<PgRepositories>,
+) -> Result<HttpResponse, ServerError> {
+    let staff_repository = &repositories.staff_repository;
+    let staff = staff_repository.get_staff(login.staff_id).await?;
+    if let Some(staff) = &staff {
+        if let Some(locked_for) = staff_repository.login_locked_for(staff.id).await? {
+            return Err(ServerError::TooManyRequests(locked_for));
+        }
+    }
+    let pin_hash = staff.as_ref().map(|staff| staff.pin_hash.clone());
+    let pin_matches = verify_pin_blocking(pin_hash, login.pin.clone()).await?;
+    let staff = match staff {
+        Some(staff) if staff.active && pin_matches => staff,
+        staff => {
+            if let Some(staff) = staff {
+                staff_repository
+                    .record_failed_login(staff.id, config.max_failed_logins, config.login_lockout)
+                    .await?;
+            }
+            return Err(ServerError::Unauthorized(
+                "unknown staff member or wrong PIN".to_string(),
+            ));
+        }
+    };
+    staff_repository.reset_failed_logins(staff.id).await?;
+    let role = staff
+        .role
+        .parse()
+        .map_err(|e: String| ServerError::Internal(e))?;
+
+    let token = new_session_token();
+    repositories
+        .staff_repository
+        .create_session(staff.id, &session_token_hash(&token), config.lifetime)
+        .await?;
+    Ok(HttpResponse::Ok().json(SessionResponse {
+        token,
+        staff_id: staff.id,
+        name: staff.name,
+        role,
+        idle_timeout_secs: config.idle_timeout.as_secs(),
+        expires_in_secs: config.lifetime.as_secs(),
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/session",
+    tag = "staff",
+    responses(
+        (status = 204, description = "Logged out"),
+        (status = 400, description = "The request was not authenticated with a session token", body = String),
+        (status = 500, description = "Database error", body = String),
+    )
+)]
+#[delete("/session")]
+pub async fn logout(
+    request: HttpRequest,
+    _: StaffIdentity,
+    repositories: web::Data<PgRepositories>,
+) -> Result<HttpResponse, ServerError> {
+    let token = bearer_token(request.headers())
+        .filter(|token| !token.contains('.'))
+        .ok_or_else(|| {
+            ServerError::BadRequest("only sessions of staff members can be logged out".to_string())
+        })?;
+    repositories
+        .staff_repository
+        .end_session(&session_token_hash(token))
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    post,
+    path = "/staff",
+    tag = "staff",
+    request_body = AddStaffRequest,
+    responses(
+        (status = 200, description = "Staff member added", body = AddStaffResponse),
+        (status = 400, description = "Invalid name, PIN or role", body = String),
+        (status = 403, description = "Missing `manage_staff`", body = String),
+        (status = 500, description = "Database error", body = String),
+    )
+)]
+#[post("/staff")]
+pub async fn add_staff(
+    _: Authorized<require::ManageStaff>,
+    staff: Json<AddStaffRequest>,
+    repositories: web::Data<PgRepositories>,
+) -> Result<HttpResponse, ServerError> {
+    let staff = staff.into_inner();
+    if staff.name.trim().is_empty() {
+        return Err(ServerError::BadRequest(
+            "the name can't be empty".to_string(),
+        ));
+    }
+    validate_pin(&staff.pin)?;
+    if staff.role == Role::Monitoring {
+        return Err(ServerError::BadRequest(
+            "the monitoring role is only for devices".to_string(),
+        ));
+    }
+
+    let staff_id = repositories
+        .staff_repository
+        .add_staff(InsertStaffDao::new(
+            staff.name,
+            staff.role.to_string(),
+            hash_pin(&staff.pin)?,
+        ))
+        .await?;
+    Ok(HttpResponse::Ok().json(AddStaffResponse::new(staff_id)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/table/{table_id}/waiters",
+    tag = "tables",
+    params(("table_id" = i32, Path, description = "Number of the table")),
+    request_body = AssignWaitersRequest,
+    responses(
+        (status = 200, description = "Waiters now serving the table", body = TableWaitersResponse),
+        (status = 400, description = "A staff member is no active waiter", body = String),
+        (status = 403, description = "Missing `assign_tables`", body = String),
+        (status = 500, description = "Database error", body = String),
+    )
+)]
+#[put("/table/{table_id}/waiters")]
+pub async fn assign_waiters(
+    _: Authorized<require::AssignTables>,
+    table_id: web::Path<i32>,
+    request: Json<AssignWaitersRequest>,
+    repositories: web::Data<PgRepositories>,
+) -> Result<HttpResponse, ServerError> {
+    record_table_id(*table_id);
+    let mut staff_ids = request.into_inner().staff_ids;
+    staff_ids.sort_unstable();
+    staff_ids.dedup();
+    for staff_id in &staff_ids {
+        let staff = repositories.staff_repository.get_staff(*staff_id).await?;
+        if !staff.is_some_and(|staff| staff.active && staff.role == Role::Waiter.to_string()) {
+            return Err(ServerError::BadRequest(format!(
+                "staff member {} is no active waiter",
+                staff_id
+            )));
+        }
+    }
+
+    repositories
+        .staff_repository
+        .set_table_waiters(*table_id, &staff_ids)
+        .await?;
+    Ok(HttpResponse::Ok().json(TableWaitersResponse::new(*table_id, staff_ids)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/staff/{staff_id}/tables",
+    tag = "staff",
+    params(("staff_id" = i32, Path, description = "Id of the waiter")),
+    responses(
+        (status = 200, description = "Items of all tables the waiter is assigned to", body = GetStaffTablesResponse),
+        (status = 403, description = "Missing `view_items`, or `view_all_items` for the tables of someone else", body = String),
+        (status = 404, description = "Staff member not found"),
+        (status = 500, description = "Database error", body = String),
+    )
+)]
+#[get("/staff/{staff_id}/tables")]
+pub async fn get_staff_tables(
+    staff: Authorized<require::ViewItems>,
+    staff_id: web::Path<i32>,
+    repositories: web::Data<PgRepositories>,
+) -> Result<HttpResponse, ServerError> {
+    let staff_id = *staff_id;
+    if staff.identity.staff_id() != Some(staff_id) {
+        staff.identity.require(Permission::ViewAllItems)?;
+    }
+    if repositories
+        .staff_repository
+        .get_staff(staff_id)
+        .await?
+        .is_none()
+    {
+        return Err(ServerError::NotFound);
+    }
+
+    let table_ids = repositories
+        .staff_repository
+        .get_assigned_tables(staff_id)
+        .await?;
+    let items = repositories
+        .item_repository
+        .get_items_for_tables(&table_ids)
+        .await?
+        .into_iter()
+        .map(Item::from_dao)
+        .collect();
+    Ok(
+        HttpResponse::Ok().json(GetStaffTablesResponse::from_domain_items(
+            staff_id, table_ids, items,
+        )),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/notifications",
+    tag = "staff",
+    responses(
+        (status = 200, description = "Stream of server-sent events, one JSON notification per event", body = Notification, content_type = "text/event-stream"),
+        (status = 400, description = "The request was not authenticated with the session of a staff member", body = String),
+        (status = 403, description = "Missing `view_items`", body = String),
+    )
+)]
+#[get("/notifications")]
+pub async fn notifications(
+    staff: Authorized<require::ViewItems>,
+    notifier: web::Data<Notifier>,
+) -> Result<HttpResponse, ServerError> {
+    let staff_id = staff.identity.staff_id().ok_or_else(|| {
+        ServerError::BadRequest("only staff members receive notifications".to_string())
+    })?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .streaming(event_stream(notifier.subscribe(staff_id))))
+}
+
 #[cfg(test)]
 mod test {
+    use crate::auth::{
+        authenticate, authenticate_as_manager, Authenticator, Claims, API_KEY_HEADER,
+    };
+    use crate::config::{AuthConfig, DeviceKey};
     use crate::dto::GetItemForTableResponse;
 
     use super::*;
+    use actix_web::middleware::from_fn;
     use actix_web::{test, App};
-    use persistence::{init_test_db, truncate_table};
+    use persistence::admin_repository::AdminRepository;
+    use persistence::cached_item_repository::CacheConfig;
+    use persistence::resilient_item_repository::ResilienceConfig;
+    use persistence::{init_test_db, reset_test_db};
+    use std::collections::HashMap;
+
+    const STAFF: &str = "test";
 
     #[actix_web::test]
     #[serial_test::serial]
     async fn test_add_and_get_item() {
         let item_repository = init_test_db().await;
-        let repositories = persistence::postgres_repositories::PgRepositories {
-            item_repository: item_repository.clone(),
-        };
+        let repositories =
+            persistence::postgres_repositories::PgRepositories::new(item_repository.clone());
         let app = test::init_service(
             App::new()
+                .wrap(from_fn(authenticate_as_manager))
                 .app_data(web::Data::new(repositories))
                 .service(add_item)
                 .service(get_item),
@@ -144,18 +868,98 @@ mod test {
         assert!(get_item_response.time_to_prepare >= 5 && get_item_response.time_to_prepare <= 15);
         assert_eq!(get_item_response.quantity, 1);
 
-        truncate_table(item_repository.connection_pool).await;
+        reset_test_db(item_repository.connection_pool).await;
     }
 
     #[actix_web::test]
     #[serial_test::serial]
-    async fn test_get_item_for_table() {
+    async fn test_add_item_of_the_menu() {
         let item_repository = init_test_db().await;
-        let repositories = persistence::postgres_repositories::PgRepositories {
-            item_repository: item_repository.clone(),
+        let admin_repository = persistence::postgres_admin_repository::PgAdminRepository {
+            connection_pool: item_repository.connection_pool.clone(),
         };
+        admin_repository
+            .seed_menu(&[persistence::dao::MenuItemDao::new("Sushi".to_string(), 12)])
+            .await
+            .unwrap();
+        let repositories =
+            persistence::postgres_repositories::PgRepositories::new(item_repository.clone());
         let app = test::init_service(
             App::new()
+                .wrap(from_fn(authenticate_as_manager))
+                .app_data(web::Data::new(repositories))
+                .service(add_item)
+                .service(get_item),
+        )
+        .await;
+        let add = |name: &str| {
+            test::TestRequest::post()
+                .uri("/item")
+                .set_json(AddItemRequest {
+                    name: name.to_string(),
+                    table_id: 1,
+                    quantity: 1,
+                })
+                .to_request()
+        };
+
+        // Dishes of the menu are found regardless of case.
+        for name in ["sushi", "ramen"] {
+            let result = test::call_service(&app, add(name)).await;
+            assert_eq!(result.status(), 200);
+        }
+        let request = test::TestRequest::get().uri("/item/1").to_request();
+        let sushi: GetItemResponse = test::call_and_read_body_json(&app, request).await;
+        assert_eq!((sushi.name.as_str(), sushi.time_to_prepare), ("sushi", 12));
+        let request = test::TestRequest::get().uri("/item/2").to_request();
+        let ramen: GetItemResponse = test::call_and_read_body_json(&app, request).await;
+        assert!((5..=15).contains(&ramen.time_to_prepare));
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_database_unavailable() {
+        let item_repository = init_test_db().await;
+        let resilience = ResilienceConfig {
+            failure_threshold: 1,
+            ..Default::default()
+        };
+        let repositories = PgRepositories::with_config(
+            item_repository.clone(),
+            resilience,
+            CacheConfig::default(),
+        );
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate_as_manager))
+                .app_data(web::Data::new(repositories))
+                .service(get_item),
+        )
+        .await;
+        reset_test_db(item_repository.connection_pool.clone()).await;
+
+        item_repository.connection_pool.close().await;
+        let request = test::TestRequest::get().uri("/item/1").to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 500);
+
+        let request = test::TestRequest::get().uri("/item/1").to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 503);
+        assert_eq!(result.headers().get("Retry-After").unwrap(), "10");
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_get_item_for_table() {
+        let item_repository = init_test_db().await;
+        let repositories =
+            persistence::postgres_repositories::PgRepositories::new(item_repository.clone());
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate_as_manager))
                 .app_data(web::Data::new(repositories))
                 .service(get_items_for_table)
                 .service(get_all_items),
@@ -166,15 +970,15 @@ mod test {
         let item_2 = Item::new("onigiri".to_string(), 1, 10, 3);
         let item_3 = Item::new("ramen".to_string(), 2, 10, 1);
         item_repository
-            .add_item(item_1.to_insert_dao())
+            .add_item(item_1.to_insert_dao(), STAFF)
             .await
             .unwrap();
         item_repository
-            .add_item(item_2.to_insert_dao())
+            .add_item(item_2.to_insert_dao(), STAFF)
             .await
             .unwrap();
         item_repository
-            .add_item(item_3.to_insert_dao())
+            .add_item(item_3.to_insert_dao(), STAFF)
             .await
             .unwrap();
 
@@ -198,17 +1002,64 @@ mod test {
 
         let table_response: GetAllItemsResponse = test::read_body_json(result).await;
         assert_eq!(table_response.items.len(), 3);
+        assert!(table_response.next_cursor.is_none());
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_get_all_items_paginated() {
+        let item_repository = init_test_db().await;
+        let repositories =
+            persistence::postgres_repositories::PgRepositories::new(item_repository.clone());
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate_as_manager))
+                .app_data(web::Data::new(repositories))
+                .service(get_all_items),
+        )
+        .await;
+        for (name, table_id) in [("sushi", 1), ("onigiri", 2), ("ramen", 3), ("udon", 4)] {
+            let item = Item::new(name.to_string(), table_id, 10, 1);
+            item_repository
+                .add_item(item.to_insert_dao(), STAFF)
+                .await
+                .unwrap();
+        }
+
+        let request = test::TestRequest::get()
+            .uri("/items?limit=3&sort=name&order=desc")
+            .to_request();
+        let first_page: GetAllItemsResponse = test::call_and_read_body_json(&app, request).await;
+        let names: Vec<_> = first_page.items.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, vec!["udon", "sushi", "ramen"]);
+        let next = first_page.next.unwrap();
+        assert!(next.starts_with("/items?"));
+        assert!(next.contains(&format!("cursor={}", first_page.next_cursor.unwrap())));
+
+        let request = test::TestRequest::get().uri(&next).to_request();
+        let second_page: GetAllItemsResponse = test::call_and_read_body_json(&app, request).await;
+        let names: Vec<_> = second_page.items.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, vec!["onigiri"]);
+        assert!(second_page.next.is_none());
+
+        let request = test::TestRequest::get()
+            .uri("/items?cursor=invalid")
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 400);
+
+        reset_test_db(item_repository.connection_pool).await;
     }
 
     #[actix_web::test]
     #[serial_test::serial]
     async fn test_remove_item() {
         let item_repository = init_test_db().await;
-        let repositories = persistence::postgres_repositories::PgRepositories {
-            item_repository: item_repository.clone(),
-        };
+        let repositories =
+            persistence::postgres_repositories::PgRepositories::new(item_repository.clone());
         let app = test::init_service(
             App::new()
+                .wrap(from_fn(authenticate_as_manager))
                 .app_data(web::Data::new(repositories))
                 .service(remove_item)
                 .service(get_all_items),
@@ -216,11 +1067,21 @@ mod test {
         .await;
         let item = Item::new("sushi".to_string(), 1, 10, 1);
         let item_id = item_repository
-            .add_item(item.to_insert_dao())
+            .add_item(item.to_insert_dao(), STAFF)
             .await
             .unwrap();
+        for quantity in [0, -5] {
+            let request_for_remove = test::TestRequest::delete()
+                .uri(format!("/item/{}/{}", item_id, quantity).as_str())
+                .to_request();
+            let result = test::call_service(&app, request_for_remove).await;
+            assert_eq!(result.status(), 400);
+        }
+        let stored = item_repository.get_item(item_id).await.unwrap().unwrap();
+        assert_eq!(stored.quantity, 1);
+
         let request_for_remove = test::TestRequest::delete()
-            .uri(format!("/item/{}", item_id).as_str())
+            .uri(format!("/item/{}/1", item_id).as_str())
             .to_request();
         let result = test::call_service(&app, request_for_remove).await;
         assert_eq!(result.status(), 200);
@@ -232,4 +1093,677 @@ mod test {
         let table_response: GetAllItemsResponse = test::read_body_json(result).await;
         assert_eq!(table_response.items.len(), 0);
     }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_execute_batch() {
+        let item_repository = init_test_db().await;
+        let repositories =
+            persistence::postgres_repositories::PgRepositories::new(item_repository.clone());
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate_as_manager))
+                .app_data(web::Data::new(repositories))
+                .service(execute_batch),
+        )
+        .await;
+        let item = Item::new("sushi".to_string(), 1, 10, 2);
+        let item_id = item_repository
+            .add_item(item.to_insert_dao(), STAFF)
+            .await
+            .unwrap();
+
+        let request = test::TestRequest::post()
+            .uri("/items:batch")
+            .set_json(serde_json::json!({
+                "operations": [
+                    { "op": "add", "name": "ramen", "table_id": 1, "quantity": 2 },
+                    { "op": "remove", "item_id": item_id, "quantity": 1 },
+                ]
+            }))
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 200);
+        let response: BatchResponse = test::read_body_json(result).await;
+        assert!(response.committed);
+        assert!(matches!(
+            response.results.first().unwrap(),
+            BatchOperationResponse::Added { .. }
+        ));
+        assert_eq!(
+            response.results.last().unwrap(),
+            &BatchOperationResponse::Removed
+        );
+
+        let request = test::TestRequest::post()
+            .uri("/items:batch")
+            .set_json(serde_json::json!({
+                "operations": [
+                    { "op": "add", "name": "udon", "table_id": 1, "quantity": 1 },
+                    { "op": "add", "name": "x".repeat(256), "table_id": 1, "quantity": 1 },
+                    { "op": "remove", "item_id": item_id, "quantity": 1 },
+                ]
+            }))
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 422);
+        let response: BatchResponse = test::read_body_json(result).await;
+        assert!(!response.committed);
+        assert_eq!(
+            response.results.first().unwrap(),
+            &BatchOperationResponse::RolledBack
+        );
+        assert!(matches!(
+            response.results.get(1).unwrap(),
+            BatchOperationResponse::Failed { .. }
+        ));
+        assert_eq!(
+            response.results.last().unwrap(),
+            &BatchOperationResponse::Skipped
+        );
+        assert_eq!(item_repository.get_all_items().await.unwrap().len(), 2);
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_etag_and_if_match() {
+        let item_repository = init_test_db().await;
+        let repositories = PgRepositories::new(item_repository.clone());
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate_as_manager))
+                .app_data(web::Data::new(repositories))
+                .service(get_item)
+                .service(get_items_for_table)
+                .service(remove_item),
+        )
+        .await;
+        let item = Item::new("sushi".to_string(), 1, 10, 3);
+        let item_id = item_repository
+            .add_item(item.to_insert_dao(), STAFF)
+            .await
+            .unwrap();
+
+        let request = test::TestRequest::get()
+            .uri(&format!("/item/{}", item_id))
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        let etag = result.headers().get("ETag").unwrap().clone();
+        assert_eq!(etag, "\"1\"");
+
+        let request = test::TestRequest::get()
+            .uri(&format!("/item/{}", item_id))
+            .insert_header(("If-None-Match", etag.clone()))
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 304);
+
+        let request = test::TestRequest::get().uri("/table/1").to_request();
+        let result = test::call_service(&app, request).await;
+        let table_etag = result.headers().get("ETag").unwrap().clone();
+
+        let request = test::TestRequest::delete()
+            .uri(&format!("/item/{}/1", item_id))
+            .insert_header(("If-Match", etag.clone()))
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 200);
+        assert_eq!(result.headers().get("ETag").unwrap(), "\"2\"");
+
+        let request = test::TestRequest::delete()
+            .uri(&format!("/item/{}/1", item_id))
+            .insert_header(("If-Match", etag))
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 412);
+        let item = item_repository.get_item(item_id).await.unwrap().unwrap();
+        assert_eq!(item.quantity, 2);
+
+        let request = test::TestRequest::get()
+            .uri("/table/1")
+            .insert_header(("If-None-Match", table_etag.clone()))
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 200);
+        assert_ne!(result.headers().get("ETag").unwrap(), table_etag);
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_update_item() {
+        let item_repository = init_test_db().await;
+        let repositories = PgRepositories::new(item_repository.clone());
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate_as_manager))
+                .app_data(web::Data::new(repositories))
+                .service(update_item),
+        )
+        .await;
+        let item_id = item_repository
+            .add_item(
+                Item::new("sushi".to_string(), 1, 10, 2).to_insert_dao(),
+                STAFF,
+            )
+            .await
+            .unwrap();
+        let target_id = item_repository
+            .add_item(
+                Item::new("sushi".to_string(), 2, 10, 1).to_insert_dao(),
+                STAFF,
+            )
+            .await
+            .unwrap();
+
+        let request = test::TestRequest::patch()
+            .uri(&format!("/item/{}", item_id))
+            .insert_header(("If-Match", "\"1\""))
+            .set_json(UpdateItemRequest {
+                quantity: Some(4),
+                notes: Some("no wasabi".to_string()),
+                ..Default::default()
+            })
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 200);
+        assert_eq!(result.headers().get("ETag").unwrap(), "\"2\"");
+        let item: GetItemResponse = test::read_body_json(result).await;
+        assert_eq!(item.quantity, 4);
+        assert_eq!(item.notes, Some("no wasabi".to_string()));
+
+        let request = test::TestRequest::patch()
+            .uri(&format!("/item/{}", item_id))
+            .set_json(UpdateItemRequest {
+                table_id: Some(2),
+                ..Default::default()
+            })
+            .to_request();
+        let item: GetItemResponse = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(item.id, target_id);
+        assert_eq!(item.table_id, 2);
+        assert_eq!(item.quantity, 5);
+        assert_eq!(item.notes, Some("no wasabi".to_string()));
+
+        let request = test::TestRequest::patch()
+            .uri(&format!("/item/{}", item_id))
+            .set_json(UpdateItemRequest {
+                quantity: Some(1),
+                ..Default::default()
+            })
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 404);
+
+        let request = test::TestRequest::patch()
+            .uri(&format!("/item/{}", target_id))
+            .set_json(UpdateItemRequest {
+                quantity: Some(0),
+                ..Default::default()
+            })
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 400);
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_transfer_table() {
+        let item_repository = init_test_db().await;
+        let repositories = PgRepositories::new(item_repository.clone());
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate_as_manager))
+                .app_data(web::Data::new(repositories))
+                .service(transfer_table),
+        )
+        .await;
+        for item in [
+            Item::new("sushi".to_string(), 1, 10, 2),
+            Item::new("ramen".to_string(), 1, 10, 1),
+            Item::new("sushi".to_string(), 2, 10, 1),
+        ] {
+            item_repository
+                .add_item(item.to_insert_dao(), STAFF)
+                .await
+                .unwrap();
+        }
+
+        let request = test::TestRequest::post()
+            .uri("/table/1/transfer/2")
+            .to_request();
+        let result: GetItemForTableResponse = test::call_and_read_body_json(&app, request).await;
+        let items: Vec<(String, i32, i32)> = result
+            .items
+            .into_iter()
+            .map(|item| (item.name, item.table_id, item.quantity))
+            .collect();
+        assert_eq!(
+            items,
+            vec![("ramen".to_string(), 2, 1), ("sushi".to_string(), 2, 3)]
+        );
+
+        let request = test::TestRequest::post()
+            .uri("/table/2/transfer/2")
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 400);
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_permissions_of_roles() {
+        let item_repository = init_test_db().await;
+        let api_keys = [Role::Waiter, Role::Kitchen, Role::Manager]
+            .into_iter()
+            .map(|role| {
+                let key = role.to_string();
+                (role.to_string(), DeviceKey { key, role })
+            })
+            .collect::<HashMap<_, _>>();
+        let authenticator = Authenticator::from_config(&AuthConfig {
+            api_keys,
+            ..Default::default()
+        })
+        .unwrap();
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate))
+                .app_data(web::Data::new(authenticator))
+                .app_data(web::Data::new(PgRepositories::new(item_repository.clone())))
+                .app_data(web::Data::new(Notifier::default()))
+                .service(add_item)
+                .service(get_all_items)
+                .service(set_item_status)
+                .service(update_item)
+                .service(remove_item),
+        )
+        .await;
+        let as_role = |request: test::TestRequest, role: Role| {
+            request
+                .insert_header((API_KEY_HEADER, role.to_string()))
+                .to_request()
+        };
+
+        let request = test::TestRequest::post()
+            .uri("/item")
+            .set_json(AddItemRequest {
+                name: "sushi".to_string(),
+                table_id: 1,
+                quantity: 2,
+            });
+        let result = test::call_service(&app, as_role(request, Role::Waiter)).await;
+        assert_eq!(result.status(), 200);
+        let added: AddItemResponse = test::read_body_json(result).await;
+        let item_id = added.added_item_id;
+
+        let request = test::TestRequest::get().uri("/items");
+        let result = test::call_service(&app, as_role(request, Role::Waiter)).await;
+        assert_eq!(result.status(), 403);
+        let body = test::read_body(result).await;
+        assert_eq!(body, "missing permission view_all_items");
+        let request = test::TestRequest::get().uri("/items");
+        let result = test::call_service(&app, as_role(request, Role::Manager)).await;
+        assert_eq!(result.status(), 200);
+
+        let set_ready = || {
+            test::TestRequest::put()
+                .uri(&format!("/item/{}/status", item_id))
+                .set_json(SetItemStatusRequest {
+                    status: ItemStatus::Ready,
+                })
+        };
+        let result = test::call_service(&app, as_role(set_ready(), Role::Waiter)).await;
+        assert_eq!(result.status(), 403);
+        let result = test::call_service(&app, as_role(set_ready(), Role::Kitchen)).await;
+        assert_eq!(result.status(), 200);
+        let item: GetItemResponse = test::read_body_json(result).await;
+        assert_eq!(item.status, ItemStatus::Ready);
+
+        // Lowering the quantity or adding a negative one removes part of the item as well.
+        let lower = || {
+            test::TestRequest::patch()
+                .uri(&format!("/item/{}", item_id))
+                .set_json(UpdateItemRequest {
+                    quantity: Some(1),
+                    ..Default::default()
+                })
+        };
+        let result = test::call_service(&app, as_role(lower(), Role::Waiter)).await;
+        assert_eq!(result.status(), 403);
+        let body = test::read_body(result).await;
+        assert_eq!(body, "missing permission remove_prepared_item");
+        for quantity in [0, -1] {
+            let request = test::TestRequest::post()
+                .uri("/item")
+                .set_json(AddItemRequest {
+                    name: "sushi".to_string(),
+                    table_id: 1,
+                    quantity,
+                });
+            let result = test::call_service(&app, as_role(request, Role::Waiter)).await;
+            assert_eq!(result.status(), 400);
+        }
+        let item = item_repository.get_item(item_id).await.unwrap().unwrap();
+        assert_eq!(
+            (item.quantity, item.status),
+            (2, persistence::dao::ItemStatus::Ready)
+        );
+
+        let remove = || test::TestRequest::delete().uri(&format!("/item/{}/1", item_id));
+        let result = test::call_service(&app, as_role(remove(), Role::Kitchen)).await;
+        assert_eq!(result.status(), 403);
+        let result = test::call_service(&app, as_role(remove(), Role::Waiter)).await;
+        assert_eq!(result.status(), 403);
+        let body = test::read_body(result).await;
+        assert_eq!(body, "missing permission remove_prepared_item");
+        let result = test::call_service(&app, as_role(remove(), Role::Manager)).await;
+        assert_eq!(result.status(), 200);
+        let item = item_repository.get_item(item_id).await.unwrap().unwrap();
+        assert_eq!(item.quantity, 1);
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_staff_sessions() {
+        let item_repository = init_test_db().await;
+        let authenticator = Authenticator::from_config(&AuthConfig {
+            api_keys: HashMap::from([(
+                "office".to_string(),
+                DeviceKey {
+                    key: "manager-key".to_string(),
+                    role: Role::Manager,
+                },
+            )]),
+            ..Default::default()
+        })
+        .unwrap();
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate))
+                .app_data(web::Data::new(authenticator))
+                .app_data(web::Data::new(SessionConfig::default()))
+                .app_data(web::Data::new(PgRepositories::new(item_repository.clone())))
+                .service(add_staff)
+                .service(login)
+                .service(logout)
+                .service(add_item),
+        )
+        .await;
+
+        let add_staff_request = |pin: &str, role: Role| {
+            test::TestRequest::post()
+                .uri("/staff")
+                .insert_header((API_KEY_HEADER, "manager-key"))
+                .set_json(AddStaffRequest {
+                    name: "Anna".to_string(),
+                    role,
+                    pin: pin.to_string(),
+                })
+                .to_request()
+        };
+        let result = test::call_service(&app, add_staff_request("12", Role::Waiter)).await;
+        assert_eq!(result.status(), 400);
+        let result = test::call_service(&app, add_staff_request("1234", Role::Monitoring)).await;
+        assert_eq!(result.status(), 400);
+        let result = test::call_service(&app, add_staff_request("1234", Role::Waiter)).await;
+        assert_eq!(result.status(), 200);
+        let AddStaffResponse { staff_id } = test::read_body_json(result).await;
+
+        let login_request = |pin: &str| {
+            test::TestRequest::post()
+                .uri("/session")
+                .set_json(LoginRequest {
+                    staff_id,
+                    pin: pin.to_string(),
+                })
+                .to_request()
+        };
+        let result = test::call_service(&app, login_request("4321")).await;
+        assert_eq!(result.status(), 401);
+        let request = test::TestRequest::post()
+            .uri("/session")
+            .set_json(LoginRequest {
+                staff_id: staff_id + 1,
+                pin: "1234".to_string(),
+            })
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 401);
+        let result = test::call_service(&app, login_request("1234")).await;
+        assert_eq!(result.status(), 200);
+        let session: SessionResponse = test::read_body_json(result).await;
+        assert_eq!(session.role, Role::Waiter);
+        assert_eq!(session.idle_timeout_secs, 15 * 60);
+        let bearer = format!("Bearer {}", session.token);
+
+        let request = test::TestRequest::post()
+            .uri("/item")
+            .insert_header(("Authorization", bearer.as_str()))
+            .set_json(AddItemRequest {
+                name: "sushi".to_string(),
+                table_id: 1,
+                quantity: 1,
+            })
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 200);
+        let added: AddItemResponse = test::read_body_json(result).await;
+        let changes = item_repository
+            .get_item_changes(added.added_item_id)
+            .await
+            .unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].performed_by, Some(staff_id.to_string()));
+
+        let logout_request = || {
+            test::TestRequest::delete()
+                .uri("/session")
+                .insert_header(("Authorization", bearer.as_str()))
+                .to_request()
+        };
+        let result = test::call_service(&app, logout_request()).await;
+        assert_eq!(result.status(), 204);
+        let result = test::call_service(&app, logout_request()).await;
+        assert_eq!(result.status(), 401);
+
+        // Guessing the PIN locks the logins of the staff member, even with the right PIN.
+        for _ in 0..SessionConfig::default().max_failed_logins {
+            let result = test::call_service(&app, login_request("0000")).await;
+            assert_eq!(result.status(), 401);
+        }
+        let result = test::call_service(&app, login_request("1234")).await;
+        assert_eq!(result.status(), 429);
+        assert!(result.headers().contains_key("Retry-After"));
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_waiter_tables() {
+        let item_repository = init_test_db().await;
+        let repositories = PgRepositories::new(item_repository.clone());
+        let api_keys = [Role::Kitchen, Role::Manager]
+            .into_iter()
+            .map(|role| {
+                let key = role.to_string();
+                (role.to_string(), DeviceKey { key, role })
+            })
+            .collect::<HashMap<_, _>>();
+        let authenticator = Authenticator::from_config(&AuthConfig {
+            api_keys,
+            jwt_hs256_secret: Some("secret".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        let notifier = web::Data::new(Notifier::default());
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate))
+                .app_data(web::Data::new(authenticator))
+                .app_data(web::Data::new(repositories.clone()))
+                .app_data(notifier.clone())
+                .service(assign_waiters)
+                .service(get_staff_tables)
+                .service(set_item_status)
+                .service(notifications),
+        )
+        .await;
+
+        let mut staff = Vec::new();
+        for (name, role) in [("Anna", "waiter"), ("Ben", "waiter"), ("Chef", "kitchen")] {
+            let staff_id = repositories
+                .staff_repository
+                .add_staff(InsertStaffDao::new(
+                    name.to_string(),
+                    role.to_string(),
+                    "hash".to_string(),
+                ))
+                .await
+                .unwrap();
+            let token = new_session_token();
+            repositories
+                .staff_repository
+                .create_session(
+                    staff_id,
+                    &session_token_hash(&token),
+                    SessionConfig::default().lifetime,
+                )
+                .await
+                .unwrap();
+            staff.push((staff_id, format!("Bearer {}", token)));
+        }
+        let (anna, ben, chef) = (staff[0].clone(), staff[1].clone(), staff[2].0);
+
+        let assign = |table_id: i32, staff_ids: Vec<i32>| {
+            test::TestRequest::put()
+                .uri(&format!("/table/{}/waiters", table_id))
+                .insert_header((API_KEY_HEADER, "manager"))
+                .set_json(AssignWaitersRequest { staff_ids })
+                .to_request()
+        };
+        let result = test::call_service(&app, assign(1, vec![chef])).await;
+        assert_eq!(result.status(), 400);
+        let result = test::call_service(&app, assign(1, vec![anna.0])).await;
+        assert_eq!(result.status(), 200);
+        let result = test::call_service(&app, assign(2, vec![ben.0, anna.0, ben.0])).await;
+        let waiters: TableWaitersResponse = test::read_body_json(result).await;
+        assert_eq!(waiters.staff_ids, vec![anna.0, ben.0]);
+
+        let mut item_ids = Vec::new();
+        for item in [
+            Item::new("sushi".to_string(), 1, 10, 1),
+            Item::new("ramen".to_string(), 2, 10, 1),
+            Item::new("onigiri".to_string(), 3, 10, 1),
+        ] {
+            let item_id = item_repository
+                .add_item(item.to_insert_dao(), STAFF)
+                .await
+                .unwrap();
+            item_ids.push(item_id);
+        }
+
+        let tables_of = |staff_id: i32, authorization: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/staff/{}/tables", staff_id))
+                .insert_header(("Authorization", authorization.to_string()))
+                .to_request()
+        };
+        let result = test::call_service(&app, tables_of(anna.0, &anna.1)).await;
+        assert_eq!(result.status(), 200);
+        let tables: GetStaffTablesResponse = test::read_body_json(result).await;
+        assert_eq!(tables.staff_id, anna.0);
+        let items = tables
+            .tables
+            .iter()
+            .map(|table| {
+                let names = table.items.iter().map(|item| item.name.as_str());
+                (table.table_id, names.collect::<Vec<_>>())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(items, vec![(1, vec!["sushi"]), (2, vec!["ramen"])]);
+        let result = test::call_service(&app, tables_of(ben.0, &anna.1)).await;
+        assert_eq!(result.status(), 403);
+        let request = test::TestRequest::get()
+            .uri(&format!("/staff/{}/tables", ben.0))
+            .insert_header((API_KEY_HEADER, "manager"))
+            .to_request();
+        let tables: GetStaffTablesResponse = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(tables.tables.len(), 1);
+        // A token whose subject happens to be the id of a waiter doesn't make its holder one.
+        let claims = Claims {
+            sub: ben.0.to_string(),
+            role: Role::Waiter,
+            exp: jsonwebtoken::get_current_timestamp() + 60,
+            name: None,
+        };
+        let key = jsonwebtoken::EncodingKey::from_secret(b"secret");
+        let token = jsonwebtoken::encode(&jsonwebtoken::Header::default(), &claims, &key).unwrap();
+        let as_ben = format!("Bearer {}", token);
+        let result = test::call_service(&app, tables_of(ben.0, &as_ben)).await;
+        assert_eq!(result.status(), 403);
+        let request = test::TestRequest::get()
+            .uri("/notifications")
+            .insert_header(("Authorization", as_ben))
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 400);
+
+        let request = test::TestRequest::get()
+            .uri("/notifications")
+            .insert_header(("Authorization", anna.1.clone()))
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 200);
+        assert_eq!(
+            result.headers().get("Content-Type").unwrap(),
+            "text/event-stream"
+        );
+        let request = test::TestRequest::get()
+            .uri("/notifications")
+            .insert_header((API_KEY_HEADER, "manager"))
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 400);
+
+        let mut anna_notifications = notifier.subscribe(anna.0);
+        let mut ben_notifications = notifier.subscribe(ben.0);
+        let set_status = |item_id: i64, status: ItemStatus| {
+            test::TestRequest::put()
+                .uri(&format!("/item/{}/status", item_id))
+                .insert_header((API_KEY_HEADER, "kitchen"))
+                .set_json(SetItemStatusRequest { status })
+                .to_request()
+        };
+        let result = test::call_service(&app, set_status(item_ids[0], ItemStatus::Preparing)).await;
+        assert_eq!(result.status(), 200);
+        assert!(anna_notifications.try_recv().is_err());
+        let result = test::call_service(&app, set_status(item_ids[0], ItemStatus::Ready)).await;
+        assert_eq!(result.status(), 200);
+        assert_eq!(
+            anna_notifications.try_recv().unwrap(),
+            Notification::ItemReady {
+                item_id: item_ids[0],
+                table_id: 1,
+                name: "sushi".to_string(),
+                quantity: 1,
+            }
+        );
+        assert!(ben_notifications.try_recv().is_err());
+        test::call_service(&app, set_status(item_ids[1], ItemStatus::Ready)).await;
+        assert!(anna_notifications.try_recv().is_ok());
+        assert!(ben_notifications.try_recv().is_ok());
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
 }
@@ -0,0 +1,135 @@
+//! Liveness and readiness probes.
+//!
+//! `/health/live` only tells that the process serves requests, `/health/ready` checks the
+//! dependencies the handlers need and answers 503 as long as one of them is down, so that
+//! no traffic is routed to the server before the database is reachable and migrated.
+
+use actix_web::{get, web, HttpResponse};
+use persistence::error::DbError;
+use persistence::health_repository::HealthRepository;
+use persistence::postgres_repositories::PgRepositories;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::dto::{DependencyHealth, HealthResponse, HealthStatus};
+
+/// Time after which a check counts as failed, probes are usually given a few seconds.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Runs the check, mapping failures and timeouts to a down dependency.
+async fn check<T>(
+    run: impl Future<Output = Result<T, DbError>>,
+    evaluate: impl FnOnce(T) -> DependencyHealth,
+) -> DependencyHealth {
+    let started_at = Instant::now();
+    let error = match actix_web::rt::time::timeout(CHECK_TIMEOUT, run).await {
+        Ok(Ok(value)) => {
+            let mut health = evaluate(value);
+            health.duration_ms = started_at.elapsed().as_millis() as u64;
+            return health;
+        }
+        Ok(Err(e)) => e.to_string(),
+        Err(_) => format!("timed out after {} ms", CHECK_TIMEOUT.as_millis()),
+    };
+    DependencyHealth {
+        status: HealthStatus::Down,
+        duration_ms: started_at.elapsed().as_millis() as u64,
+        error: Some(error),
+        pending_migrations: vec![],
+    }
+}
+
+fn up() -> DependencyHealth {
+    DependencyHealth {
+        status: HealthStatus::Up,
+        duration_ms: 0,
+        error: None,
+        pending_migrations: vec![],
+    }
+}
+
+#[get("/health/live")]
+pub async fn live() -> HttpResponse {
+    HttpResponse::Ok().json(HealthResponse {
+        status: HealthStatus::Up,
+        checks: BTreeMap::new(),
+    })
+}
+
+#[get("/health/ready")]
+pub async fn ready(repositories: web::Data<PgRepositories>) -> HttpResponse {
+    let health_repository = &repositories.health_repository;
+    let database = check(health_repository.ping(), |_| up()).await;
+    let migrations = check(health_repository.pending_migrations(), |pending| {
+        if pending.is_empty() {
+            up()
+        } else {
+            DependencyHealth {
+                status: HealthStatus::Down,
+                error: Some(format!("{} migrations are not applied", pending.len())),
+                pending_migrations: pending,
+                ..up()
+            }
+        }
+    })
+    .await;
+
+    let checks = BTreeMap::from([
+        ("database".to_string(), database),
+        ("migrations".to_string(), migrations),
+    ]);
+    let status = if checks.values().all(|c| c.status == HealthStatus::Up) {
+        HealthStatus::Up
+    } else {
+        HealthStatus::Down
+    };
+    let mut response = match status {
+        HealthStatus::Up => HttpResponse::Ok(),
+        HealthStatus::Down => HttpResponse::ServiceUnavailable(),
+    };
+    response.json(HealthResponse { status, checks })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::{test, App};
+    use persistence::{init_test_db, reset_test_db};
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_health() {
+        let item_repository = init_test_db().await;
+        let connection_pool = item_repository.connection_pool.clone();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(PgRepositories::new(item_repository)))
+                .service(live)
+                .service(ready),
+        )
+        .await;
+
+        let request = test::TestRequest::get().uri("/health/live").to_request();
+        let result: HealthResponse = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(result.status, HealthStatus::Up);
+
+        let request = test::TestRequest::get().uri("/health/ready").to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 200);
+        let result: HealthResponse = test::read_body_json(result).await;
+        assert_eq!(result.status, HealthStatus::Up);
+        assert_eq!(result.checks["database"].status, HealthStatus::Up);
+        assert_eq!(result.checks["migrations"].status, HealthStatus::Up);
+        reset_test_db(connection_pool.clone()).await;
+
+        connection_pool.close().await;
+        let request = test::TestRequest::get().uri("/health/ready").to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 503);
+        let result: HealthResponse = test::read_body_json(result).await;
+        assert_eq!(result.status, HealthStatus::Down);
+        assert_eq!(result.checks["database"].status, HealthStatus::Down);
+        assert!(result.checks["database"].error.is_some());
+    }
+}
@@ -0,0 +1,345 @@
+use actix_web::body::{to_bytes, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpResponse};
+use persistence::dao::StoredResponseDao;
+use persistence::idempotency_repository::{IdempotencyRepository, KeyReservation};
+use persistence::postgres_repositories::PgRepositories;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+use crate::auth::StaffIdentity;
+use crate::config::IdempotencyConfig;
+use crate::errors::ServerError;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+pub const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+const MAX_KEY_LENGTH: usize = 255;
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+fn is_mutating(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    )
+}
+
+fn request_hash(request: &ServiceRequest, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(request.method().as_str());
+    hasher.update(b" ");
+    hasher.update(request.uri().to_string());
+    hasher.update(b"\n");
+    hasher.update(body);
+    format!("{:x}", hasher.finalize())
+}
+
+fn replay(stored: StoredResponseDao) -> HttpResponse {
+    let status = StatusCode::from_u16(stored.status_code as u16).unwrap_or(StatusCode::OK);
+    let mut response = HttpResponse::build(status);
+    if let Some(content_type) = stored.content_type {
+        response.content_type(content_type);
+    }
+    response
+        .insert_header((REPLAYED_HEADER, "true"))
+        .body(stored.response_body)
+}
+
+/// Makes mutating requests carrying an `Idempotency-Key` header safe to retry: the first
+/// response for a key is stored and replayed for repeated requests instead of applying
+/// them again. Server errors are not stored, so a failed request can be retried. Keys are
+/// scoped by the authenticated staff member or device, and a request which was abandoned
+/// while it was processed gives up its key after the lock timeout.
+pub async fn idempotency(
+    mut request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let key = match request.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(key) if is_mutating(request.method()) => key.to_str().unwrap_or_default().to_string(),
+        _ => return next.call(request).await.map(|r| r.map_into_boxed_body()),
+    };
+    if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+        return Err(ServerError::BadRequest(format!(
+            "{} must be a visible ASCII string of at most {} characters",
+            IDEMPOTENCY_KEY_HEADER, MAX_KEY_LENGTH
+        ))
+        .into());
+    }
+    let (Some(repositories), Some(config)) = (
+        request.app_data::<web::Data<PgRepositories>>().cloned(),
+        request.app_data::<web::Data<IdempotencyConfig>>().cloned(),
+    ) else {
+        return next.call(request).await.map(|r| r.map_into_boxed_body());
+    };
+
+    // Every mutating route is authenticated, so the identity is known here.
+    let owner = request
+        .extensions()
+        .get::<StaffIdentity>()
+        .map(|staff| staff.id.clone())
+        .unwrap_or_default();
+
+    let body = request.extract::<web::Bytes>().await?;
+    let hash = request_hash(&request, &body);
+    request.set_payload(body.into());
+
+    let repository = &repositories.idempotency_repository;
+    match repository
+        .reserve_key(&owner, &key, &hash, config.key_ttl, config.lock_timeout)
+        .await
+        .map_err(ServerError::from)?
+    {
+        KeyReservation::Reserved => {}
+        KeyReservation::Completed(stored) => {
+            return Ok(request.into_response(replay(stored)));
+        }
+        KeyReservation::InProgress => {
+            return Ok(request.into_response(
+                HttpResponse::Conflict().body("a request with this idempotency key is in progress"),
+            ));
+        }
+        KeyReservation::Mismatch => {
+            return Ok(request.into_response(
+                HttpResponse::UnprocessableEntity()
+                    .body("the idempotency key was already used for a different request"),
+            ));
+        }
+    }
+
+    let response = match next.call(request).await {
+        Ok(response) => response,
+        Err(e) => {
+            repository
+                .release_key(&owner, &key)
+                .await
+                .map_err(ServerError::from)?;
+            return Err(e);
+        }
+    };
+    if response.status().is_server_error() {
+        repository
+            .release_key(&owner, &key)
+            .await
+            .map_err(ServerError::from)?;
+        return Ok(response.map_into_boxed_body());
+    }
+
+    let (request, response) = response.into_parts();
+    let (response, body) = response.into_parts();
+    let body = to_bytes(body).await.map_err(|_| {
+        actix_web::error::ErrorInternalServerError("failed to read the response body")
+    })?;
+    let stored = StoredResponseDao::new(
+        response.status().as_u16() as i16,
+        response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+            .map(str::to_string),
+        body.to_vec(),
+    );
+    repository
+        .complete_key(&owner, &key, stored)
+        .await
+        .map_err(ServerError::from)?;
+
+    Ok(ServiceResponse::new(
+        request,
+        response.set_body(BoxBody::new(body)),
+    ))
+}
+
+/// Deletes expired keys once per `PURGE_INTERVAL`, so the table doesn't grow unbounded.
+pub async fn purge_expired_keys_periodically(repositories: PgRepositories, key_ttl: Duration) {
+    let mut interval = actix_web::rt::time::interval(PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        match repositories
+            .idempotency_repository
+            .purge_expired_keys(key_ttl)
+            .await
+        {
+            Ok(purged) => log::debug!("purged {} expired idempotency keys", purged),
+            Err(e) => log::warn!("failed to purge expired idempotency keys: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::auth::authenticate_as_manager;
+    use crate::dto::{AddItemRequest, AddItemResponse};
+    use crate::handlers::add_item;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App};
+    use persistence::item_repository::ItemRepository;
+    use persistence::{init_test_db, reset_test_db};
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_repeated_request_is_replayed() {
+        let item_repository = init_test_db().await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(PgRepositories::new(item_repository.clone())))
+                .app_data(web::Data::new(IdempotencyConfig::default()))
+                .wrap(from_fn(idempotency))
+                .wrap(from_fn(authenticate_as_manager))
+                .service(add_item),
+        )
+        .await;
+        purge_keys(&item_repository).await;
+        let request_dto = || AddItemRequest {
+            name: "sushi".to_string(),
+            table_id: 1,
+            quantity: 1,
+        };
+
+        let request = test::TestRequest::post()
+            .uri("/item")
+            .insert_header((IDEMPOTENCY_KEY_HEADER, "order-1"))
+            .set_json(request_dto())
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 200);
+        assert!(result.headers().get(REPLAYED_HEADER).is_none());
+        let first: AddItemResponse = test::read_body_json(result).await;
+
+        let request = test::TestRequest::post()
+            .uri("/item")
+            .insert_header((IDEMPOTENCY_KEY_HEADER, "order-1"))
+            .set_json(request_dto())
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 200);
+        assert_eq!(result.headers().get(REPLAYED_HEADER).unwrap(), "true");
+        let replayed: AddItemResponse = test::read_body_json(result).await;
+        assert_eq!(replayed.added_item_id, first.added_item_id);
+
+        let item = item_repository
+            .get_item(first.added_item_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(item.quantity, 1);
+
+        let request = test::TestRequest::post()
+            .uri("/item")
+            .insert_header((IDEMPOTENCY_KEY_HEADER, "order-1"))
+            .set_json(AddItemRequest {
+                quantity: 2,
+                ..request_dto()
+            })
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 422);
+
+        let request = test::TestRequest::post()
+            .uri("/item")
+            .set_json(request_dto())
+            .to_request();
+        test::call_service(&app, request).await;
+        let item = item_repository
+            .get_item(first.added_item_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(item.quantity, 2);
+
+        purge_keys(&item_repository).await;
+        reset_test_db(item_repository.connection_pool).await;
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_abandoned_request_gives_up_key() {
+        let item_repository = init_test_db().await;
+        let repositories = PgRepositories::new(item_repository.clone());
+        let config = IdempotencyConfig {
+            lock_timeout: Duration::from_millis(200),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(repositories.clone()))
+                .app_data(web::Data::new(config.clone()))
+                .wrap(from_fn(idempotency))
+                .wrap(from_fn(authenticate_as_manager))
+                .service(add_item),
+        )
+        .await;
+        purge_keys(&item_repository).await;
+        let request_dto = AddItemRequest {
+            name: "sushi".to_string(),
+            table_id: 1,
+            quantity: 1,
+        };
+        let mut hasher = Sha256::new();
+        hasher.update(b"POST /item\n");
+        hasher.update(serde_json::to_vec(&request_dto).unwrap());
+        let hash = format!("{:x}", hasher.finalize());
+
+        // The first attempt of the manager died while it was processed.
+        let repository = &repositories.idempotency_repository;
+        repository
+            .reserve_key(
+                "manager",
+                "order-1",
+                &hash,
+                config.key_ttl,
+                config.lock_timeout,
+            )
+            .await
+            .unwrap();
+        let request = || {
+            test::TestRequest::post()
+                .uri("/item")
+                .insert_header((IDEMPOTENCY_KEY_HEADER, "order-1"))
+                .set_json(request_dto.clone())
+                .to_request()
+        };
+        let result = test::call_service(&app, request()).await;
+        assert_eq!(result.status(), 409);
+
+        actix_web::rt::time::sleep(config.lock_timeout).await;
+        let result = test::call_service(&app, request()).await;
+        assert_eq!(result.status(), 200);
+        assert!(result.headers().get(REPLAYED_HEADER).is_none());
+        let result = test::call_service(&app, request()).await;
+        assert_eq!(result.headers().get(REPLAYED_HEADER).unwrap(), "true");
+
+        // The same key of another device doesn't concern the manager.
+        repository
+            .reserve_key(
+                "waiter",
+                "order-2",
+                &hash,
+                config.key_ttl,
+                config.lock_timeout,
+            )
+            .await
+            .unwrap();
+        let request = test::TestRequest::post()
+            .uri("/item")
+            .insert_header((IDEMPOTENCY_KEY_HEADER, "order-2"))
+            .set_json(request_dto.clone())
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 200);
+
+        purge_keys(&item_repository).await;
+        reset_test_db(item_repository.connection_pool).await;
+    }
+
+    async fn purge_keys(item_repository: &persistence::postgres_item_repository::PgItemRepository) {
+        let repositories = PgRepositories::new(item_repository.clone());
+        repositories
+            .idempotency_repository
+            .purge_expired_keys(Duration::ZERO)
+            .await
+            .unwrap();
+    }
+}
@@ -1,3 +1,22 @@
+pub mod api;
+pub mod auth;
+pub mod cli;
+pub mod config;
+pub mod consistency;
 pub mod dto;
 pub mod errors;
+pub mod etag;
+pub mod graphql;
 pub mod handlers;
+pub mod health;
+pub mod idempotency;
+pub mod limits;
+pub mod logging;
+pub mod metrics;
+pub mod notifications;
+pub mod openapi;
+pub mod pagination;
+pub mod policy;
+pub mod session;
+pub mod shutdown;
+pub mod telemetry;
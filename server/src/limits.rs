@@ -0,0 +1,416 @@
+//! Rate and payload limits per client and route.
+//!
+//! Every client gets a token bucket per rate: requests to routes with their own rate in
+//! [`LimitsConfig::route_rates`] take tokens from the bucket of that route, all other
+//! requests share the bucket of the default rate. Clients are told apart by the staff member
+//! or device they were authenticated as and, on public routes, by their IP address. Requests
+//! rejected with 401 are charged to a separate bucket of their IP address, which is checked
+//! before the credentials are. The number of buckets is capped, requests of new clients are
+//! rejected once it is reached.
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::CONTENT_LENGTH;
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::web::{self, Bytes, BytesMut};
+use actix_web::{Error, HttpMessage};
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::api::v1;
+use crate::auth::StaffIdentity;
+use crate::config::{LimitsConfig, RateLimit};
+use crate::errors::ServerError;
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60);
+/// Route of adding an item, whose body is limited by `max_add_item_payload`.
+const ADD_ITEM_ROUTE: &str = "POST /item";
+/// Route of the buckets charged for requests rejected with 401.
+const FAILED_AUTHENTICATION_ROUTE: &str = "failed authentication";
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, rate: RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate.per_second).min(f64::from(rate.burst));
+        self.updated_at = now;
+    }
+}
+
+pub struct RateLimiter {
+    config: LimitsConfig,
+    /// Buckets by client and route, the route is empty for the default rate.
+    buckets: Mutex<HashMap<(String, String), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: LimitsConfig) -> RateLimiter {
+        RateLimiter {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Bucket route and rate of a route, the bucket route is empty for the default rate.
+    fn bucket_route<'a>(&self, route: &'a str) -> (&'a str, RateLimit) {
+        if route == FAILED_AUTHENTICATION_ROUTE {
+            return (route, self.config.failed_authentication_rate);
+        }
+        match self.config.route_rates.get(route) {
+            Some(rate) => (route, *rate),
+            None => ("", self.config.rate),
+        }
+    }
+
+    /// Takes a token from the bucket of the client for the route. Fails with the time
+    /// until the next token is available when the bucket is empty, or until the next token
+    /// of a new bucket when all buckets are taken.
+    pub fn acquire(&self, client: &str, route: &str, now: Instant) -> Result<(), Duration> {
+        self.take(client, route, now, true)
+    }
+
+    /// Fails like [`acquire`](Self::acquire) when the bucket of the client is empty, but
+    /// neither takes a token nor creates a bucket.
+    pub fn check(&self, client: &str, route: &str, now: Instant) -> Result<(), Duration> {
+        self.take(client, route, now, false)
+    }
+
+    fn take(&self, client: &str, route: &str, now: Instant, consume: bool) -> Result<(), Duration> {
+        let (route, rate) = self.bucket_route(route);
+        let mut buckets = self.buckets.lock().unwrap();
+        let key = (client.to_string(), route.to_string());
+        if !buckets.contains_key(&key) {
+            if !consume {
+                return Ok(());
+            }
+            if buckets.len() >= self.config.max_buckets {
+                self.purge(&mut buckets, now);
+                if buckets.len() >= self.config.max_buckets {
+                    return Err(Duration::from_secs_f64(1.0 / rate.per_second));
+                }
+            }
+        }
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: f64::from(rate.burst),
+            updated_at: now,
+        });
+        bucket.refill(rate, now);
+        if bucket.tokens >= 1.0 {
+            if consume {
+                bucket.tokens -= 1.0;
+            }
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / rate.per_second,
+            ))
+        }
+    }
+
+    /// Drops the buckets which are full again, they are recreated full when needed.
+    pub fn purge_full_buckets(&self, now: Instant) {
+        self.purge(&mut self.buckets.lock().unwrap(), now);
+    }
+
+    fn purge(&self, buckets: &mut HashMap<(String, String), Bucket>, now: Instant) {
+        buckets.retain(|(_, route), bucket| {
+            let (_, rate) = self.bucket_route(route);
+            bucket.refill(rate, now);
+            bucket.tokens < f64::from(rate.burst)
+        });
+    }
+
+    /// Largest body accepted by the route, if it is limited below `max_json_payload`.
+    fn max_payload(&self, route: &str) -> Option<usize> {
+        (route == ADD_ITEM_ROUTE).then_some(self.config.max_add_item_payload)
+    }
+}
+
+/// Deletes the buckets of clients which stopped sending requests once per `PURGE_INTERVAL`.
+pub async fn purge_full_buckets_periodically(rate_limiter: web::Data<RateLimiter>) {
+    let mut interval = actix_web::rt::time::interval(PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        rate_limiter.purge_full_buckets(Instant::now());
+    }
+}
+
+/// Staff member or device of an authenticated request, IP address of the others.
+fn client_key(request: &ServiceRequest) -> String {
+    if let Some(staff) = request.extensions().get::<StaffIdentity>() {
+        return format!("staff:{}", staff.id);
+    }
+    ip_key(request)
+}
+
+fn ip_key(request: &ServiceRequest) -> String {
+    match request.peer_addr() {
+        Some(address) => format!("ip:{}", address.ip()),
+        None => "ip:unknown".to_string(),
+    }
+}
+
+/// `METHOD /path` of the matched route without the API version prefix, e.g. `POST /item`.
+fn route_key(request: &ServiceRequest) -> String {
+    let pattern = request
+        .match_pattern()
+        .unwrap_or_else(|| request.path().to_string());
+    let path = pattern.strip_prefix(v1::SCOPE).unwrap_or(&pattern);
+    format!("{} {}", request.method(), path)
+}
+
+/// Reads the body of the request, failing with 413 as soon as it exceeds `limit`.
+async fn read_body(request: &mut ServiceRequest, limit: usize) -> Result<Bytes, ServerError> {
+    let content_length = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if content_length.is_some_and(|length| length > limit) {
+        return Err(ServerError::PayloadTooLarge(limit));
+    }
+    let mut payload = request.take_payload();
+    let mut body = BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| ServerError::BadRequest(e.to_string()))?;
+        if body.len() + chunk.len() > limit {
+            return Err(ServerError::PayloadTooLarge(limit));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body.freeze())
+}
+
+/// Charges requests rejected with 401 to the bucket of failed authentications of their IP
+/// address, and rejects all requests of the address with 429 while it is empty. Wrapped
+/// outside [`authenticate`](crate::auth::authenticate), so that guessing API keys, tokens
+/// or PINs is slowed down before the guesses are checked.
+pub async fn limit_failed_authentications(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(rate_limiter) = request.app_data::<web::Data<RateLimiter>>().cloned() else {
+        return next.call(request).await.map(|r| r.map_into_boxed_body());
+    };
+    let client = ip_key(&request);
+    let checked = rate_limiter.check(&client, FAILED_AUTHENTICATION_ROUTE, Instant::now());
+    if let Err(retry_after) = checked {
+        return Ok(request.error_response(ServerError::TooManyRequests(retry_after)));
+    }
+    let response = next.call(request).await?.map_into_boxed_body();
+    if response.status() == StatusCode::UNAUTHORIZED {
+        // The request was answered already, an empty bucket only rejects the next ones.
+        let _ = rate_limiter.acquire(&client, FAILED_AUTHENTICATION_ROUTE, Instant::now());
+    }
+    Ok(response)
+}
+
+/// Rejects requests of clients exceeding their rate with 429 and a `Retry-After` header,
+/// and bodies larger than the limit of their route with 413. Wrapped inside
+/// [`authenticate`](crate::auth::authenticate), so clients can't pick their bucket with an
+/// invalid API key.
+pub async fn limit_requests(
+    mut request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(rate_limiter) = request.app_data::<web::Data<RateLimiter>>().cloned() else {
+        return next.call(request).await.map(|r| r.map_into_boxed_body());
+    };
+    let route = route_key(&request);
+    if let Err(retry_after) = rate_limiter.acquire(&client_key(&request), &route, Instant::now()) {
+        return Ok(request.error_response(ServerError::TooManyRequests(retry_after)));
+    }
+    if let Some(limit) = rate_limiter.max_payload(&route) {
+        match read_body(&mut request, limit).await {
+            Ok(body) => request.set_payload(body.into()),
+            Err(e) => return Ok(request.error_response(e)),
+        }
+    }
+    next.call(request).await.map(|r| r.map_into_boxed_body())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::auth::{authenticate, Authenticator, API_KEY_HEADER};
+    use crate::config::{AuthConfig, DeviceKey};
+    use crate::dto::AddItemRequest;
+    use crate::policy::Role;
+    use actix_web::middleware::from_fn;
+    use actix_web::{get, post, test, App, HttpResponse};
+
+    fn rate(burst: u32, per_second: f64) -> RateLimit {
+        RateLimit { burst, per_second }
+    }
+
+    #[actix_web::test]
+    async fn test_token_buckets() {
+        let rate_limiter = RateLimiter::new(LimitsConfig {
+            rate: rate(2, 1.0),
+            route_rates: HashMap::from([("POST /item".to_string(), rate(1, 0.5))]),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        assert!(rate_limiter.acquire("a", "GET /items", now).is_ok());
+        assert!(rate_limiter
+            .acquire("a", "GET /item/{item_id}", now)
+            .is_ok());
+        assert_eq!(
+            rate_limiter.acquire("a", "GET /items", now),
+            Err(Duration::from_secs(1))
+        );
+        assert!(rate_limiter.acquire("b", "GET /items", now).is_ok());
+
+        assert!(rate_limiter.acquire("a", "POST /item", now).is_ok());
+        assert_eq!(
+            rate_limiter.acquire("a", "POST /item", now),
+            Err(Duration::from_secs(2))
+        );
+        let later = now + Duration::from_millis(1500);
+        assert!(rate_limiter.acquire("a", "GET /items", later).is_ok());
+        assert_eq!(
+            rate_limiter.acquire("a", "POST /item", later),
+            Err(Duration::from_millis(500))
+        );
+
+        rate_limiter.purge_full_buckets(now + Duration::from_secs(2));
+        assert_eq!(rate_limiter.buckets.lock().unwrap().len(), 1);
+        rate_limiter.purge_full_buckets(now + Duration::from_secs(3));
+        assert!(rate_limiter.buckets.lock().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_max_buckets() {
+        let rate_limiter = RateLimiter::new(LimitsConfig {
+            rate: rate(1, 1.0),
+            max_buckets: 2,
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        assert!(rate_limiter.acquire("a", "GET /items", now).is_ok());
+        assert!(rate_limiter.acquire("b", "GET /items", now).is_ok());
+        // New clients are rejected while no bucket can be purged.
+        assert_eq!(
+            rate_limiter.acquire("c", "GET /items", now),
+            Err(Duration::from_secs(1))
+        );
+        assert!(rate_limiter.check("c", "GET /items", now).is_ok());
+        assert!(rate_limiter.acquire("a", "GET /items", now).is_err());
+
+        let later = now + Duration::from_secs(1);
+        assert!(rate_limiter.acquire("d", "GET /items", later).is_ok());
+        let buckets = rate_limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), 1);
+        assert!(buckets.contains_key(&("d".to_string(), String::new())));
+    }
+
+    #[post("/item")]
+    async fn echo_name(item: web::Json<AddItemRequest>) -> HttpResponse {
+        HttpResponse::Ok().body(item.name.clone())
+    }
+
+    #[get("/health/live")]
+    async fn live() -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_web::test]
+    async fn test_limit_requests() {
+        let rate_limiter = RateLimiter::new(LimitsConfig {
+            rate: rate(2, 0.1),
+            failed_authentication_rate: rate(2, 0.01),
+            max_add_item_payload: 64,
+            ..Default::default()
+        });
+        let device_key = |device: &str| DeviceKey {
+            key: format!("{}-key", device),
+            role: Role::Waiter,
+        };
+        let authenticator = Authenticator::from_config(&AuthConfig {
+            api_keys: HashMap::from([
+                ("a".to_string(), device_key("a")),
+                ("b".to_string(), device_key("b")),
+            ]),
+            ..Default::default()
+        })
+        .unwrap();
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(limit_requests))
+                .wrap(from_fn(authenticate))
+                .wrap(from_fn(limit_failed_authentications))
+                .app_data(web::Data::new(rate_limiter))
+                .app_data(web::Data::new(authenticator))
+                .service(live)
+                .service(web::scope(v1::SCOPE).service(echo_name)),
+        )
+        .await;
+        let add_item = |name: &str, key: &str| {
+            test::TestRequest::post()
+                .uri("/api/v1/item")
+                .insert_header((API_KEY_HEADER, key))
+                .set_json(AddItemRequest {
+                    name: name.to_string(),
+                    table_id: 1,
+                    quantity: 1,
+                })
+                .to_request()
+        };
+
+        let result = test::call_service(&app, add_item(&"x".repeat(64), "a-key")).await;
+        assert_eq!(result.status(), 413);
+        let result = test::call_service(&app, add_item("sushi", "a-key")).await;
+        assert_eq!(result.status(), 200);
+        assert_eq!(test::read_body(result).await, "sushi");
+
+        let result = test::call_service(&app, add_item("sushi", "a-key")).await;
+        assert_eq!(result.status(), 429);
+        assert_eq!(result.headers().get("Retry-After").unwrap(), "10");
+        // Invalid keys are rejected before they get a bucket.
+        let result = test::call_service(&app, add_item("sushi", "forged")).await;
+        assert_eq!(result.status(), 401);
+        let result = test::call_service(&app, add_item("sushi", "b-key")).await;
+        assert_eq!(result.status(), 200);
+
+        // Requests without credentials are told apart by their address.
+        let live_from = |address: &str| {
+            test::TestRequest::get()
+                .uri("/health/live")
+                .peer_addr(address.parse().unwrap())
+                .to_request()
+        };
+        for status in [200, 200, 429] {
+            let result = test::call_service(&app, live_from("10.0.0.1:4000")).await;
+            assert_eq!(result.status(), status);
+        }
+        let result = test::call_service(&app, live_from("10.0.0.2:4000")).await;
+        assert_eq!(result.status(), 200);
+
+        // Repeated invalid credentials get the address rejected before they are checked.
+        let list_from = |address: &str, key: &str| {
+            test::TestRequest::get()
+                .uri("/api/v1/items")
+                .insert_header((API_KEY_HEADER, key))
+                .peer_addr(address.parse().unwrap())
+                .to_request()
+        };
+        for status in [401, 401, 429, 429] {
+            let result = test::call_service(&app, list_from("10.0.0.3:4000", "forged")).await;
+            assert_eq!(result.status(), status);
+        }
+        let result = test::call_service(&app, list_from("10.0.0.3:4000", "a-key")).await;
+        assert_eq!(result.status(), 429);
+        assert_eq!(result.headers().get("Retry-After").unwrap(), "100");
+        let result = test::call_service(&app, list_from("10.0.0.4:4000", "forged")).await;
+        assert_eq!(result.status(), 401);
+    }
+}
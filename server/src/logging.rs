@@ -0,0 +1,237 @@
+//! Structured JSON logs with request ids.
+//!
+//! Every request gets an id, taken from its `X-Request-Id` header or generated, which is
+//! echoed in the response and recorded on the request span opened by
+//! [`trace_requests`](crate::telemetry::trace_requests). Handlers add the ids of the table
+//! and the item they work on to that span, so every log record of a request carries them
+//! in its `spans`. Records of the `log` crate are logged as JSON as well.
+
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use rand::Rng;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::config::TracingConfig;
+use crate::telemetry;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+/// Longest request id accepted from clients, longer ones are replaced.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Id of the request, stored in the extensions of the request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestId(pub String);
+
+/// Layer writing every record as a line of JSON to `writer`, with the fields of the spans
+/// it was recorded in.
+pub fn json_layer<S, W>(writer: W) -> impl Layer<S>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt::layer()
+        .json()
+        .flatten_event(true)
+        .with_current_span(false)
+        .with_span_list(true)
+        .with_writer(writer)
+}
+
+/// Logs JSON to stdout, filtered by `RUST_LOG` and `info` by default. Exports the spans
+/// as well when a collector is configured, the returned provider has to be shut down
+/// before the server exits.
+pub fn init_logging(config: &TracingConfig) -> Option<SdkTracerProvider> {
+    let tracer_provider = config.otlp_endpoint.as_ref().map(|endpoint| {
+        telemetry::tracer_provider(endpoint, &config.service_name).map_err(|e| e.to_string())
+    });
+    let tracer_layer = match &tracer_provider {
+        Some(Ok(provider)) => Some(telemetry::layer(provider)),
+        _ => None,
+    };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let result = tracing_subscriber::registry()
+        .with(filter)
+        .with(json_layer(std::io::stdout))
+        .with(tracer_layer)
+        .try_init();
+    if let Err(e) = result {
+        eprintln!("failed to initialize logging: {}", e);
+    }
+
+    // Logged only now that the logs are set up.
+    match (tracer_provider, &config.otlp_endpoint) {
+        (Some(Ok(provider)), Some(endpoint)) => {
+            log::info!("exporting traces to {}", endpoint);
+            Some(provider)
+        }
+        (Some(Err(e)), _) => {
+            log::warn!("traces are not exported: {}", e);
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Records the table the request works on in the logs of the request.
+pub fn record_table_id(table_id: i32) {
+    tracing::Span::current().record("table_id", table_id);
+}
+
+/// Records the item the request works on in the logs of the request.
+pub fn record_item_id(item_id: i64) {
+    tracing::Span::current().record("item_id", item_id);
+}
+
+fn new_request_id() -> String {
+    format!("{:032x}", rand::thread_rng().gen::<u128>())
+}
+
+/// Ids of clients are only used when they can be logged and echoed safely.
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Gives the request an id, echoed in the `X-Request-Id` header of the response and
+/// appended to the body of error responses.
+pub async fn request_id(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(new_request_id);
+    request.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut response = next.call(request).await?.map_into_boxed_body();
+    if response.response().error().is_some() {
+        let (request, response_without_id) = response.into_parts();
+        let (mut error_response, error_body) = response_without_id.into_parts();
+        let message = body::to_bytes(error_body).await.unwrap_or_default();
+        let message = String::from_utf8_lossy(&message);
+        let with_id = if message.is_empty() {
+            format!("request id: {}", id)
+        } else {
+            format!("{}\nrequest id: {}", message, id)
+        };
+        error_response.headers_mut().remove("content-length");
+        response = ServiceResponse::new(request, error_response.set_body(BoxBody::new(with_id)));
+    }
+    // Valid ids and generated ones are visible ASCII.
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static("x-request-id"), value);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::auth::authenticate_as_manager;
+    use crate::handlers::get_items_for_table;
+    use crate::telemetry::trace_requests;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App};
+    use persistence::postgres_repositories::PgRepositories;
+    use persistence::{init_test_db, reset_test_db};
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    /// Collects the written logs.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_request_ids() {
+        let logs = Buffer::default();
+        let _subscriber = tracing_subscriber::registry()
+            .with(json_layer(logs.clone()))
+            .set_default();
+        let item_repository = init_test_db().await;
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate_as_manager))
+                .wrap(from_fn(trace_requests))
+                .wrap(from_fn(request_id))
+                .app_data(web::Data::new(PgRepositories::new(item_repository.clone())))
+                .service(get_items_for_table),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/table/7")
+            .insert_header((REQUEST_ID_HEADER, "order-42"))
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 200);
+        assert_eq!(result.headers().get(REQUEST_ID_HEADER).unwrap(), "order-42");
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let completed = logs
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .find(|record| record["message"] == "request completed")
+            .unwrap_or_else(|| panic!("no completed request in {}", logs));
+        assert_eq!(completed["status"], 200);
+        assert_eq!(completed["spans"][0]["request_id"], "order-42");
+        assert_eq!(completed["spans"][0]["table_id"], 7);
+
+        for header in [None, Some("bad id"), Some("")] {
+            let mut request = test::TestRequest::get().uri("/table/x");
+            if let Some(header) = header {
+                request = request.insert_header((REQUEST_ID_HEADER, header));
+            }
+            let result = test::call_service(&app, request.to_request()).await;
+            assert_eq!(result.status(), 404);
+            let id = result.headers().get(REQUEST_ID_HEADER).unwrap().clone();
+            let id = id.to_str().unwrap();
+            assert_eq!(id.len(), 32);
+            assert!(id.bytes().all(|b| b.is_ascii_hexdigit()));
+        }
+
+        let request = test::TestRequest::get()
+            .uri("/table/7?limit=0")
+            .insert_header((REQUEST_ID_HEADER, "order-43"))
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 400);
+        let body = test::read_body(result).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.ends_with("\nrequest id: order-43"), "{}", body);
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+}
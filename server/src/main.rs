@@ -1,31 +1,207 @@
-mod dto;
-mod errors;
-mod handlers;
-
-use actix_web::{middleware, web, App, HttpServer};
+use actix_web::middleware::from_fn;
+use actix_web::{web, App, HttpServer};
+use persistence::health_repository::HealthRepository;
+use persistence::migrations::{
+    migrate_down, migrate_up, migration_status, previous_version, MigrationStatus,
+};
+use persistence::postgres_item_repository::PgItemRepository;
 use persistence::postgres_repositories::PgRepositories;
-use server::handlers::*;
-use std::io::Result;
+use persistence::replicated_item_repository::ReplicatedItemRepository;
+use persistence::retry::ConnectOptions;
+use server::api;
+use server::auth::{authenticate, Authenticator};
+use server::cli::{Command, MigrateCommand, USAGE};
+use server::config::{ServerConfig, TracingConfig};
+use server::consistency::read_your_writes;
+use server::graphql::{build_schema, graphiql, graphql};
+use server::health::{live, ready};
+use server::idempotency::purge_expired_keys_periodically;
+use server::limits::{
+    limit_failed_authentications, limit_requests, purge_full_buckets_periodically, RateLimiter,
+};
+use server::logging::{init_logging, request_id};
+use server::metrics::{get_metrics, record_metrics, Metrics};
+use server::notifications::Notifier;
+use server::openapi::swagger_ui;
+use server::session::purge_expired_sessions_periodically;
+use server::shutdown::{shut_down_on_signal, Connections};
+use server::telemetry::trace_requests;
+use std::io::{Error, Result};
 
 #[actix_web::main]
 async fn main() -> Result<()> {
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    let command = match Command::parse(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let tracer_provider = init_logging(&TracingConfig::from_env());
+    let mut config = ServerConfig::from_env();
+    let result = match command {
+        Command::Serve { migrate } => {
+            config.lifecycle.connect.migrate = migrate;
+            serve(config).await
+        }
+        Command::Migrate(command) => run_migrate_command(command, &config).await,
+    };
 
-    let repositories = PgRepositories::init_prod().await;
+    if let Some(tracer_provider) = tracer_provider {
+        if let Err(e) = tracer_provider.shutdown() {
+            log::warn!("failed to export the remaining spans: {}", e);
+        }
+    }
+    result
+}
+
+fn print_migration(migration: &MigrationStatus) {
+    let applied_at = match migration.applied_at {
+        Some(applied_at) => applied_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => "pending".to_string(),
+    };
+    let reversible = if migration.reversible {
+        ""
+    } else {
+        " (irreversible)"
+    };
+    println!(
+        "{}  {:<19}  {}{}",
+        migration.version, applied_at, migration.description, reversible
+    );
+}
+
+async fn run_migrate_command(command: MigrateCommand, config: &ServerConfig) -> Result<()> {
+    let options = ConnectOptions {
+        migrate: false,
+        ..config.lifecycle.connect
+    };
+    let connection_pool = PgItemRepository::init_prod(&options)
+        .await
+        .map_err(|e| Error::other(format!("failed to connect to the database: {}", e)))?
+        .connection_pool;
+    let migration_error = |e| Error::other(format!("failed to migrate the database: {}", e));
+    match command {
+        MigrateCommand::Status => {
+            let migrations = migration_status(&connection_pool)
+                .await
+                .map_err(migration_error)?;
+            migrations.iter().for_each(print_migration);
+        }
+        MigrateCommand::Up => {
+            let applied = migrate_up(&connection_pool)
+                .await
+                .map_err(migration_error)?;
+            println!("applied {} migrations", applied.len());
+            for version in applied {
+                println!("{}", version);
+            }
+        }
+        MigrateCommand::Down { to } => {
+            let migrations = migration_status(&connection_pool)
+                .await
+                .map_err(migration_error)?;
+            let Some(target) = to.or_else(|| previous_version(&migrations)) else {
+                println!("no migration is applied");
+                return Ok(());
+            };
+            let reverted = migrate_down(&connection_pool, target)
+                .await
+                .map_err(migration_error)?;
+            println!("reverted {} migrations", reverted.len());
+            for version in reverted {
+                println!("{}", version);
+            }
+        }
+    }
+    connection_pool.close().await;
+    Ok(())
+}
+
+async fn serve(config: ServerConfig) -> Result<()> {
+    let mut repositories =
+        PgRepositories::init_prod(&config.lifecycle.connect, config.resilience, config.cache)
+            .await
+            .map_err(|e| Error::other(format!("failed to connect to the database: {}", e)))?;
+    if let Some(url) = &config.read_replica.url {
+        let routing = config.read_replica.routing;
+        let replica = ReplicatedItemRepository::connect_replica(url, &routing)
+            .map_err(|e| Error::other(format!("invalid read replica: {}", e)))?;
+        repositories = repositories.with_read_replica(replica, routing);
+        log::info!("reading lists of items and reports from the read replica");
+    }
+    if !config.lifecycle.connect.migrate {
+        match repositories.health_repository.pending_migrations().await {
+            Ok(pending) if pending.is_empty() => {}
+            Ok(pending) => log::warn!(
+                "{} migrations are not applied, the server is not ready until they are: {}",
+                pending.len(),
+                pending.join(", ")
+            ),
+            Err(e) => log::warn!("failed to check the migrations: {}", e),
+        }
+    }
+    let schema = build_schema(repositories.clone());
+    let authenticator = web::Data::new(Authenticator::from_config(&config.auth)?);
+    let notifier = web::Data::new(Notifier::default());
+    let rate_limiter = web::Data::new(RateLimiter::new(config.limits.clone()));
+    let metrics = web::Data::new(Metrics::new());
+
+    actix_web::rt::spawn(purge_expired_keys_periodically(
+        repositories.clone(),
+        config.idempotency.key_ttl,
+    ));
+    actix_web::rt::spawn(purge_expired_sessions_periodically(
+        repositories.clone(),
+        config.auth.session.idle_timeout,
+    ));
+    actix_web::rt::spawn(purge_full_buckets_periodically(rate_limiter.clone()));
 
     log::info!("starting HTTP server at http://localhost:8080");
 
-    HttpServer::new(move || {
+    let app_repositories = repositories.clone();
+    let app_notifier = notifier.clone();
+    let connections = Connections::default();
+    let tracked = connections.clone();
+    let server = HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(repositories.clone()))
-            .wrap(middleware::Logger::default())
-            .service(add_item)
-            .service(get_item)
-            .service(get_items_for_table)
-            .service(get_all_items)
-            .service(remove_item)
+            .app_data(web::Data::new(app_repositories.clone()))
+            .app_data(web::Data::new(schema.clone()))
+            .app_data(web::Data::new(config.idempotency.clone()))
+            .app_data(web::Data::new(config.auth.session.clone()))
+            .app_data(authenticator.clone())
+            .app_data(app_notifier.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(metrics.clone())
+            .app_data(web::JsonConfig::default().limit(config.limits.max_json_payload))
+            .wrap(from_fn(read_your_writes))
+            .wrap(from_fn(limit_requests))
+            .wrap(from_fn(authenticate))
+            .wrap(from_fn(limit_failed_authentications))
+            .wrap(from_fn(record_metrics))
+            .wrap(from_fn(trace_requests))
+            .wrap(from_fn(request_id))
+            .service(live)
+            .service(ready)
+            .service(get_metrics)
+            .service(graphql)
+            .service(graphiql)
+            .service(swagger_ui())
+            .configure(api::configure)
     })
+    .on_connect(move |connection, extensions| tracked.track(connection, extensions))
+    .disable_signals()
     .bind(("localhost", 8080))?
-    .run()
-    .await
+    .run();
+    actix_web::rt::spawn(shut_down_on_signal(
+        server.handle(),
+        notifier,
+        connections,
+        config.lifecycle.shutdown_timeout,
+    ));
+    server.await?;
+
+    repositories.close().await;
+    log::info!("closed the database connections");
+    Ok(())
 }
@@ -0,0 +1,379 @@
+//! Prometheus metrics served at `/metrics` to staff members and devices with the
+//! `view_metrics` permission.
+//!
+//! Request metrics are recorded by the [`record_metrics`] middleware, the gauges of the
+//! connection pool and of the open items as well as the statistics of the item cache and
+//! of the read replica are updated when the metrics are scraped.
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{get, web, Error, HttpResponse};
+use persistence::cached_item_repository::CacheCounts;
+use persistence::item_repository::ItemRepository;
+use persistence::postgres_repositories::PgRepositories;
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, Gauge, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    Opts, Registry, TextEncoder,
+};
+use std::time::Instant;
+
+use crate::policy::{require, Authorized};
+
+use crate::errors::ServerError;
+
+/// Route label of requests which did not match any route, so that scans of random paths
+/// don't create a time series per path.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    errors: IntCounterVec,
+    pool_connections: IntGaugeVec,
+    pool_max_connections: IntGauge,
+    pool_acquire_duration: Gauge,
+    open_items: IntGaugeVec,
+    average_time_to_prepare: Gauge,
+    cache_hits: IntCounterVec,
+    cache_misses: IntCounterVec,
+    cache_entries: IntGauge,
+    replica_reads: IntCounter,
+    replica_fallbacks: IntCounter,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        let metrics = Metrics {
+            registry: Registry::new(),
+            requests: IntCounterVec::new(
+                Opts::new("http_requests_total", "Handled HTTP requests"),
+                &["method", "route", "status"],
+            )
+            .unwrap(),
+            request_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "http_request_duration_seconds",
+                    "Time to handle HTTP requests",
+                ),
+                &["method", "route"],
+            )
+            .unwrap(),
+            errors: IntCounterVec::new(
+                Opts::new("server_errors_total", "Failed requests by kind of error"),
+                &["error"],
+            )
+            .unwrap(),
+            pool_connections: IntGaugeVec::new(
+                Opts::new(
+                    "db_pool_connections",
+                    "Connections of the database pool by state",
+                ),
+                &["state"],
+            )
+            .unwrap(),
+            pool_max_connections: IntGauge::new(
+                "db_pool_max_connections",
+                "Maximum number of connections of the database pool",
+            )
+            .unwrap(),
+            pool_acquire_duration: Gauge::new(
+                "db_pool_acquire_duration_seconds",
+                "Time it took to acquire a connection from the database pool when the metrics were scraped",
+            )
+            .unwrap(),
+            open_items: IntGaugeVec::new(
+                Opts::new("open_items", "Ordered items which were not served yet by table"),
+                &["table_id"],
+            )
+            .unwrap(),
+            average_time_to_prepare: Gauge::new(
+                "average_time_to_prepare_minutes",
+                "Average time to prepare the open items",
+            )
+            .unwrap(),
+            cache_hits: IntCounterVec::new(
+                Opts::new(
+                    "item_cache_hits_total",
+                    "Lookups served by the item cache by kind of result",
+                ),
+                &["cache"],
+            )
+            .unwrap(),
+            cache_misses: IntCounterVec::new(
+                Opts::new(
+                    "item_cache_misses_total",
+                    "Lookups the item cache passed to the database by kind of result",
+                ),
+                &["cache"],
+            )
+            .unwrap(),
+            cache_entries: IntGauge::new("item_cache_entries", "Results held by the item cache")
+                .unwrap(),
+            replica_reads: IntCounter::new(
+                "db_replica_reads_total",
+                "Reads served by the read replica",
+            )
+            .unwrap(),
+            replica_fallbacks: IntCounter::new(
+                "db_replica_fallbacks_total",
+                "Reads sent to the primary because the read replica was down or behind",
+            )
+            .unwrap(),
+        };
+        let collectors: [Box<dyn Collector>; 13] = [
+            Box::new(metrics.requests.clone()),
+            Box::new(metrics.request_duration.clone()),
+            Box::new(metrics.errors.clone()),
+            Box::new(metrics.pool_connections.clone()),
+            Box::new(metrics.pool_max_connections.clone()),
+            Box::new(metrics.pool_acquire_duration.clone()),
+            Box::new(metrics.open_items.clone()),
+            Box::new(metrics.average_time_to_prepare.clone()),
+            Box::new(metrics.cache_hits.clone()),
+            Box::new(metrics.cache_misses.clone()),
+            Box::new(metrics.cache_entries.clone()),
+            Box::new(metrics.replica_reads.clone()),
+            Box::new(metrics.replica_fallbacks.clone()),
+        ];
+        for collector in collectors {
+            // The names are unique, so registering on the own registry can't fail.
+            metrics.registry.register(collector).unwrap();
+        }
+        metrics
+    }
+
+    fn observe_request(&self, method: &str, route: &str, status: u16, started_at: Instant) {
+        self.requests
+            .with_label_values(&[method, route, &status.to_string()])
+            .inc();
+        self.request_duration
+            .with_label_values(&[method, route])
+            .observe(started_at.elapsed().as_secs_f64());
+    }
+
+    fn observe_error(&self, error: &ServerError) {
+        self.errors.with_label_values(&[error.variant()]).inc();
+    }
+
+    /// Catches the counters of the item cache and of the read replica up with their
+    /// statistics.
+    fn update_repository_stats(&self, repositories: &PgRepositories) {
+        let catch_up = |counter: &IntCounter, total: u64| {
+            counter.inc_by(total.saturating_sub(counter.get()));
+        };
+        let stats = repositories.item_repository.stats();
+        for (cache, counts) in [("items", stats.items), ("tables", stats.tables)] {
+            let CacheCounts { hits, misses } = counts;
+            catch_up(&self.cache_hits.with_label_values(&[cache]), hits);
+            catch_up(&self.cache_misses.with_label_values(&[cache]), misses);
+        }
+        self.cache_entries.set(stats.entries as i64);
+
+        let stats = repositories.replicated_item_repository().stats();
+        catch_up(&self.replica_reads, stats.replica_reads);
+        catch_up(&self.replica_fallbacks, stats.primary_fallbacks);
+    }
+
+    /// Updates the gauges of the connection pool and of the open items. A failing query
+    /// keeps the previous values of the item gauges.
+    async fn update_gauges(&self, repositories: &PgRepositories) {
+        let pool = repositories.connection_pool();
+        let idle = pool.num_idle() as i64;
+        let size = i64::from(pool.size());
+        self.pool_connections.with_label_values(&["idle"]).set(idle);
+        self.pool_connections
+            .with_label_values(&["in_use"])
+            .set(size - idle);
+        self.pool_max_connections
+            .set(i64::from(pool.options().get_max_connections()));
+        let started_at = Instant::now();
+        match pool.acquire().await {
+            Ok(_) => self
+                .pool_acquire_duration
+                .set(started_at.elapsed().as_secs_f64()),
+            Err(e) => log::warn!("failed to acquire a connection for the metrics: {}", e),
+        }
+
+        let stats = match repositories.item_repository.get_table_stats().await {
+            Ok(stats) => stats,
+            Err(e) => {
+                log::warn!("failed to get the statistics of the tables: {}", e);
+                return;
+            }
+        };
+        // Tables without open items are dropped instead of being reported with 0 forever.
+        self.open_items.reset();
+        let mut open_items = 0;
+        let mut time_to_prepare = 0.0;
+        for table in stats {
+            self.open_items
+                .with_label_values(&[&table.table_id.to_string()])
+                .set(table.open_items);
+            open_items += table.open_items;
+            time_to_prepare += table.average_time_to_prepare * table.open_items as f64;
+        }
+        self.average_time_to_prepare.set(if open_items > 0 {
+            time_to_prepare / open_items as f64
+        } else {
+            0.0
+        });
+    }
+
+    fn encode(&self) -> Result<String, ServerError> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|e| ServerError::Internal(e.to_string()))?;
+        String::from_utf8(buffer).map_err(|e| ServerError::Internal(e.to_string()))
+    }
+}
+
+/// Counts the requests and measures their duration by route, and counts the failed ones
+/// by the variant of their [`ServerError`].
+pub async fn record_metrics(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(metrics) = request.app_data::<web::Data<Metrics>>().cloned() else {
+        return next.call(request).await.map(|r| r.map_into_boxed_body());
+    };
+    let started_at = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .match_pattern()
+        .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+
+    let result = next.call(request).await.map(|r| r.map_into_boxed_body());
+    let (status, error) = match &result {
+        Ok(response) => (response.status(), response.response().error()),
+        Err(e) => (e.as_response_error().status_code(), Some(e)),
+    };
+    metrics.observe_request(&method, &route, status.as_u16(), started_at);
+    if let Some(error) = error.and_then(|e| e.as_error::<ServerError>()) {
+        metrics.observe_error(error);
+    }
+    result
+}
+
+#[get("/metrics")]
+pub async fn get_metrics(
+    _staff: Authorized<require::ViewMetrics>,
+    metrics: web::Data<Metrics>,
+    repositories: web::Data<PgRepositories>,
+) -> Result<HttpResponse, ServerError> {
+    metrics.update_gauges(&repositories).await;
+    metrics.update_repository_stats(&repositories);
+    Ok(HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(metrics.encode()?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::auth::{authenticate, Authenticator, API_KEY_HEADER};
+    use crate::config::{AuthConfig, DeviceKey};
+    use crate::handlers::get_item;
+    use crate::policy::Role;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App};
+    use domain::item::Item;
+    use persistence::{init_test_db, reset_test_db};
+    use std::collections::HashMap;
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_metrics() {
+        let item_repository = init_test_db().await;
+        for item in [
+            Item::new("sushi".to_string(), 1, 5, 3),
+            Item::new("ramen".to_string(), 2, 10, 1),
+        ] {
+            item_repository
+                .add_item(item.to_insert_dao(), "test")
+                .await
+                .unwrap();
+        }
+        let device_key = |role: Role| DeviceKey {
+            key: role.to_string(),
+            role,
+        };
+        let authenticator = Authenticator::from_config(&AuthConfig {
+            api_keys: HashMap::from([
+                ("prometheus".to_string(), device_key(Role::Monitoring)),
+                ("tablet".to_string(), device_key(Role::Waiter)),
+            ]),
+            ..Default::default()
+        })
+        .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(authenticator))
+                .wrap(from_fn(authenticate))
+                .wrap(from_fn(record_metrics))
+                .app_data(web::Data::new(Metrics::new()))
+                .app_data(web::Data::new(PgRepositories::new(item_repository.clone())))
+                .service(get_metrics)
+                .service(get_item),
+        )
+        .await;
+
+        let get = |uri: &str, key: &str| {
+            test::TestRequest::get()
+                .uri(uri)
+                .insert_header((API_KEY_HEADER, key))
+                .to_request()
+        };
+        for uri in ["/item/1", "/item/1", "/item/42", "/unknown/path"] {
+            test::call_service(&app, get(uri, "waiter")).await;
+        }
+        let result = test::call_service(&app, get("/metrics", "monitoring")).await;
+        assert_eq!(result.status(), 200);
+        let body = test::read_body(result).await;
+        let body = std::str::from_utf8(&body).unwrap();
+
+        for line in [
+            r#"http_requests_total{method="GET",route="/item/{item_id}",status="200"} 2"#,
+            r#"http_requests_total{method="GET",route="/item/{item_id}",status="404"} 1"#,
+            r#"http_requests_total{method="GET",route="unmatched",status="404"} 1"#,
+            r#"http_request_duration_seconds_count{method="GET",route="/item/{item_id}"} 3"#,
+            r#"server_errors_total{error="not_found"} 1"#,
+            r#"open_items{table_id="1"} 3"#,
+            r#"open_items{table_id="2"} 1"#,
+            "average_time_to_prepare_minutes 6.25",
+            r#"item_cache_hits_total{cache="items"} 1"#,
+            r#"item_cache_misses_total{cache="items"} 2"#,
+            r#"item_cache_misses_total{cache="tables"} 0"#,
+            "db_replica_reads_total 0",
+        ] {
+            assert!(
+                body.lines().any(|l| l == line),
+                "{} missing in\n{}",
+                line,
+                body
+            );
+        }
+        assert!(body.contains(r#"db_pool_connections{state="in_use"}"#));
+        assert!(body.contains("db_pool_max_connections"));
+        assert!(body.contains("db_pool_acquire_duration_seconds"));
+
+        let request = test::TestRequest::get().uri("/metrics").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 401);
+        let result = test::call_service(&app, get("/metrics", "waiter")).await;
+        assert_eq!(result.status(), 403);
+        // The metrics credential grants nothing else.
+        let result = test::call_service(&app, get("/item/1", "monitoring")).await;
+        assert_eq!(result.status(), 403);
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+}
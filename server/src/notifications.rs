@@ -0,0 +1,160 @@
+//! Notifications pushed to staff members as server-sent events.
+//!
+//! Every open `GET /notifications` stream subscribes to the [`Notifier`] with the id of
+//! its staff member, notifications about a table only reach the waiters assigned to it.
+
+use actix_web::web::Bytes;
+use futures_util::stream::{self, Stream};
+use persistence::dao::{ItemDao, ItemStatus};
+use persistence::postgres_repositories::PgRepositories;
+use persistence::staff_repository::StaffRepository;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::dto::Notification;
+
+#[derive(Default)]
+pub struct Notifier {
+    subscribers: Mutex<HashMap<i32, Vec<UnboundedSender<Notification>>>>,
+    closed: AtomicBool,
+}
+
+impl Notifier {
+    /// Opens a stream of the notifications sent to the staff member.
+    pub fn subscribe(&self, staff_id: i32) -> UnboundedReceiver<Notification> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if !self.closed.load(Ordering::SeqCst) {
+            subscribers.entry(staff_id).or_default().push(sender);
+        }
+        receiver
+    }
+
+    /// Sends the notification to every open stream of the staff member and returns how
+    /// many streams received it. Closed streams are dropped.
+    pub fn notify(&self, staff_id: i32, notification: &Notification) -> usize {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let Some(senders) = subscribers.get_mut(&staff_id) else {
+            return 0;
+        };
+        senders.retain(|sender| sender.send(notification.clone()).is_ok());
+        let delivered = senders.len();
+        if delivered == 0 {
+            subscribers.remove(&staff_id);
+        }
+        delivered
+    }
+
+    /// Ends every open stream once it has sent the notifications already queued for it,
+    /// streams opened afterwards end right away. Returns how many streams were open.
+    pub fn close(&self) -> usize {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        self.closed.store(true, Ordering::SeqCst);
+        subscribers.drain().map(|(_, senders)| senders.len()).sum()
+    }
+}
+
+/// Tells the waiters of the table of `item` that it is ready. Failures are only logged,
+/// the status of the item has already been changed.
+pub async fn notify_when_ready(repositories: &PgRepositories, notifier: &Notifier, item: &ItemDao) {
+    if item.status != ItemStatus::Ready {
+        return;
+    }
+    let waiters = match repositories
+        .staff_repository
+        .get_table_waiters(item.table_id)
+        .await
+    {
+        Ok(waiters) => waiters,
+        Err(e) => {
+            log::warn!(
+                "failed to get the waiters of table {}: {}",
+                item.table_id,
+                e
+            );
+            return;
+        }
+    };
+    let notification = Notification::ItemReady {
+        item_id: item.id,
+        table_id: item.table_id,
+        name: item.name.clone(),
+        quantity: item.quantity,
+    };
+    for staff_id in waiters {
+        notifier.notify(staff_id, &notification);
+    }
+}
+
+/// Encodes the notifications as `text/event-stream`, one `data:` line of JSON per event.
+pub fn event_stream(
+    receiver: UnboundedReceiver<Notification>,
+) -> impl Stream<Item = Result<Bytes, Infallible>> {
+    stream::unfold(receiver, |mut receiver| async move {
+        let notification = receiver.recv().await?;
+        let json = serde_json::to_string(&notification).unwrap_or_default();
+        Some((Ok(Bytes::from(format!("data: {}\n\n", json))), receiver))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_util::StreamExt;
+
+    fn item_ready(item_id: i64) -> Notification {
+        Notification::ItemReady {
+            item_id,
+            table_id: 1,
+            name: "sushi".to_string(),
+            quantity: 2,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_notifier() {
+        let notifier = Notifier::default();
+        let first = notifier.subscribe(1);
+        let second = notifier.subscribe(1);
+        let mut other = notifier.subscribe(2);
+
+        assert_eq!(notifier.notify(1, &item_ready(7)), 2);
+        assert!(other.try_recv().is_err());
+
+        drop(second);
+        assert_eq!(notifier.notify(1, &item_ready(8)), 1);
+        assert_eq!(notifier.notify(3, &item_ready(8)), 0);
+
+        let mut events = Box::pin(event_stream(first));
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(
+            event,
+            "data: {\"event\":\"item_ready\",\"item_id\":7,\"table_id\":1,\"name\":\"sushi\",\"quantity\":2}\n\n"
+        );
+        assert!(events.next().await.unwrap().is_ok());
+        drop(notifier);
+        assert!(events.next().await.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_close() {
+        let notifier = Notifier::default();
+        let receiver = notifier.subscribe(1);
+        notifier.subscribe(2);
+        notifier.notify(1, &item_ready(7));
+
+        assert_eq!(notifier.close(), 2);
+        let mut events = Box::pin(event_stream(receiver));
+        assert!(events.next().await.unwrap().is_ok());
+        assert!(events.next().await.is_none());
+
+        assert!(Box::pin(event_stream(notifier.subscribe(1)))
+            .next()
+            .await
+            .is_none());
+        assert_eq!(notifier.notify(1, &item_ready(8)), 0);
+    }
+}
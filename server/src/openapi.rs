@@ -0,0 +1,287 @@
+use utoipa::openapi::header::HeaderBuilder;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::{Object, ResponseBuilder, Type};
+use utoipa::{Modify, OpenApi};
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::auth::API_KEY_HEADER;
+use crate::dto::*;
+use crate::handlers;
+use crate::policy::Role;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER))),
+        );
+        components.add_security_scheme(
+            "bearer_token",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// Adds the responses telling clients to retry later to every operation: 429 when they
+/// exceed their rate limit, 503 while the database is unavailable.
+struct RetryLaterResponses;
+
+impl Modify for RetryLaterResponses {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let response = |description: &str, retry_after: &str| {
+            ResponseBuilder::new()
+                .description(description)
+                .header(
+                    "Retry-After",
+                    HeaderBuilder::new()
+                        .schema(Object::with_type(Type::Integer))
+                        .description(Some(retry_after))
+                        .build(),
+                )
+                .build()
+        };
+        let responses = [
+            (
+                "429",
+                response(
+                    "Too many requests of the client",
+                    "Seconds until the next request is accepted",
+                ),
+            ),
+            (
+                "503",
+                response(
+                    "The database is unavailable",
+                    "Seconds until the database is tried again",
+                ),
+            ),
+        ];
+        for path in openapi.paths.paths.values_mut() {
+            let operations = [
+                &mut path.get,
+                &mut path.put,
+                &mut path.post,
+                &mut path.delete,
+                &mut path.patch,
+            ];
+            for operation in operations.into_iter().flatten() {
+                for (status, response) in &responses {
+                    operation
+                        .responses
+                        .responses
+                        .insert(status.to_string(), response.clone().into());
+                }
+            }
+        }
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Simple Restaurant API"),
+    servers((url = "/api/v1", description = "Current version")),
+    modifiers(&SecuritySchemes, &RetryLaterResponses),
+    security(("api_key" = []), ("bearer_token" = [])),
+    paths(
+        handlers::add_item,
+        handlers::get_item,
+        handlers::get_items_for_table,
+        handlers::get_all_items,
+        handlers::remove_item,
+        handlers::update_item,
+        handlers::set_item_status,
+        handlers::transfer_table,
+        handlers::execute_batch,
+        handlers::login,
+        handlers::logout,
+        handlers::add_staff,
+        handlers::assign_waiters,
+        handlers::get_staff_tables,
+        handlers::notifications,
+    ),
+    components(schemas(
+        AddItemRequest,
+        AddItemResponse,
+        UpdateItemRequest,
+        ItemStatus,
+        SetItemStatusRequest,
+        GetItemResponse,
+        GetItemForTableResponse,
+        GetAllItemsResponse,
+        ItemSort,
+        SortOrder,
+        BatchRequest,
+        BatchModeRequest,
+        BatchOperationRequest,
+        BatchResponse,
+        BatchOperationResponse,
+        LoginRequest,
+        SessionResponse,
+        AddStaffRequest,
+        AddStaffResponse,
+        Role,
+        AssignWaitersRequest,
+        TableWaitersResponse,
+        TableItemsResponse,
+        GetStaffTablesResponse,
+        Notification,
+    )),
+    tags(
+        (name = "items", description = "Ordered items"),
+        (name = "tables", description = "Items grouped by table"),
+        (name = "staff", description = "Staff members and their sessions"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Serves the OpenAPI document at `/openapi.json` together with the Swagger UI page.
+pub fn swagger_ui() -> SwaggerUi {
+    SwaggerUi::new("/swagger-ui/{_:.*}").url("/openapi.json", ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::{test, App};
+    use utoipa::openapi::path::HttpMethod;
+    use utoipa::openapi::{RefOr, Response};
+
+    const ROUTE_MACROS: [(&str, HttpMethod); 5] = [
+        ("get", HttpMethod::Get),
+        ("post", HttpMethod::Post),
+        ("put", HttpMethod::Put),
+        ("patch", HttpMethod::Patch),
+        ("delete", HttpMethod::Delete),
+    ];
+
+    /// Sources of every module declaring routes with the actix route macros.
+    const ROUTE_SOURCES: [&str; 4] = [
+        include_str!("handlers.rs"),
+        include_str!("graphql.rs"),
+        include_str!("health.rs"),
+        include_str!("metrics.rs"),
+    ];
+
+    /// Routes served outside of `/api/v1`, which are not part of the REST API document.
+    const UNDOCUMENTED_ROUTES: [(&str, &str); 5] = [
+        ("post", "/graphql"),
+        ("get", "/graphql"),
+        ("get", "/health/live"),
+        ("get", "/health/ready"),
+        ("get", "/metrics"),
+    ];
+
+    /// Collects `(macro, method, path)` of every actix route macro in the route sources.
+    fn declared_routes() -> Vec<(&'static str, HttpMethod, String)> {
+        ROUTE_SOURCES
+            .iter()
+            .flat_map(|source| source.lines())
+            .map(str::trim)
+            .filter_map(|line| {
+                ROUTE_MACROS.iter().find_map(|(name, method)| {
+                    let path = line
+                        .strip_prefix("#[")?
+                        .strip_prefix(name)?
+                        .strip_prefix('(')?
+                        .split('"')
+                        .nth(1)?;
+                    Some((*name, method.clone(), path.to_string()))
+                })
+            })
+            .collect()
+    }
+
+    fn schema_name(reference: &str) -> &str {
+        reference.trim_start_matches("#/components/schemas/")
+    }
+
+    #[actix_web::test]
+    async fn test_every_route_is_documented() {
+        let doc = ApiDoc::openapi();
+        let schemas = doc.components.as_ref().unwrap().schemas.clone();
+        let routes = declared_routes();
+        for (name, path) in UNDOCUMENTED_ROUTES {
+            assert!(
+                routes
+                    .iter()
+                    .any(|route| (route.0, route.2.as_str()) == (name, path)),
+                "{} {} is no route anymore",
+                name,
+                path
+            );
+        }
+
+        for (name, method, path) in routes {
+            if UNDOCUMENTED_ROUTES.contains(&(name, path.as_str())) {
+                continue;
+            }
+            let operation = doc
+                .paths
+                .get_path_operation(&path, method)
+                .unwrap_or_else(|| panic!("{} {} is not documented", name, path));
+
+            let success = operation
+                .responses
+                .responses
+                .iter()
+                .find(|(status, _)| status.starts_with('2'));
+            assert!(
+                success.is_some(),
+                "{} {} has no documented success response",
+                name,
+                path
+            );
+
+            for (status, response) in &operation.responses.responses {
+                let RefOr::T(Response { content, .. }) = response else {
+                    continue;
+                };
+                for media_type in content.values() {
+                    if let Some(RefOr::Ref(reference)) = &media_type.schema {
+                        let schema = schema_name(&reference.ref_location);
+                        assert!(
+                            schemas.contains_key(schema),
+                            "{} {} response {} references undocumented schema {}",
+                            name,
+                            path,
+                            status,
+                            schema
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_openapi_json_is_served() {
+        let app = test::init_service(App::new().service(swagger_ui())).await;
+
+        let request = test::TestRequest::get().uri("/openapi.json").to_request();
+        let result: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert!(result["paths"]["/item/{item_id}/{quantity}"]["delete"].is_object());
+        assert!(
+            result["paths"]["/item"]["post"]["responses"]["429"]["headers"]["Retry-After"]
+                .is_object()
+        );
+        assert!(
+            result["paths"]["/table/{table_id}"]["get"]["responses"]["503"]["headers"]
+                ["Retry-After"]
+                .is_object()
+        );
+
+        let request = test::TestRequest::get()
+            .uri("/swagger-ui/index.html")
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 200);
+    }
+}
@@ -0,0 +1,185 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use persistence::query::{
+    ItemCursor, ItemCursorValue, ItemFilter, ItemQuery, ItemSortField, SortDirection,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::dto::{ItemSort, PageQuery, SortOrder};
+use crate::errors::ServerError;
+
+pub const DEFAULT_PAGE_LIMIT: i64 = 50;
+pub const MAX_PAGE_LIMIT: i64 = 500;
+
+/// Content of the opaque cursor handed out to clients. The sort order is kept in the
+/// cursor so that it can't be replayed against a differently sorted query.
+#[derive(Debug, Deserialize, Serialize)]
+struct CursorToken {
+    sort: ItemSort,
+    order: SortOrder,
+    value: serde_json::Value,
+    id: i64,
+}
+
+fn sort_field(sort: ItemSort) -> ItemSortField {
+    match sort {
+        ItemSort::TableId => ItemSortField::TableId,
+        ItemSort::CreatedAt => ItemSortField::CreatedAt,
+        ItemSort::Name => ItemSortField::Name,
+        ItemSort::TimeToPrepare => ItemSortField::TimeToPrepare,
+    }
+}
+
+fn sort_direction(order: SortOrder) -> SortDirection {
+    match order {
+        SortOrder::Asc => SortDirection::Asc,
+        SortOrder::Desc => SortDirection::Desc,
+    }
+}
+
+fn invalid_cursor() -> ServerError {
+    ServerError::BadRequest("invalid cursor".to_string())
+}
+
+pub fn encode_cursor(cursor: &ItemCursor, sort: ItemSort, order: SortOrder) -> String {
+    let value = match &cursor.value {
+        ItemCursorValue::TableId(value) => serde_json::json!(value),
+        ItemCursorValue::CreatedAt(value) => serde_json::json!(value),
+        ItemCursorValue::Name(value) => serde_json::json!(value),
+        ItemCursorValue::TimeToPrepare(value) => serde_json::json!(value),
+    };
+    let token = CursorToken {
+        sort,
+        order,
+        value,
+        id: cursor.id,
+    };
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(&token).unwrap_or_default())
+}
+
+pub fn decode_cursor(
+    cursor: &str,
+    sort: ItemSort,
+    order: SortOrder,
+) -> Result<ItemCursor, ServerError> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(cursor)
+        .map_err(|_| invalid_cursor())?;
+    let token: CursorToken = serde_json::from_slice(&bytes).map_err(|_| invalid_cursor())?;
+    if token.sort != sort || token.order != order {
+        return Err(ServerError::BadRequest(
+            "cursor does not match the requested sort order".to_string(),
+        ));
+    }
+
+    let value = match sort {
+        ItemSort::TableId => serde_json::from_value(token.value).map(ItemCursorValue::TableId),
+        ItemSort::CreatedAt => serde_json::from_value(token.value).map(ItemCursorValue::CreatedAt),
+        ItemSort::Name => serde_json::from_value(token.value).map(ItemCursorValue::Name),
+        ItemSort::TimeToPrepare => {
+            serde_json::from_value(token.value).map(ItemCursorValue::TimeToPrepare)
+        }
+    }
+    .map_err(|_| invalid_cursor())?;
+
+    Ok(ItemCursor::new(value, token.id))
+}
+
+impl PageQuery {
+    /// Builds the repository query, restricted to a single table when `table_id` is set.
+    pub fn to_item_query(&self, table_id: Option<i32>) -> Result<ItemQuery, ServerError> {
+        let limit = self.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+        if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+            return Err(ServerError::BadRequest(format!(
+                "limit must be between 1 and {}",
+                MAX_PAGE_LIMIT
+            )));
+        }
+        let sort = self.sort.unwrap_or_default();
+        let order = self.order.unwrap_or_default();
+        let cursor = match &self.cursor {
+            Some(cursor) => Some(decode_cursor(cursor, sort, order)?),
+            None => None,
+        };
+
+        Ok(ItemQuery {
+            filter: ItemFilter {
+                name_contains: self.name.clone(),
+                table_ids: None,
+                table_id_from: table_id.or(self.table_from),
+                table_id_to: table_id.or(self.table_to),
+                created_after: self.created_after,
+            },
+            sort: sort_field(sort),
+            direction: sort_direction(order),
+            limit,
+            cursor,
+        })
+    }
+
+    /// Returns the encoded cursor of the next page and a link to it on `path`.
+    pub fn next_page(
+        &self,
+        path: &str,
+        next_cursor: Option<&ItemCursor>,
+    ) -> (Option<String>, Option<String>) {
+        let Some(next_cursor) = next_cursor else {
+            return (None, None);
+        };
+        let cursor = encode_cursor(
+            next_cursor,
+            self.sort.unwrap_or_default(),
+            self.order.unwrap_or_default(),
+        );
+        let next_query = PageQuery {
+            cursor: Some(cursor.clone()),
+            ..self.clone()
+        };
+        let link = serde_urlencoded::to_string(&next_query)
+            .map(|query| format!("{}?{}", path, query))
+            .ok();
+        (Some(cursor), link)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let created_at = NaiveDate::from_ymd_opt(2023, 8, 21)
+            .unwrap()
+            .and_hms_opt(17, 24, 0)
+            .unwrap();
+        let cursor = ItemCursor::new(ItemCursorValue::CreatedAt(created_at), 42);
+
+        let encoded = encode_cursor(&cursor, ItemSort::CreatedAt, SortOrder::Desc);
+        let decoded = decode_cursor(&encoded, ItemSort::CreatedAt, SortOrder::Desc).unwrap();
+        assert_eq!(decoded, cursor);
+
+        let mismatched = decode_cursor(&encoded, ItemSort::Name, SortOrder::Desc);
+        assert!(matches!(mismatched, Err(ServerError::BadRequest(_))));
+
+        let garbage = decode_cursor("not a cursor", ItemSort::CreatedAt, SortOrder::Desc);
+        assert!(matches!(garbage, Err(ServerError::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_limit_is_validated() {
+        let query = PageQuery {
+            limit: Some(MAX_PAGE_LIMIT + 1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            query.to_item_query(None),
+            Err(ServerError::BadRequest(_))
+        ));
+
+        let query = PageQuery::default().to_item_query(Some(3)).unwrap();
+        assert_eq!(query.limit, DEFAULT_PAGE_LIMIT);
+        assert_eq!(query.filter.table_id_from, Some(3));
+        assert_eq!(query.filter.table_id_to, Some(3));
+    }
+}
@@ -0,0 +1,221 @@
+//! Roles of staff members and the permissions they grant.
+//!
+//! Handlers require a permission by taking an [`Authorized`] argument, e.g.
+//! `Authorized<require::AddItem>`, which rejects requests of staff without it with 403.
+
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+use std::future::{ready, Ready};
+use std::marker::PhantomData;
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+use crate::auth::StaffIdentity;
+use crate::errors::ServerError;
+
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    #[display(fmt = "waiter")]
+    Waiter,
+    #[display(fmt = "kitchen")]
+    Kitchen,
+    #[display(fmt = "manager")]
+    Manager,
+    /// Devices which only scrape the metrics, like Prometheus. Staff members can't have it.
+    #[display(fmt = "monitoring")]
+    Monitoring,
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "waiter" => Ok(Role::Waiter),
+            "kitchen" => Ok(Role::Kitchen),
+            "manager" => Ok(Role::Manager),
+            "monitoring" => Ok(Role::Monitoring),
+            _ => Err(format!("unknown role {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Permission {
+    /// View single items and the items of a table.
+    #[display(fmt = "view_items")]
+    ViewItems,
+    /// View the items of all tables at once.
+    #[display(fmt = "view_all_items")]
+    ViewAllItems,
+    #[display(fmt = "add_item")]
+    AddItem,
+    /// Change quantity, table or notes of an item.
+    #[display(fmt = "update_item")]
+    UpdateItem,
+    /// Move an item through the preparation statuses.
+    #[display(fmt = "change_item_status")]
+    ChangeItemStatus,
+    /// Remove items the kitchen has not prepared yet.
+    #[display(fmt = "remove_item")]
+    RemoveItem,
+    /// Remove items which are ready or served.
+    #[display(fmt = "remove_prepared_item")]
+    RemovePreparedItem,
+    #[display(fmt = "transfer_table")]
+    TransferTable,
+    /// Create accounts of staff members.
+    #[display(fmt = "manage_staff")]
+    ManageStaff,
+    /// Assign waiters to tables.
+    #[display(fmt = "assign_tables")]
+    AssignTables,
+    /// Scrape the Prometheus metrics.
+    #[display(fmt = "view_metrics")]
+    ViewMetrics,
+}
+
+/// Permissions granted to every role.
+pub const POLICY: [(Role, &[Permission]); 4] = [
+    (
+        Role::Waiter,
+        &[
+            Permission::ViewItems,
+            Permission::AddItem,
+            Permission::UpdateItem,
+            Permission::RemoveItem,
+            Permission::TransferTable,
+        ],
+    ),
+    (
+        Role::Kitchen,
+        &[Permission::ViewItems, Permission::ChangeItemStatus],
+    ),
+    (
+        Role::Manager,
+        &[
+            Permission::ViewItems,
+            Permission::ViewAllItems,
+            Permission::AddItem,
+            Permission::UpdateItem,
+            Permission::ChangeItemStatus,
+            Permission::RemoveItem,
+            Permission::RemovePreparedItem,
+            Permission::TransferTable,
+            Permission::ManageStaff,
+            Permission::AssignTables,
+            Permission::ViewMetrics,
+        ],
+    ),
+    (Role::Monitoring, &[Permission::ViewMetrics]),
+];
+
+impl Role {
+    pub fn permissions(&self) -> &'static [Permission] {
+        POLICY
+            .iter()
+            .find(|(role, _)| role == self)
+            .map(|(_, permissions)| *permissions)
+            .unwrap_or_default()
+    }
+
+    pub fn has(&self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+}
+
+impl StaffIdentity {
+    /// Fails with 403 naming the permission when the role of the staff member lacks it.
+    pub fn require(&self, permission: Permission) -> Result<(), ServerError> {
+        if self.role.has(permission) {
+            Ok(())
+        } else {
+            Err(ServerError::Forbidden(permission))
+        }
+    }
+}
+
+/// Permission required by an [`Authorized`] argument.
+pub trait RequiredPermission {
+    const PERMISSION: Permission;
+}
+
+macro_rules! required_permissions {
+    ($($permission:ident),* $(,)?) => {
+        /// Marker types of the permissions, to be used as `Authorized<require::AddItem>`.
+        pub mod require {
+            $(
+                pub struct $permission;
+
+                impl super::RequiredPermission for $permission {
+                    const PERMISSION: super::Permission = super::Permission::$permission;
+                }
+            )*
+        }
+    };
+}
+
+required_permissions!(
+    ViewItems,
+    ViewAllItems,
+    AddItem,
+    UpdateItem,
+    ChangeItemStatus,
+    RemoveItem,
+    RemovePreparedItem,
+    TransferTable,
+    ManageStaff,
+    AssignTables,
+    ViewMetrics,
+);
+
+/// Staff member authorized with the permission `P`. Extracting it fails with 401 for
+/// unauthenticated requests and with 403 when the role of the staff member lacks `P`.
+pub struct Authorized<P> {
+    pub identity: StaffIdentity,
+    permission: PhantomData<P>,
+}
+
+impl<P: RequiredPermission> FromRequest for Authorized<P> {
+    type Error = ServerError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let identity = match StaffIdentity::from_request(request, payload).into_inner() {
+            Ok(identity) => identity,
+            Err(e) => return ready(Err(e)),
+        };
+        ready(identity.require(P::PERMISSION).map(|_| Authorized {
+            identity,
+            permission: PhantomData,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_policy() {
+        assert!(Role::Waiter.has(Permission::AddItem));
+        assert!(!Role::Waiter.has(Permission::RemovePreparedItem));
+        assert!(!Role::Waiter.has(Permission::ViewAllItems));
+        assert!(Role::Kitchen.has(Permission::ChangeItemStatus));
+        assert!(!Role::Kitchen.has(Permission::AddItem));
+        assert!(Role::Manager.has(Permission::RemovePreparedItem));
+        assert!(Role::Manager.has(Permission::ViewAllItems));
+        assert!(!Role::Kitchen.has(Permission::ViewMetrics));
+        assert_eq!(Role::Monitoring.permissions(), &[Permission::ViewMetrics]);
+
+        assert_eq!("kitchen".parse::<Role>(), Ok(Role::Kitchen));
+        assert!("chef".parse::<Role>().is_err());
+        assert_eq!(
+            Permission::RemovePreparedItem.to_string(),
+            "remove_prepared_item"
+        );
+    }
+}
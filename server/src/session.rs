@@ -0,0 +1,122 @@
+use actix_web::web;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use persistence::postgres_repositories::PgRepositories;
+use persistence::staff_repository::StaffRepository;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::errors::ServerError;
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// PINs are typed on the handhelds, so they are short and only made of digits.
+pub fn validate_pin(pin: &str) -> Result<(), ServerError> {
+    if (4..=8).contains(&pin.len()) && pin.chars().all(|c| c.is_ascii_digit()) {
+        Ok(())
+    } else {
+        Err(ServerError::BadRequest(
+            "a PIN must consist of 4 to 8 digits".to_string(),
+        ))
+    }
+}
+
+pub fn hash_pin(pin: &str) -> Result<String, ServerError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(pin.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| ServerError::Internal(format!("the PIN can't be hashed: {}", e)))
+}
+
+/// Hash no PIN is checked against in earnest. Logins of unknown staff members verify the
+/// PIN against it, so they take as long as the others.
+fn unknown_staff_pin_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| hash_pin("0000").unwrap_or_default())
+}
+
+/// Verifies the PIN on a blocking thread, argon2 is slow on purpose. Without a hash, the
+/// PIN is verified against a dummy hash and rejected.
+pub async fn verify_pin_blocking(
+    pin_hash: Option<String>,
+    pin: String,
+) -> Result<bool, ServerError> {
+    web::block(move || match pin_hash {
+        Some(pin_hash) => verify_pin(&pin_hash, &pin),
+        None => {
+            verify_pin(unknown_staff_pin_hash(), &pin);
+            false
+        }
+    })
+    .await
+    .map_err(|e| ServerError::Internal(format!("the PIN can't be verified: {}", e)))
+}
+
+pub fn verify_pin(pin_hash: &str, pin: &str) -> bool {
+    PasswordHash::new(pin_hash)
+        .map(|hash| {
+            Argon2::default()
+                .verify_password(pin.as_bytes(), &hash)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+/// Generates a random session token. Unlike JWTs it never contains a `.`.
+pub fn new_session_token() -> String {
+    let mut token = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut token);
+    URL_SAFE_NO_PAD.encode(token)
+}
+
+/// Only the hash of a session token is stored, so a leaked database can't be used to log in.
+pub fn session_token_hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Deletes expired and idle sessions once per `PURGE_INTERVAL`.
+pub async fn purge_expired_sessions_periodically(
+    repositories: PgRepositories,
+    idle_timeout: Duration,
+) {
+    let mut interval = actix_web::rt::time::interval(PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        match repositories
+            .staff_repository
+            .purge_expired_sessions(idle_timeout)
+            .await
+        {
+            Ok(purged) => log::debug!("purged {} expired sessions", purged),
+            Err(e) => log::warn!("failed to purge expired sessions: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pins() {
+        assert!(validate_pin("1234").is_ok());
+        assert!(validate_pin("123").is_err());
+        assert!(validate_pin("12a4").is_err());
+
+        let hash = hash_pin("1234").unwrap();
+        assert!(verify_pin(&hash, "1234"));
+        assert!(!verify_pin(&hash, "4321"));
+        assert!(!verify_pin("not a hash", "1234"));
+
+        let token = new_session_token();
+        assert!(!token.contains('.'));
+        assert_ne!(token, new_session_token());
+        assert_eq!(session_token_hash(&token).len(), 64);
+    }
+}
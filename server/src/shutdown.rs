@@ -0,0 +1,206 @@
+//! Graceful shutdown on `SIGTERM` and `Ctrl-C`.
+//!
+//! The server stops accepting connections and waits for the open ones to be closed, so
+//! that the transactions of the requests in flight are committed before the process
+//! exits. Open notification streams would keep their connections busy forever, they are
+//! ended after sending the notifications already queued for them.
+//!
+//! The connections are drained here instead of by the graceful stop of actix, whose
+//! workers drop their connections when the accept thread happens to stop before them.
+
+use actix_web::dev::{Extensions, ServerHandle};
+use actix_web::web;
+use futures_util::future;
+use std::any::Any;
+use std::io;
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::notifications::Notifier;
+
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Number of open connections of the server.
+#[derive(Clone, Default)]
+pub struct Connections(Arc<AtomicUsize>);
+
+/// Stored in the extensions of a connection, which are dropped when it is closed.
+struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Connections {
+    /// Counts the connection until it is closed, to be passed to `HttpServer::on_connect`.
+    pub fn track(&self, _connection: &dyn Any, extensions: &mut Extensions) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        extensions.insert(ConnectionGuard(self.0.clone()));
+    }
+
+    pub fn open(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(unix)]
+async fn terminated() -> io::Result<()> {
+    use actix_web::rt::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let interrupted = pin!(actix_web::rt::signal::ctrl_c());
+    let terminated = pin!(terminate.recv());
+    match future::select(interrupted, terminated).await {
+        future::Either::Left((result, _)) => result,
+        future::Either::Right(_) => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+async fn terminated() -> io::Result<()> {
+    actix_web::rt::signal::ctrl_c().await
+}
+
+/// Stops the server once its open connections are closed, or `timeout` has passed.
+/// Idle keep-alive connections are waited for as well, until their keep-alive ends.
+pub async fn shut_down(
+    server: ServerHandle,
+    notifier: &Notifier,
+    connections: &Connections,
+    timeout: Duration,
+) {
+    server.pause().await;
+    let streams = notifier.close();
+    log::info!(
+        "ending {} notification streams, waiting for {} connections",
+        streams,
+        connections.open()
+    );
+    let deadline = Instant::now() + timeout;
+    while connections.open() > 0 && Instant::now() < deadline {
+        actix_web::rt::time::sleep(DRAIN_POLL_INTERVAL).await;
+    }
+    if connections.open() > 0 {
+        log::warn!("dropping {} connections", connections.open());
+    }
+    server.stop(false).await;
+}
+
+/// Shuts the server down when the process is told to terminate. The signals have to be
+/// disabled on the server, otherwise it stops on its own without draining.
+pub async fn shut_down_on_signal(
+    server: ServerHandle,
+    notifier: web::Data<Notifier>,
+    connections: Connections,
+    timeout: Duration,
+) {
+    if let Err(e) = terminated().await {
+        log::error!("failed to wait for termination signals: {}", e);
+        return;
+    }
+    log::info!("shutting down, waiting for requests in flight");
+    shut_down(server, &notifier, &connections, timeout).await;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dto::Notification;
+    use crate::notifications::event_stream;
+    use actix_web::{get, App, HttpResponse, HttpServer};
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+
+    static SLOW_STARTED: AtomicBool = AtomicBool::new(false);
+
+    #[get("/slow")]
+    async fn slow() -> HttpResponse {
+        SLOW_STARTED.store(true, Ordering::SeqCst);
+        actix_web::rt::time::sleep(Duration::from_millis(300)).await;
+        HttpResponse::Ok().body("done")
+    }
+
+    #[get("/events")]
+    async fn events(notifier: web::Data<Notifier>) -> HttpResponse {
+        HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .streaming(event_stream(notifier.subscribe(1)))
+    }
+
+    /// Sends a GET request on a new connection and returns the whole response.
+    fn get_in_thread(address: SocketAddr, path: &str) -> thread::JoinHandle<String> {
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            path
+        );
+        thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        })
+    }
+
+    #[actix_web::test]
+    async fn test_shut_down() {
+        let notifier = web::Data::new(Notifier::default());
+        let app_notifier = notifier.clone();
+        let connections = Connections::default();
+        let tracked = connections.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(app_notifier.clone())
+                .service(slow)
+                .service(events)
+        })
+        .workers(1)
+        .on_connect(move |connection, extensions| tracked.track(connection, extensions))
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        let running = actix_web::rt::spawn(server);
+
+        let event_response = get_in_thread(address, "/events");
+        let slow_response = get_in_thread(address, "/slow");
+        let ready = Notification::ItemReady {
+            item_id: 7,
+            table_id: 1,
+            name: "sushi".to_string(),
+            quantity: 2,
+        };
+        while !SLOW_STARTED.load(Ordering::SeqCst) || notifier.notify(1, &ready) == 0 {
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let started_at = Instant::now();
+        shut_down(handle, &notifier, &connections, Duration::from_secs(10)).await;
+        running.await.unwrap().unwrap();
+        assert!(started_at.elapsed() < Duration::from_secs(5));
+
+        let slow_response = slow_response.join().unwrap();
+        assert!(
+            slow_response.starts_with("HTTP/1.1 200 OK"),
+            "{}",
+            slow_response
+        );
+        assert!(slow_response.ends_with("done"), "{}", slow_response);
+        let event_response = event_response.join().unwrap();
+        assert!(
+            event_response.contains("\"item_id\":7"),
+            "{}",
+            event_response
+        );
+        assert_eq!(connections.open(), 0);
+        assert!(TcpStream::connect(address).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+//! Distributed tracing with OpenTelemetry.
+//!
+//! Every request gets a server span named after the route of its handler, continuing the
+//! trace of the caller given in the W3C `traceparent` header. The methods of the item
+//! repository open child spans, so a slow request shows which database call it waited for.
+//! The spans are exported over OTLP/HTTP to the configured collector.
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::HeaderMap;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage};
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use std::time::Instant;
+use tracing::field::Empty;
+use tracing::Instrument;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::logging::RequestId;
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// Layer recording the `tracing` spans with the tracer of `provider`.
+pub fn layer<S>(provider: &SdkTracerProvider) -> OpenTelemetryLayer<S, SdkTracer>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer("restaurant"))
+}
+
+/// Creates the provider exporting spans to the OTLP/HTTP endpoint of a collector. It has
+/// to be shut down before the server exits, so that the last spans are exported.
+pub fn tracer_provider(
+    endpoint: &str,
+    service_name: &str,
+) -> Result<SdkTracerProvider, ExporterBuildError> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()?;
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(service_name.to_string())
+                .build(),
+        )
+        .build())
+}
+
+/// Runs the request in a server span continuing the trace of the `traceparent` header,
+/// and logs its status and duration when it completed.
+pub async fn trace_requests(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let started_at = Instant::now();
+    let method = request.method().to_string();
+    let route = request.match_pattern();
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|request_id| request_id.0.clone());
+    let name = match &route {
+        Some(route) => format!("{} {}", method, route),
+        None => method.clone(),
+    };
+    let span = tracing::info_span!(
+        "HTTP request",
+        otel.name = name,
+        otel.kind = "server",
+        otel.status_code = Empty,
+        http.request.method = method,
+        http.route = route,
+        url.path = request.path(),
+        http.response.status_code = Empty,
+        request_id,
+        table_id = Empty,
+        item_id = Empty,
+    );
+    let parent = TraceContextPropagator::new().extract(&HeaderExtractor(request.headers()));
+    // Fails only when no OpenTelemetry layer is installed, the span is not exported then.
+    let _ = span.set_parent(parent);
+
+    let result = next
+        .call(request)
+        .instrument(span.clone())
+        .await
+        .map(|r| r.map_into_boxed_body());
+    let status = match &result {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    span.record("http.response.status_code", i64::from(status.as_u16()));
+    if status.is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+    // In scope rather than with `parent:`, as the JSON logs only list the entered spans.
+    span.in_scope(|| {
+        tracing::info!(
+            status = status.as_u16(),
+            elapsed_ms = started_at.elapsed().as_millis() as u64,
+            "request completed"
+        )
+    });
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::auth::authenticate_as_manager;
+    use crate::handlers::get_item;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App};
+    use domain::item::Item;
+    use opentelemetry::trace::{SpanId, SpanKind, TraceId};
+    use opentelemetry_sdk::trace::InMemorySpanExporter;
+    use persistence::item_repository::ItemRepository;
+    use persistence::postgres_repositories::PgRepositories;
+    use persistence::{init_test_db, reset_test_db};
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_requests_are_traced() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let _subscriber = tracing_subscriber::registry()
+            .with(layer(&provider))
+            .set_default();
+
+        let item_repository = init_test_db().await;
+        let item_id = item_repository
+            .add_item(
+                Item::new("sushi".to_string(), 1, 10, 1).to_insert_dao(),
+                "test",
+            )
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate_as_manager))
+                .wrap(from_fn(trace_requests))
+                .app_data(web::Data::new(PgRepositories::new(item_repository.clone())))
+                .service(get_item),
+        )
+        .await;
+        exporter.reset();
+
+        let request = test::TestRequest::get()
+            .uri(&format!("/item/{}", item_id))
+            .insert_header((
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            ))
+            .to_request();
+        let result = test::call_service(&app, request).await;
+        assert_eq!(result.status(), 200);
+        provider.force_flush().unwrap();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let span = |name: &str| {
+            spans
+                .iter()
+                .find(|span| span.name == name)
+                .unwrap_or_else(|| panic!("no span {} in {:?}", name, spans))
+        };
+        let server = span("GET /item/{item_id}");
+        assert_eq!(server.span_kind, SpanKind::Server);
+        assert_eq!(
+            server.span_context.trace_id(),
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap()
+        );
+        assert_eq!(
+            server.parent_span_id,
+            SpanId::from_hex("00f067aa0ba902b7").unwrap()
+        );
+        assert!(server.attributes.iter().any(|attribute| {
+            attribute.key.as_str() == "http.response.status_code"
+                && attribute.value == 200_i64.into()
+        }));
+
+        let database = span("PgItemRepository::get_item");
+        assert_eq!(
+            database.span_context.trace_id(),
+            server.span_context.trace_id()
+        );
+        assert_eq!(database.parent_span_id, server.span_context.span_id());
+
+        reset_test_db(item_repository.connection_pool).await;
+    }
+}